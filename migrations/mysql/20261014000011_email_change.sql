@@ -0,0 +1,9 @@
+-- Requested new address, kept apart from `email` until its owner follows the confirmation link
+ALTER TABLE users ADD COLUMN pending_email VARCHAR(255) NULL;
+
+CREATE TABLE IF NOT EXISTS email_change_tokens (
+    token VARCHAR(64) PRIMARY KEY,
+    user_id VARCHAR(36) NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
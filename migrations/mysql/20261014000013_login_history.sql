@@ -0,0 +1,11 @@
+-- One row per successful login, shown to the account owner at GET /users/{id}/logins
+CREATE TABLE IF NOT EXISTS login_history (
+    id VARCHAR(36) PRIMARY KEY,
+    user_id VARCHAR(36) NOT NULL,
+    ip VARCHAR(45) NULL,
+    user_agent VARCHAR(512) NULL,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_login_history_user_created ON login_history (user_id, created_at);
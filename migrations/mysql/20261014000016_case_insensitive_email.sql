@@ -0,0 +1,4 @@
+-- Lowercased copy of the email so uniqueness holds whatever the letter case,
+-- even for rows written outside the application's normalization
+ALTER TABLE users ADD COLUMN email_lower VARCHAR(255) GENERATED ALWAYS AS (LOWER(email)) STORED;
+CREATE UNIQUE INDEX idx_users_email_lower ON users (email_lower);
@@ -0,0 +1,2 @@
+-- Optional contact number in E.164 form (`+` and 7-15 digits)
+ALTER TABLE users ADD COLUMN phone VARCHAR(20) NULL;
@@ -0,0 +1,16 @@
+-- Scope saved registration responses to the client that sent the key and remember which body
+-- they answered; saved responses only live for a day, so the old rows are dropped rather than migrated
+DROP TABLE IF EXISTS idempotency_keys;
+
+CREATE TABLE idempotency_keys (
+    client VARCHAR(64) NOT NULL,
+    idempotency_key VARCHAR(255) NOT NULL,
+    request_hash CHAR(64) NOT NULL,
+    status_code INT NOT NULL,
+    response_body TEXT NOT NULL,
+    location VARCHAR(255) NULL,
+    expires_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (client, idempotency_key)
+);
+
+CREATE INDEX idx_idempotency_keys_expires_at ON idempotency_keys (expires_at);
@@ -0,0 +1,4 @@
+-- Nullable so existing accounts keep working; every new registration sets one
+ALTER TABLE users ADD COLUMN username VARCHAR(50) NULL;
+
+CREATE UNIQUE INDEX idx_users_username ON users (username);
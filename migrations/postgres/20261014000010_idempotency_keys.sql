@@ -0,0 +1,10 @@
+-- Responses of successful POST /register calls, replayed for retries carrying the same Idempotency-Key
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    idempotency_key VARCHAR(255) PRIMARY KEY,
+    status_code INT NOT NULL,
+    response_body TEXT NOT NULL,
+    location VARCHAR(255) NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_idempotency_keys_expires_at ON idempotency_keys (expires_at);
@@ -0,0 +1,10 @@
+-- One row per successful login, shown to the account owner at GET /users/{id}/logins
+CREATE TABLE IF NOT EXISTS login_history (
+    id VARCHAR(36) PRIMARY KEY,
+    user_id VARCHAR(36) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ip VARCHAR(45) NULL,
+    user_agent VARCHAR(512) NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_login_history_user_created ON login_history (user_id, created_at);
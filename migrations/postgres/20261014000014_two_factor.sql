@@ -0,0 +1,3 @@
+-- AES-GCM sealed TOTP secret; set by POST /2fa/enable, only enforced once `totp_enabled` is switched on by POST /2fa/confirm
+ALTER TABLE users ADD COLUMN totp_secret VARCHAR(255) NULL;
+ALTER TABLE users ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT FALSE;
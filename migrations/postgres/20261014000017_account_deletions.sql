@@ -0,0 +1,6 @@
+-- One-time tokens confirming a self-service account deletion
+CREATE TABLE IF NOT EXISTS account_deletion_tokens (
+    token VARCHAR(64) PRIMARY KEY,
+    user_id VARCHAR(36) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
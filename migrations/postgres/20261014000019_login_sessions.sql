@@ -0,0 +1,5 @@
+-- Tie each login to the token it issued, so GET /users/me/sessions can list the live ones
+ALTER TABLE login_history ADD COLUMN jti VARCHAR(36) NULL;
+ALTER TABLE login_history ADD COLUMN expires_at TIMESTAMPTZ NULL;
+
+CREATE INDEX idx_login_history_jti ON login_history (jti);
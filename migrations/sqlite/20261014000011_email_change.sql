@@ -0,0 +1,8 @@
+-- Requested new address, kept apart from `email` until its owner follows the confirmation link
+ALTER TABLE users ADD COLUMN pending_email VARCHAR(255) NULL;
+
+CREATE TABLE IF NOT EXISTS email_change_tokens (
+    token VARCHAR(64) PRIMARY KEY,
+    user_id VARCHAR(36) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL
+);
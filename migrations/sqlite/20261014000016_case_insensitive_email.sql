@@ -0,0 +1,5 @@
+-- Lowercased copy of the email so uniqueness holds whatever the letter case,
+-- even for rows written outside the application's normalization.
+-- SQLite can only add VIRTUAL generated columns; the index stores the values.
+ALTER TABLE users ADD COLUMN email_lower TEXT GENERATED ALWAYS AS (LOWER(email)) VIRTUAL;
+CREATE UNIQUE INDEX idx_users_email_lower ON users (email_lower);
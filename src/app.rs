@@ -0,0 +1,236 @@
+use actix_web::middleware::from_fn;
+use actix_web::{web, Route};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client_ip::ProxyConfig;
+use crate::blocklist::{DomainAllowlist, DomainBlocklist};
+use crate::breach::BreachChecker;
+use crate::config::{AppConfig, DeleteMode};
+use crate::db::{DbPool, RetryPolicy};
+use crate::error::{json_payload_error, ErrorFormat};
+use crate::handlers::account_deletion::{confirm_account_deletion, request_account_deletion};
+use crate::handlers::debug::pool_stats;
+use crate::handlers::docs::{openapi_json, swagger_ui};
+use crate::handlers::email_change::{confirm_email_change, request_email_change};
+use crate::handlers::export::{export_user_data, export_users_csv};
+use crate::handlers::health::{livez, readyz};
+use crate::handlers::import::{import_users, ImportLimit};
+use crate::handlers::login_history::{list_logins, list_sessions, revoke_session};
+use crate::handlers::metrics::metrics;
+use crate::handlers::password_reset::{confirm_password_reset, request_password_reset};
+use crate::handlers::user::{
+    change_password, count_users, delete_user, get_current_user, get_user_by_id, get_users, login_user, logout_user,
+    patch_user, register_user, restore_user, search_users, set_user_status, update_user,
+};
+use crate::handlers::verification::{resend_verification, verify_email};
+use crate::handlers::two_factor::{confirm_two_factor, enable_two_factor};
+use crate::handlers::version::version;
+use crate::jwt::JwtConfig;
+use crate::lockout::LockoutPolicy;
+use crate::mailer::Mailer;
+use crate::maintenance::MaintenanceMode;
+use crate::metrics::Metrics;
+use crate::middleware::logging::LogExcludePaths;
+use crate::middleware::rate_limit::{RateLimit, RateLimiter};
+use crate::middleware::timeout::{enforce_timeout, RequestTimeout};
+use crate::models::pagination::PageSize;
+use crate::password::PasswordHasher;
+use crate::repository::{self, UserRepository};
+use crate::totp::TotpCipher;
+
+/// Everything the handlers share, built once and cloned into each worker
+#[derive(Clone)]
+pub struct AppState {
+    pub db_pool: DbPool,
+    pub db_retry: RetryPolicy,
+    pub request_timeout: Duration,
+    pub import_timeout: Duration,
+    pub log_exclude_paths: Arc<LogExcludePaths>,
+    pub import_limit: ImportLimit,
+    pub page_size: PageSize,
+    pub users: Arc<dyn UserRepository>,
+    pub lockout: LockoutPolicy,
+    pub hasher: Arc<PasswordHasher>,
+    pub jwt: Arc<JwtConfig>,
+    pub proxy: Arc<ProxyConfig>,
+    pub auth_limiter: Arc<RateLimiter>,
+    pub resend_limiter: Arc<RateLimiter>,
+    pub metrics: Arc<Metrics>,
+    pub delete_mode: DeleteMode,
+    pub maintenance: Arc<MaintenanceMode>,
+    pub error_format: ErrorFormat,
+    pub disposable_domains: Option<Arc<DomainBlocklist>>,
+    pub allowed_email_domains: Option<Arc<DomainAllowlist>>,
+    pub totp: Option<Arc<TotpCipher>>,
+    pub breach_checker: Option<Arc<BreachChecker>>,
+    pub mailer: Option<Arc<Mailer>>,
+}
+
+impl AppState {
+    /// Build the state for `db_pool` from the loaded configuration
+    pub fn new(config: &AppConfig, db_pool: DbPool) -> Self {
+        AppState {
+            users: repository::user_repository(&db_pool),
+            db_pool,
+            db_retry: config.db_retry,
+            request_timeout: config.request_timeout,
+            import_timeout: config.import_timeout,
+            log_exclude_paths: Arc::new(config.log_exclude_paths.clone()),
+            import_limit: ImportLimit(config.import_max_users),
+            page_size: config.page_size,
+            lockout: config.lockout,
+            hasher: Arc::new(PasswordHasher::new(
+                config.argon2_algorithm,
+                config.argon2_version,
+                config.argon2.clone(),
+                config.password_pepper.as_deref(),
+            )),
+            jwt: Arc::new(config.jwt.clone()),
+            proxy: Arc::new(config.proxy.clone()),
+            auth_limiter: Arc::new(RateLimiter::new(config.rate_limit_per_minute)),
+            resend_limiter: Arc::new(RateLimiter::new(config.resend_rate_limit_per_minute)),
+            metrics: Arc::new(Metrics::new()),
+            delete_mode: config.delete_mode,
+            maintenance: Arc::new(MaintenanceMode::new(config.maintenance_mode)),
+            error_format: config.error_format,
+            disposable_domains: config.disposable_domains.clone(),
+            allowed_email_domains: config.allowed_email_domains.clone(),
+            totp: config.totp.clone(),
+            breach_checker: config.breach_checker.clone(),
+            mailer: config.mailer.clone(),
+        }
+    }
+}
+
+/// Largest JSON body accepted by any route but `/users/bulk`; every payload is a few short strings
+const JSON_BODY_LIMIT_BYTES: usize = 16 * 1024;
+
+/// Apply `REQUEST_TIMEOUT_SECS` to a route; `enforce_timeout` only works once the request is routed
+fn timed(route: Route) -> Route {
+    route.wrap(from_fn(enforce_timeout))
+}
+
+/// Register the shared data and every route (used by `main` and the integration tests)
+pub fn configure(cfg: &mut web::ServiceConfig, state: &AppState) {
+    // Only registered when DISPOSABLE_DOMAINS_PATH is set, so `register_user` skips the check otherwise
+    if let Some(blocklist) = &state.disposable_domains {
+        cfg.app_data(web::Data::from(blocklist.clone()));
+    }
+
+    // Only registered when ALLOWED_EMAIL_DOMAINS is set; every domain may register otherwise
+    if let Some(allowlist) = &state.allowed_email_domains {
+        cfg.app_data(web::Data::from(allowlist.clone()));
+    }
+
+    // Only registered when HIBP_CHECK_ENABLED is on; passwords aren't checked for breaches otherwise
+    if let Some(checker) = &state.breach_checker {
+        cfg.app_data(web::Data::from(checker.clone()));
+    }
+
+    // Only registered when TOTP_ENCRYPTION_KEY is set; the /2fa routes answer 404 otherwise
+    if let Some(totp) = &state.totp {
+        cfg.app_data(web::Data::from(totp.clone()));
+    }
+
+    // Only registered when SMTP_URL is set; registration skips the welcome email otherwise
+    if let Some(mailer) = &state.mailer {
+        cfg.app_data(web::Data::from(mailer.clone()));
+    }
+
+    cfg.app_data(
+        web::JsonConfig::default()
+            .limit(JSON_BODY_LIMIT_BYTES) // Reject oversized JSON bodies with 413
+            .error_handler(json_payload_error), // 415 / 400 / 413 as JSON, like every other error
+    )
+        .app_data(web::Data::new(state.db_pool.clone())) // Pass the database pool to the app
+        .app_data(web::Data::from(state.users.clone())) // Share the user storage behind its trait
+        .app_data(web::Data::new(state.db_retry)) // How often writes retry transient database errors
+        .app_data(web::Data::new(RequestTimeout(state.request_timeout))) // Deadline `enforce_timeout` applies to every handler
+        .app_data(web::Data::from(state.log_exclude_paths.clone())) // Paths `request_logger` only logs at debug while they succeed
+        .app_data(web::Data::new(state.page_size)) // Default and largest `limit` of every paged listing
+        .app_data(web::Data::new(state.lockout)) // Share the failed-login lockout policy
+        .app_data(web::Data::from(state.hasher.clone())) // Share one Argon2 hasher for hashing and verifying
+        .app_data(web::Data::from(state.jwt.clone())) // Share the token signing keys with login and the auth extractors
+        .app_data(web::Data::from(state.proxy.clone())) // Tell `client_ip` which peers may set X-Forwarded-For
+        .app_data(web::Data::new(state.delete_mode)) // Soft or hard deletes for DELETE /users/{id}
+        .app_data(web::Data::from(state.maintenance.clone())) // Lets `WritesAllowed` refuse writes while MAINTENANCE_MODE is on
+        .app_data(web::Data::new(state.error_format)) // Default error envelope for `negotiate_error_format`
+        .app_data(web::Data::from(state.metrics.clone())) // Collectors fed by `track_requests` and served at /metrics
+        .route("/health", timed(web::get().to(readyz)))
+        .route("/livez", timed(web::get().to(livez)))
+        .route("/readyz", timed(web::get().to(readyz)))
+        .route("/metrics", timed(web::get().to(metrics)))
+        .route("/debug/pool", timed(web::get().to(pool_stats)))
+        .route("/version", timed(web::get().to(version)))
+        .route("/api-docs/openapi.json", timed(web::get().to(openapi_json)))
+        .route("/swagger-ui", timed(web::get().to(swagger_ui)))
+        .service(
+            web::resource("/register")
+                .wrap(RateLimit::new(state.auth_limiter.clone())) // Throttle signup spam per client IP
+                .route(timed(web::post().to(register_user))),
+        )
+        .route("/users", timed(web::get().to(get_users)))
+        .service(
+            web::resource("/login")
+                .wrap(RateLimit::new(state.auth_limiter.clone())) // Throttle credential guessing per client IP
+                .route(timed(web::post().to(login_user))),
+        )
+        .route("/logout", timed(web::post().to(logout_user)))
+        .route("/2fa/enable", timed(web::post().to(enable_two_factor)))
+        .service(
+            web::resource("/2fa/confirm")
+                .wrap(RateLimit::new(state.auth_limiter.clone())) // Throttle guessing of the 6-digit code
+                .route(timed(web::post().to(confirm_two_factor))),
+        )
+        .route("/verify", timed(web::get().to(verify_email)))
+        .service(
+            web::resource("/verify/resend")
+                .wrap(RateLimit::new(state.resend_limiter.clone())) // Each request can send an email
+                .route(timed(web::post().to(resend_verification))),
+        )
+        .route("/verify-email-change", timed(web::get().to(confirm_email_change)))
+        .service(
+            web::resource("/password-reset/request")
+                .wrap(RateLimit::new(state.resend_limiter.clone())) // Each request can send an email
+                .route(timed(web::post().to(request_password_reset))),
+        )
+        .route("/password-reset/confirm", timed(web::post().to(confirm_password_reset)))
+        .service(
+            web::resource("/users/bulk") // Must precede /users/{id}
+                .app_data(
+                    web::JsonConfig::default()
+                        .limit(state.import_limit.body_limit_bytes()) // Room for a full import batch
+                        .error_handler(json_payload_error),
+                )
+                .app_data(web::Data::new(state.import_limit)) // Entry cap `import_users` enforces while parsing
+                .app_data(web::Data::new(RequestTimeout(state.import_timeout))) // A full batch of Argon2 hashes outlasts REQUEST_TIMEOUT_SECS
+                .route(timed(web::post().to(import_users))),
+        )
+        .route("/users/count", timed(web::get().to(count_users))) // Must precede /users/{id}
+        .route("/users/export.csv", timed(web::get().to(export_users_csv))) // Must precede /users/{id}
+        .route("/users/search", timed(web::get().to(search_users))) // Must precede /users/{id}
+        .route("/users/me", timed(web::get().to(get_current_user))) // Must precede /users/{id}
+        .service(
+            web::resource("/users/me/delete-request")
+                .wrap(RateLimit::new(state.resend_limiter.clone())) // Each request can send an email
+                .route(timed(web::post().to(request_account_deletion))),
+        )
+        .route("/users/me/delete-confirm", timed(web::post().to(confirm_account_deletion)))
+        .route("/users/me/sessions", timed(web::get().to(list_sessions)))
+        .route("/users/me/sessions/{jti}", timed(web::delete().to(revoke_session)))
+        .route("/users/{id}", timed(web::get().to(get_user_by_id)))
+        .route("/users/{id}", timed(web::put().to(update_user)))
+        .route("/users/{id}", timed(web::patch().to(patch_user)))
+        .route("/users/{id}", timed(web::delete().to(delete_user)))
+        .route("/users/{id}/password", timed(web::post().to(change_password)))
+        .service(
+            web::resource("/users/{id}/email")
+                .wrap(RateLimit::new(state.resend_limiter.clone())) // Each request can send an email
+                .route(timed(web::post().to(request_email_change))),
+        )
+        .route("/users/{id}/export", timed(web::get().to(export_user_data)))
+        .route("/users/{id}/logins", timed(web::get().to(list_logins)))
+        .route("/users/{id}/restore", timed(web::post().to(restore_user)))
+        .route("/users/{id}/status", timed(web::post().to(set_user_status)));
+}
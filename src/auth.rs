@@ -0,0 +1,131 @@
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+use crate::error::AppError;
+use crate::jwt::JwtConfig;
+use crate::models::user::AccountStatus;
+use crate::repository::UserRepository;
+use crate::roles;
+
+/// Caller identity extracted from a valid `Authorization: Bearer <token>` header.
+///
+/// Adding this as a handler parameter makes the route require authentication;
+/// requests with a missing, invalid or revoked token, or one whose user has
+/// since been deleted, are rejected with a 401, and suspended users with a 403.
+#[derive(Debug)]
+pub struct AuthenticatedUser {
+    pub user_id: String,
+    pub role: String,
+    pub jti: String,                // Id of the presented token, needed to revoke it
+    pub expires_at: DateTime<Utc>,  // When the presented token expires
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // 🔍 Pull the bearer token out of the Authorization header
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_string);
+
+        let jwt = req.app_data::<web::Data<JwtConfig>>().cloned();
+        let users = req.app_data::<web::Data<dyn UserRepository>>().cloned();
+
+        Box::pin(async move {
+            let jwt = jwt.ok_or_else(|| AppError::Internal("JwtConfig is not registered".to_string()))?;
+            let users = users.ok_or_else(|| AppError::Internal("UserRepository is not registered".to_string()))?;
+
+            // 🔐 Verify the token's signature and expiry
+            let Some(Ok(claims)) = token.map(|token| jwt.decode_token(&token)) else {
+                return Err(AppError::Unauthorized("unauthorized".to_string()));
+            };
+
+            // 🚪 Tokens handed back through /logout stay rejected until they expire
+            if users.is_token_revoked(&claims.jti).await? {
+                return Err(AppError::Unauthorized("unauthorized".to_string()));
+            }
+
+            // ⛔ A token outlives neither its account nor a suspension
+            match users.account_status(&claims.sub).await? {
+                None => return Err(AppError::Unauthorized("unauthorized".to_string())),
+                Some(status) if status == AccountStatus::Suspended.as_str() => {
+                    return Err(AppError::Forbidden("account suspended".to_string()));
+                }
+                Some(_) => {}
+            }
+
+            Ok(AuthenticatedUser {
+                user_id: claims.sub,
+                role: claims.role,
+                jti: claims.jti,
+                expires_at: DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or(DateTime::<Utc>::MAX_UTC),
+            })
+        })
+    }
+}
+
+impl AuthenticatedUser {
+    /// 403 unless the caller is the user `user_id` or an admin
+    pub fn require_self_or_admin(&self, user_id: &str) -> Result<(), AppError> {
+        if self.user_id == user_id || self.role == roles::ADMIN {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden("forbidden".to_string()))
+        }
+    }
+}
+
+/// A role that `RequireRole` can demand, named as it is stored in `users.role`
+pub trait Role {
+    const NAME: &'static str;
+}
+
+/// Marker for `RequireRole<Admin>`
+#[derive(Debug)]
+pub struct Admin;
+
+impl Role for Admin {
+    const NAME: &'static str = roles::ADMIN;
+}
+
+/// An `AuthenticatedUser` whose token carries role `R`.
+///
+/// Missing or invalid tokens are rejected with a 401 and any other role with
+/// a 403. The role is read from the token, so a change takes effect at the
+/// user's next login.
+#[derive(Debug)]
+pub struct RequireRole<R: Role> {
+    pub user: AuthenticatedUser,
+    role: PhantomData<R>,
+}
+
+impl<R: Role> FromRequest for RequireRole<R> {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        // 🔐 Authenticate first so unauthenticated callers still get a 401
+        let user = AuthenticatedUser::from_request(req, payload);
+
+        Box::pin(async move {
+            let user = user.await?;
+
+            // 🛡️ Then require the role
+            if user.role != R::NAME {
+                return Err(AppError::Forbidden("forbidden".to_string()));
+            }
+
+            Ok(RequireRole { user, role: PhantomData })
+        })
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// Email domains refused at registration, loaded from `DISPOSABLE_DOMAINS_PATH`
+#[derive(Debug, Default)]
+pub struct DomainBlocklist {
+    domains: HashSet<String>, // Lowercased, without a leading `@`
+}
+
+impl DomainBlocklist {
+    /// Read one domain per line; blank lines and `#` comments are ignored
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let domains = contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|domain| !domain.is_empty())
+            .map(|domain| domain.trim_start_matches('@').to_lowercase())
+            .collect();
+
+        Ok(DomainBlocklist { domains })
+    }
+
+    pub fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+
+    /// Whether the part of `email` after the last `@` is on the list, or is a
+    /// subdomain of a listed domain, ignoring case
+    pub fn is_blocked(&self, email: &str) -> bool {
+        let Some(domain) = email_domain(email) else {
+            return false;
+        };
+        // Check `a.mailinator.com`, then `mailinator.com`, then `com`
+        let mut rest = domain.as_str();
+        loop {
+            if self.domains.contains(rest) {
+                return true;
+            }
+            match rest.split_once('.') {
+                Some((_, parent)) => rest = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// The only email domains that may register, from `ALLOWED_EMAIL_DOMAINS`.
+///
+/// Domains match exactly: allowing `example.com` doesn't allow
+/// `eu.example.com`, which has to be listed itself.
+#[derive(Debug, Default)]
+pub struct DomainAllowlist {
+    domains: HashSet<String>, // Lowercased, without a leading `@`
+}
+
+impl DomainAllowlist {
+    /// Parse a comma-separated list of domains; blank entries are ignored
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let domains: HashSet<String> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|domain| !domain.is_empty())
+            .map(|domain| domain.trim_start_matches('@').to_lowercase())
+            .collect();
+        if domains.is_empty() {
+            return Err("ALLOWED_EMAIL_DOMAINS must name at least one domain".to_string());
+        }
+
+        Ok(DomainAllowlist { domains })
+    }
+
+    /// Whether the part of `email` after the last `@` is on the list, ignoring case
+    pub fn is_allowed(&self, email: &str) -> bool {
+        email_domain(email).is_some_and(|domain| self.domains.contains(&domain))
+    }
+}
+
+/// The lowercased part of `email` after the last `@`
+fn email_domain(email: &str) -> Option<String> {
+    email.rsplit_once('@').map(|(_, domain)| domain.trim().to_lowercase())
+}
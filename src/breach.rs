@@ -0,0 +1,73 @@
+use actix_web::http::header;
+use sha1::{Digest, Sha1};
+use std::time::Duration;
+
+/// Largest range response read; real ones are around 40 KiB even with padding
+const MAX_RANGE_BODY_BYTES: usize = 1024 * 1024;
+
+/// Looks passwords up in HaveIBeenPwned's Pwned Passwords range API (`HIBP_CHECK_ENABLED`).
+///
+/// Only the first five hex digits of the password's SHA-1 are sent; the API
+/// answers with every breached suffix under that prefix and the match is made
+/// here, so neither the password nor its full hash leaves the process.
+#[derive(Debug, Clone)]
+pub struct BreachChecker {
+    api_url: String,   // `HIBP_API_URL`, without a trailing slash
+    timeout: Duration, // Bound on the whole lookup, connect to last byte
+}
+
+impl BreachChecker {
+    pub fn new(api_url: &str, timeout: Duration) -> Self {
+        BreachChecker { api_url: api_url.trim_end_matches('/').to_string(), timeout }
+    }
+
+    /// Whether the password appears in a known breach; `Err` when the API couldn't be asked
+    pub async fn is_breached(&self, password: &str) -> Result<bool, String> {
+        let digest = format!("{:X}", Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = digest.split_at(5);
+
+        let lookup = async {
+            // awc clients can't move between worker threads, so each lookup builds its own
+            let mut response = awc::Client::default()
+                .get(format!("{}/range/{}", self.api_url, prefix))
+                .insert_header((header::USER_AGENT, concat!("rust_learning/", env!("CARGO_PKG_VERSION"))))
+                .insert_header(("Add-Padding", "true")) // Every answer is about the same size, hiding which prefix was asked for
+                .send()
+                .await
+                .map_err(|e| format!("request failed: {}", e))?;
+
+            if !response.status().is_success() {
+                return Err(format!("unexpected status {}", response.status()));
+            }
+
+            let body = response
+                .body()
+                .limit(MAX_RANGE_BODY_BYTES)
+                .await
+                .map_err(|e| format!("reading the response failed: {}", e))?;
+            String::from_utf8(body.to_vec()).map_err(|_| "response is not UTF-8".to_string())
+        };
+
+        let body = tokio::time::timeout(self.timeout, lookup)
+            .await
+            .map_err(|_| format!("no answer within {:?}", self.timeout))??;
+
+        // Lines are `SUFFIX:COUNT`; padding entries have a count of 0
+        Ok(body.lines().any(|line| match line.trim().split_once(':') {
+            Some((candidate, count)) => candidate.eq_ignore_ascii_case(suffix) && count.trim() != "0",
+            None => false,
+        }))
+    }
+
+    /// `is_breached`, failing open: an unreachable or broken API lets the password
+    /// through with a warning rather than blocking signups and password changes
+    pub async fn check(&self, password: &str) -> bool {
+        match self.is_breached(password).await {
+            Ok(breached) => breached,
+            Err(e) => {
+                tracing::warn!(error = %e, "Breached password check failed, allowing the password");
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::repository::UserRepository;
+
+/// Delete expired revocations and idempotency keys every `period`
+/// (`REVOCATION_CLEANUP_SECS`) for as long as the server runs
+pub fn spawn_revocation_cleanup(users: Arc<dyn UserRepository>, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            // A failed sweep is retried on the next tick; the rows are only dead weight
+            match users.delete_expired_revocations(Utc::now()).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!(removed, "Deleted expired token revocations"),
+                Err(e) => tracing::warn!(error = %e, "Failed to delete expired token revocations"),
+            }
+
+            match users.delete_expired_idempotency_keys(Utc::now()).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!(removed, "Deleted expired idempotency keys"),
+                Err(e) => tracing::warn!(error = %e, "Failed to delete expired idempotency keys"),
+            }
+        }
+    })
+}
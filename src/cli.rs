@@ -0,0 +1,27 @@
+use clap::{Parser, Subcommand};
+
+/// Command line of the server binary; without a subcommand it serves HTTP
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create a verified admin account (skipped if the email or username is taken) and exit
+    SeedAdmin {
+        #[arg(long)]
+        email: String,
+
+        #[arg(long)]
+        password: String,
+
+        #[arg(long, default_value = "Admin")]
+        name: String,
+
+        #[arg(long, default_value = "admin")]
+        username: String,
+    },
+}
@@ -0,0 +1,78 @@
+use actix_web::{web, HttpRequest};
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+/// Which peers may tell us the client's address through `X-Forwarded-For`
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    /// `TRUSTED_PROXIES`; list only the load balancers in front of the app,
+    /// since any peer in these ranges can claim to be any client
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl ProxyConfig {
+    /// Parse a comma-separated list of CIDR ranges; a bare address is a single-host range
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let trusted_proxies = list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("TRUSTED_PROXIES entry {:?} is not an IP address or CIDR range", entry))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ProxyConfig { trusted_proxies })
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|range| range.contains(ip))
+    }
+}
+
+/// Resolve the IP address of the client that sent the request.
+///
+/// `X-Forwarded-For` is only read when the socket peer is a trusted proxy.
+/// The header is then walked from the right, skipping further trusted hops,
+/// and the first address no trusted proxy claims is returned: entries to its
+/// left were supplied by the client and could be anything. Without a trusted
+/// peer the socket peer address is returned.
+///
+/// Peers on the Unix socket (`BIND_UDS`) have no address and count as trusted,
+/// since only local processes such as the reverse proxy can reach the socket.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    let config = req.app_data::<web::Data<ProxyConfig>>();
+    let is_trusted = |ip: &IpAddr| config.is_some_and(|config| config.is_trusted(ip));
+
+    if let Some(peer) = peer
+        && !is_trusted(&peer)
+    {
+        return Some(peer);
+    }
+
+    // 🔁 Every header line counts, in order, as if they were joined with commas
+    let hops: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        // 🚫 Stop at garbage; the last address we could vouch for is the best answer
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        client = Some(ip);
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+
+    client
+}
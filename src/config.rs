@@ -0,0 +1,492 @@
+use argon2::{Algorithm, Params, Version};
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::blocklist::{DomainAllowlist, DomainBlocklist};
+use crate::breach::BreachChecker;
+use crate::client_ip::ProxyConfig;
+use crate::db::RetryPolicy;
+use crate::error::ErrorFormat;
+use crate::jwt::JwtConfig;
+use crate::lockout::LockoutPolicy;
+use crate::middleware::cors::CorsConfig;
+use crate::middleware::logging::LogExcludePaths;
+use crate::middleware::security_headers::DEFAULT_CONTENT_SECURITY_POLICY;
+use crate::models::pagination::{PageSize, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::password;
+use crate::tls;
+use crate::totp::TotpCipher;
+use crate::mailer::Mailer;
+
+/// Connection settings for `db::connect`
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Option<Duration>, // None waits for a free connection indefinitely
+    pub warmup: bool,                      // Open `min_connections` before serving instead of lazily
+}
+
+/// What `DELETE /users/{id}` does (`SOFT_DELETE`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Stamp `deleted_at`, hiding the user until `POST /users/{id}/restore`
+    Soft,
+    /// Remove the row and everything that cascades from it
+    Hard,
+}
+
+/// Every setting the server reads from the environment, loaded once at startup.
+///
+/// | Variable                  | Default                                   |
+/// |---------------------------|-------------------------------------------|
+/// | `HOST`                    | `127.0.0.1` (use `0.0.0.0` in containers) |
+/// | `PORT`                    | `8080`                                    |
+/// | `BIND_TCP`                | `true` (listen on `HOST:PORT`)            |
+/// | `BIND_UDS`                | unset (Unix socket path, plain HTTP)      |
+/// | `WORKERS`                 | unset (one per CPU)                       |
+/// | `REQUEST_TIMEOUT_SECS`    | `30`                                      |
+/// | `IMPORT_MAX_USERS`        | `1000` (entries per `POST /users/bulk`)   |
+/// | `IMPORT_TIMEOUT_SECS`     | `600` (replaces `REQUEST_TIMEOUT_SECS`)   |
+/// | `DEFAULT_PAGE_SIZE`       | `20` (`limit` when a listing sends none)  |
+/// | `MAX_PAGE_SIZE`           | `100` (larger `limit`s are rejected)      |
+/// | `DATABASE_URL`            | required                                  |
+/// | `DB_MAX_CONNECTIONS`      | `5`                                       |
+/// | `DB_MIN_CONNECTIONS`      | `0`                                       |
+/// | `DB_ACQUIRE_TIMEOUT_SECS` | unset                                     |
+/// | `DB_WARMUP`               | `false`                                   |
+/// | `DB_MAX_RETRIES`          | `3`                                       |
+/// | `JWT_SECRET`              | required                                  |
+/// | `JWT_EXPIRY_SECS`         | `3600`                                    |
+/// | `ARGON2_VARIANT`          | `argon2id` (or `argon2i`, `argon2d`)      |
+/// | `ARGON2_VERSION`          | `0x13` (or `0x10`)                        |
+/// | `ARGON2_MEMORY_KIB`       | `65536`                                   |
+/// | `ARGON2_ITERATIONS`       | `3`                                       |
+/// | `ARGON2_PARALLELISM`      | `1`                                       |
+/// | `PASSWORD_PEPPER`         | unset (no pepper)                         |
+/// | `HIBP_CHECK_ENABLED`      | `false` (no breached password check)      |
+/// | `HIBP_API_URL`            | `https://api.pwnedpasswords.com`          |
+/// | `HIBP_TIMEOUT_SECS`       | `2` (then the password is allowed)        |
+/// | `TOTP_ENCRYPTION_KEY`     | unset (two-factor login unavailable)      |
+/// | `RATE_LIMIT_PER_MINUTE`   | `60`                                      |
+/// | `RESEND_LIMIT_PER_MINUTE` | `3` (per endpoint that sends email)       |
+/// | `LOCKOUT_THRESHOLD`       | `5`                                       |
+/// | `LOCKOUT_DURATION_MINS`   | `15`                                      |
+/// | `TRUSTED_PROXIES`         | empty (ignore `X-Forwarded-For`)          |
+/// | `ALLOWED_ORIGINS`         | empty (cross-origin requests are refused) |
+/// | `CORS_ALLOW_CREDENTIALS`  | `false` (not allowed with `*`)            |
+/// | `CORS_MAX_AGE_SECS`       | unset (preflights aren't cached)          |
+/// | `CONTENT_SECURITY_POLICY` | `default-src 'none'`, no framing          |
+/// | `ERROR_FORMAT`            | `json` (or `problem` for RFC 7807)        |
+/// | `LOG_EXCLUDE_PATHS`       | empty (e.g. `/health,/metrics`)           |
+/// | `SMTP_URL`                | unset (no emails are sent)                |
+/// | `SMTP_FROM`               | required when `SMTP_URL` is set           |
+/// | `PUBLIC_URL`              | `http://HOST:PORT` (base of email links)  |
+/// | `ADMIN_EMAIL`             | unset                                     |
+/// | `REVOCATION_CLEANUP_SECS` | `3600`                                    |
+/// | `SOFT_DELETE`             | `true`                                    |
+/// | `MAINTENANCE_MODE`        | `false` (re-read from `.env` on SIGHUP)   |
+/// | `DISPOSABLE_DOMAINS_PATH` | unset (no domain check)                   |
+/// | `ALLOWED_EMAIL_DOMAINS`   | unset (any domain may register)           |
+/// | `TLS_CERT_PATH`           | unset (serve plain HTTP)                  |
+/// | `TLS_KEY_PATH`            | unset (serve plain HTTP)                  |
+pub struct AppConfig {
+    pub host: String,
+    pub port: u16,
+    pub bind_tcp: bool,
+    pub bind_uds: Option<PathBuf>, // Its directory must exist; a stale socket file is replaced at startup
+    pub workers: Option<usize>, // None keeps actix's default of one worker per CPU
+    pub request_timeout: Duration,
+    pub import_max_users: usize, // Also sizes the body limit of POST /users/bulk
+    pub import_timeout: Duration, // Deadline of POST /users/bulk, which hashes a password per entry
+    pub page_size: PageSize,
+    pub database: DatabaseConfig,
+    pub db_retry: RetryPolicy, // Retries of writes that hit a MySQL deadlock or lock wait timeout
+    pub jwt: JwtConfig,
+    pub argon2_algorithm: Algorithm, // Only for new hashes; each stored hash names its own variant
+    pub argon2_version: Version,
+    pub argon2: Params,
+    pub password_pepper: Option<String>, // Argon2 secret key; rotating it invalidates every stored hash
+    pub breach_checker: Option<Arc<BreachChecker>>, // Set when HIBP_CHECK_ENABLED is on
+    pub totp: Option<Arc<TotpCipher>>,   // Encrypts TOTP secrets; changing the key breaks every enrolled authenticator
+    pub rate_limit_per_minute: u32,
+    pub resend_rate_limit_per_minute: u32, // Each resend sends an email, so it is throttled harder
+    pub lockout: LockoutPolicy,
+    pub proxy: ProxyConfig,
+    pub cors: CorsConfig,
+    pub content_security_policy: String, // Sent on every response that doesn't set its own
+    pub error_format: ErrorFormat,       // Error envelope for clients that don't ask for problem+json
+    pub log_exclude_paths: LogExcludePaths, // Successful requests here are logged at debug only
+    pub mailer: Option<Arc<Mailer>>,
+    pub admin_email: Option<String>,
+    pub revocation_cleanup_interval: Duration,
+    pub delete_mode: DeleteMode,
+    pub maintenance_mode: bool, // Refuse writes with 503 at startup; SIGHUP reloads it
+    pub disposable_domains: Option<Arc<DomainBlocklist>>,
+    pub allowed_email_domains: Option<Arc<DomainAllowlist>>, // When set, the only domains that may register
+    pub tls: Option<rustls::ServerConfig>, // Loaded from TLS_CERT_PATH / TLS_KEY_PATH when both are set
+}
+
+/// Every missing or invalid variable found while loading `AppConfig`
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl AppConfig {
+    /// Read and validate the whole configuration, reporting every problem at once
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mut env = EnvReader::default();
+
+        let host = env.string("HOST").unwrap_or_else(|| "127.0.0.1".to_string());
+        let port = env.port("PORT", 8080);
+
+        // 🔌 A Unix socket for a reverse proxy on the same host, alongside or instead of TCP
+        let bind_tcp = env.flag("BIND_TCP", true);
+        let bind_uds = env.string("BIND_UDS").map(PathBuf::from);
+        if let Some(path) = &bind_uds {
+            let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            if !parent.is_dir() {
+                env.invalid(&format!("BIND_UDS directory {} does not exist", parent.display()));
+            }
+        }
+        if !bind_tcp && bind_uds.is_none() {
+            env.invalid("BIND_TCP=false needs BIND_UDS, or the server would not listen anywhere");
+        }
+
+        // 🧵 The default counts host CPUs, which overshoots a container's cgroup CPU quota
+        let workers = env.parse_optional("WORKERS");
+        if workers == Some(0) {
+            env.invalid("WORKERS must be at least 1");
+        }
+
+        let request_timeout_secs = env.parse("REQUEST_TIMEOUT_SECS", 30);
+        if request_timeout_secs == 0 {
+            env.invalid("REQUEST_TIMEOUT_SECS must be at least 1");
+        }
+        let request_timeout = Duration::from_secs(request_timeout_secs);
+
+        let import_max_users = env.parse("IMPORT_MAX_USERS", 1000);
+        if import_max_users == 0 {
+            env.invalid("IMPORT_MAX_USERS must be at least 1");
+        }
+
+        let import_timeout_secs = env.parse("IMPORT_TIMEOUT_SECS", 600);
+        if import_timeout_secs == 0 {
+            env.invalid("IMPORT_TIMEOUT_SECS must be at least 1");
+        }
+        let import_timeout = Duration::from_secs(import_timeout_secs);
+
+        let page_size = PageSize {
+            default: env.parse("DEFAULT_PAGE_SIZE", DEFAULT_LIMIT),
+            max: env.parse("MAX_PAGE_SIZE", MAX_LIMIT),
+        };
+        if page_size.default < 1 {
+            env.invalid("DEFAULT_PAGE_SIZE must be at least 1");
+        }
+        if page_size.max < 1 {
+            env.invalid("MAX_PAGE_SIZE must be at least 1");
+        } else if page_size.default > page_size.max {
+            env.invalid(&format!(
+                "DEFAULT_PAGE_SIZE ({}) cannot exceed MAX_PAGE_SIZE ({})",
+                page_size.default, page_size.max
+            ));
+        }
+
+        let database = DatabaseConfig {
+            url: env.required("DATABASE_URL"),
+            max_connections: env.parse("DB_MAX_CONNECTIONS", 5),
+            min_connections: env.parse("DB_MIN_CONNECTIONS", 0),
+            acquire_timeout: env.parse_optional("DB_ACQUIRE_TIMEOUT_SECS").map(Duration::from_secs),
+            warmup: env.flag("DB_WARMUP", false),
+        };
+        let known_scheme = ["mysql://", "postgres://", "postgresql://", "sqlite:"]
+            .iter()
+            .any(|scheme| database.url.starts_with(scheme));
+        if !database.url.is_empty() && !known_scheme {
+            env.invalid("DATABASE_URL must start with mysql://, postgres:// or sqlite:");
+        }
+        if database.max_connections == 0 {
+            env.invalid("DB_MAX_CONNECTIONS must be at least 1");
+        }
+        if database.min_connections > database.max_connections {
+            env.invalid("DB_MIN_CONNECTIONS must not exceed DB_MAX_CONNECTIONS");
+        }
+
+        let db_retry = RetryPolicy {
+            max_retries: env.parse("DB_MAX_RETRIES", 3),
+            base_delay: Duration::from_millis(50),
+        };
+
+        let jwt_secret = env.required("JWT_SECRET");
+        let jwt_expiry_secs = env.parse("JWT_EXPIRY_SECS", 3600);
+
+        let argon2_algorithm = match env.string("ARGON2_VARIANT") {
+            None => Algorithm::Argon2id,
+            Some(name) => password::parse_algorithm(&name).unwrap_or_else(|| {
+                env.invalid(&format!("ARGON2_VARIANT must be argon2i, argon2d or argon2id, got {:?}", name));
+                Algorithm::Argon2id
+            }),
+        };
+        let argon2_version = match env.string("ARGON2_VERSION") {
+            None => Version::V0x13,
+            Some(value) => password::parse_version(&value).unwrap_or_else(|| {
+                env.invalid(&format!("ARGON2_VERSION must be 0x13 or 0x10, got {:?}", value));
+                Version::V0x13
+            }),
+        };
+        let memory_kib = env.parse("ARGON2_MEMORY_KIB", 64 * 1024);
+        let iterations = env.parse("ARGON2_ITERATIONS", 3);
+        let parallelism = env.parse("ARGON2_PARALLELISM", 1);
+        let argon2 = Params::new(memory_kib, iterations, parallelism, None).unwrap_or_else(|e| {
+            env.invalid(&format!(
+                "ARGON2_MEMORY_KIB={}, ARGON2_ITERATIONS={}, ARGON2_PARALLELISM={} are not valid Argon2 parameters: {}",
+                memory_kib, iterations, parallelism, e
+            ));
+            Params::default()
+        });
+        let password_pepper = env.string("PASSWORD_PEPPER");
+
+        let hibp_api_url = env.string("HIBP_API_URL").unwrap_or_else(|| "https://api.pwnedpasswords.com".to_string());
+        let hibp_timeout_secs = env.parse("HIBP_TIMEOUT_SECS", 2);
+        if hibp_timeout_secs == 0 {
+            env.invalid("HIBP_TIMEOUT_SECS must be at least 1");
+        }
+        let breach_checker = env
+            .flag("HIBP_CHECK_ENABLED", false)
+            .then(|| Arc::new(BreachChecker::new(&hibp_api_url, Duration::from_secs(hibp_timeout_secs))));
+
+        let totp = env
+            .string("TOTP_ENCRYPTION_KEY")
+            .and_then(|key| TotpCipher::from_base64(&key).map_err(|e| env.invalid(&e)).ok())
+            .map(Arc::new);
+
+        let rate_limit_per_minute = env.parse("RATE_LIMIT_PER_MINUTE", 60);
+        if rate_limit_per_minute == 0 {
+            env.invalid("RATE_LIMIT_PER_MINUTE must be at least 1");
+        }
+        let resend_rate_limit_per_minute = env.parse("RESEND_LIMIT_PER_MINUTE", 3);
+        if resend_rate_limit_per_minute == 0 {
+            env.invalid("RESEND_LIMIT_PER_MINUTE must be at least 1");
+        }
+
+        let lockout_threshold = env.parse("LOCKOUT_THRESHOLD", 5);
+        if lockout_threshold < 1 {
+            env.invalid("LOCKOUT_THRESHOLD must be at least 1");
+        }
+        let lockout = LockoutPolicy {
+            max_failed_attempts: lockout_threshold,
+            lock_duration: chrono::Duration::minutes(env.parse("LOCKOUT_DURATION_MINS", 15)),
+        };
+
+        let proxy = ProxyConfig::parse(&env.string("TRUSTED_PROXIES").unwrap_or_default()).unwrap_or_else(|e| {
+            env.invalid(&e);
+            ProxyConfig::default()
+        });
+
+        let cors = CorsConfig {
+            allowed_origins: env
+                .string("ALLOWED_ORIGINS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect(),
+            allow_credentials: env.flag("CORS_ALLOW_CREDENTIALS", false),
+            max_age_secs: env.parse_optional("CORS_MAX_AGE_SECS"),
+        };
+        if cors.allow_credentials && cors.allows_any_origin() {
+            env.invalid("CORS_ALLOW_CREDENTIALS cannot be combined with ALLOWED_ORIGINS=*; list the trusted origins instead");
+        }
+
+        let content_security_policy = env
+            .string("CONTENT_SECURITY_POLICY")
+            .unwrap_or_else(|| DEFAULT_CONTENT_SECURITY_POLICY.to_string());
+        if actix_web::http::header::HeaderValue::from_str(&content_security_policy).is_err() {
+            env.invalid("CONTENT_SECURITY_POLICY must be a valid header value");
+        }
+
+        let error_format = match env.string("ERROR_FORMAT") {
+            None => ErrorFormat::default(),
+            Some(name) => ErrorFormat::parse(&name).unwrap_or_else(|| {
+                env.invalid(&format!("ERROR_FORMAT must be json or problem, got {:?}", name));
+                ErrorFormat::default()
+            }),
+        };
+
+        let log_exclude_paths = env
+            .string("LOG_EXCLUDE_PATHS")
+            .and_then(|list| LogExcludePaths::parse(&list).map_err(|e| env.invalid(&e)).ok())
+            .unwrap_or_default();
+
+        let mailer = match (env.string("SMTP_URL"), env.string("SMTP_FROM")) {
+            (Some(url), Some(from)) => {
+                let public_url = env.string("PUBLIC_URL").unwrap_or_else(|| format!("http://{}:{}", host, port));
+                Mailer::new(&url, &from, &public_url).map_err(|e| env.invalid(&e)).ok().map(Arc::new)
+            }
+            (Some(_), None) => {
+                env.invalid("SMTP_FROM must be set when SMTP_URL is");
+                None
+            }
+            (None, _) => None,
+        };
+
+        let admin_email = env.string("ADMIN_EMAIL");
+        let revocation_cleanup_interval = Duration::from_secs(env.parse("REVOCATION_CLEANUP_SECS", 3600));
+
+        let delete_mode = if env.flag("SOFT_DELETE", true) { DeleteMode::Soft } else { DeleteMode::Hard };
+        let maintenance_mode = env.flag("MAINTENANCE_MODE", false);
+
+        let disposable_domains = env
+            .string("DISPOSABLE_DOMAINS_PATH")
+            .and_then(|path| DomainBlocklist::load(Path::new(&path)).map_err(|e| env.invalid(&e)).ok())
+            .map(Arc::new);
+
+        let allowed_email_domains = env
+            .string("ALLOWED_EMAIL_DOMAINS")
+            .and_then(|list| DomainAllowlist::parse(&list).map_err(|e| env.invalid(&e)).ok())
+            .map(Arc::new);
+
+        // 🔒 Load the PEM files now so a bad path fails at startup, not on the first handshake
+        let tls = match (env.string("TLS_CERT_PATH"), env.string("TLS_KEY_PATH")) {
+            (Some(cert), Some(key)) => tls::load_server_config(Path::new(&cert), Path::new(&key))
+                .map_err(|e| env.invalid(&e))
+                .ok(),
+            (None, None) => None,
+            _ => {
+                env.invalid("TLS_CERT_PATH and TLS_KEY_PATH must be set together");
+                None
+            }
+        };
+
+        if !env.errors.is_empty() {
+            return Err(ConfigError(env.errors));
+        }
+
+        Ok(AppConfig {
+            host,
+            port,
+            bind_tcp,
+            bind_uds,
+            workers,
+            request_timeout,
+            import_max_users,
+            import_timeout,
+            page_size,
+            database,
+            db_retry,
+            jwt: JwtConfig::new(&jwt_secret, jwt_expiry_secs),
+            argon2_algorithm,
+            argon2_version,
+            argon2,
+            password_pepper,
+            breach_checker,
+            totp,
+            rate_limit_per_minute,
+            resend_rate_limit_per_minute,
+            lockout,
+            proxy,
+            cors,
+            content_security_policy,
+            error_format,
+            log_exclude_paths,
+            mailer,
+            admin_email,
+            revocation_cleanup_interval,
+            delete_mode,
+            maintenance_mode,
+            disposable_domains,
+            allowed_email_domains,
+            tls,
+        })
+    }
+}
+
+/// Read just `MAINTENANCE_MODE`, for reloading it while the server runs
+pub fn maintenance_mode_from_env() -> Result<bool, ConfigError> {
+    let mut env = EnvReader::default();
+    let enabled = env.flag("MAINTENANCE_MODE", false);
+
+    if env.errors.is_empty() { Ok(enabled) } else { Err(ConfigError(env.errors)) }
+}
+
+/// Reads variables while collecting problems instead of stopping at the first one
+#[derive(Default)]
+struct EnvReader {
+    errors: Vec<String>,
+}
+
+impl EnvReader {
+    /// The variable's value, treating an empty string as unset
+    fn string(&self, name: &str) -> Option<String> {
+        env::var(name).ok().filter(|value| !value.trim().is_empty())
+    }
+
+    fn required(&mut self, name: &str) -> String {
+        self.string(name).unwrap_or_else(|| {
+            self.invalid(&format!("{} must be set", name));
+            String::new()
+        })
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, default: T) -> T {
+        self.parse_optional(name).unwrap_or(default)
+    }
+
+    fn parse_optional<T: FromStr>(&mut self, name: &str) -> Option<T> {
+        let value = self.string(name)?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.invalid(&format!("{} must be a valid number, got {:?}", name, value));
+                None
+            }
+        }
+    }
+
+    /// A TCP port, rejecting 0 and anything that doesn't fit in a `u16`
+    fn port(&mut self, name: &str, default: u16) -> u16 {
+        let Some(value) = self.string(name) else {
+            return default;
+        };
+        match value.trim().parse::<u16>() {
+            Ok(port) if port != 0 => port,
+            _ => {
+                self.invalid(&format!("{} must be a port number between 1 and 65535, got {:?}", name, value));
+                default
+            }
+        }
+    }
+
+    fn flag(&mut self, name: &str, default: bool) -> bool {
+        match self.string(name).as_deref().map(str::trim) {
+            None => default,
+            Some("0" | "false" | "no") => false,
+            Some("1" | "true" | "yes") => true,
+            Some(other) => {
+                self.invalid(&format!("{} must be true or false, got {:?}", name, other));
+                default
+            }
+        }
+    }
+
+    fn invalid(&mut self, problem: &str) {
+        self.errors.push(problem.to_string());
+    }
+}
@@ -1,15 +1,257 @@
-use sqlx::{MySqlPool, mysql::MySqlPoolOptions};
-use std::env;
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use sqlx::mysql::MySqlDatabaseError;
+use sqlx::pool::PoolOptions;
+use sqlx::{Connection, Database, MySqlPool, PgPool, Pool, SqlitePool};
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::{Duration, Instant};
 
+use crate::config::DatabaseConfig;
 
-pub async fn connect() -> MySqlPool {
-    dotenvy::dotenv().ok(); // Load environment variables from .env file
+/// Connection pool for whichever database `DATABASE_URL` points at.
+///
+/// `mysql://` URLs use the sqlx `mysql` driver, `postgres://` (or
+/// `postgresql://`) URLs use the `postgres` driver and `sqlite:` URLs use the
+/// `sqlite` driver (meant for local development and tests); all are compiled in.
+#[derive(Clone)]
+pub enum DbPool {
+    MySql(MySqlPool),
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let db_pool = MySqlPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
-        .expect("Failed to create pool.");
-    db_pool
-}
\ No newline at end of file
+pub async fn connect(config: &DatabaseConfig) -> DbPool {
+    let database_url = &config.url;
+
+    // Pick the driver from the URL scheme
+    let pool = if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        DbPool::Postgres(
+            pool_options(config)
+                .connect(database_url)
+                .await
+                .expect("Failed to create pool."),
+        )
+    } else if database_url.starts_with("mysql://") {
+        DbPool::MySql(
+            pool_options(config)
+                .connect(database_url)
+                .await
+                .expect("Failed to create pool."),
+        )
+    } else if database_url.starts_with("sqlite:") {
+        DbPool::Sqlite(
+            pool_options(config)
+                .connect(database_url)
+                .await
+                .expect("Failed to create pool."),
+        )
+    } else {
+        unreachable!("AppConfig only accepts mysql://, postgres:// and sqlite: URLs");
+    };
+
+    // 🔥 Open the idle connections now rather than on the first requests
+    if config.warmup {
+        let start = Instant::now();
+        pool.warm_up(config.min_connections).await.expect("Failed to warm up the pool.");
+        tracing::info!(
+            connections = config.min_connections,
+            elapsed_ms = start.elapsed().as_millis() as u64,
+            "Warmed up the database pool"
+        );
+    }
+
+    pool
+}
+
+/// Pool settings shared by every driver
+fn pool_options<DB: Database>(config: &DatabaseConfig) -> PoolOptions<DB> {
+    let mut options = PoolOptions::<DB>::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections);
+
+    // Fail fast instead of queueing forever when every connection is busy
+    if let Some(timeout) = config.acquire_timeout {
+        options = options.acquire_timeout(timeout);
+    }
+
+    options
+}
+
+impl DbPool {
+    /// Apply pending migrations from the directory matching the driver
+    pub async fn migrate(&self) -> Result<(), MigrateError> {
+        match self {
+            DbPool::MySql(pool) => sqlx::migrate!("./migrations/mysql").run(pool).await,
+            DbPool::Postgres(pool) => sqlx::migrate!("./migrations/postgres").run(pool).await,
+            DbPool::Sqlite(pool) => sqlx::migrate!("./migrations/sqlite").run(pool).await,
+        }
+    }
+
+    /// Count the migrations embedded in this binary that the database hasn't applied.
+    ///
+    /// Only reads `_sqlx_migrations`, so a database that was never migrated fails
+    /// here instead of having the table created.
+    pub async fn pending_migrations(&self) -> Result<usize, MigrateError> {
+        match self {
+            DbPool::MySql(pool) => count_pending(&sqlx::migrate!("./migrations/mysql"), pool).await,
+            DbPool::Postgres(pool) => count_pending(&sqlx::migrate!("./migrations/postgres"), pool).await,
+            DbPool::Sqlite(pool) => count_pending(&sqlx::migrate!("./migrations/sqlite"), pool).await,
+        }
+    }
+
+    /// Run a trivial query to check the database is reachable
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        match self {
+            DbPool::MySql(pool) => sqlx::query("SELECT 1").execute(pool).await.map(|_| ()),
+            DbPool::Postgres(pool) => sqlx::query("SELECT 1").execute(pool).await.map(|_| ()),
+            DbPool::Sqlite(pool) => sqlx::query("SELECT 1").execute(pool).await.map(|_| ()),
+        }
+    }
+
+    /// Hold `count` connections at once, each checked with a round trip, then return them to the pool idle
+    pub async fn warm_up(&self, count: u32) -> Result<(), sqlx::Error> {
+        match self {
+            DbPool::MySql(pool) => open_connections(pool, count).await,
+            DbPool::Postgres(pool) => open_connections(pool, count).await,
+            DbPool::Sqlite(pool) => open_connections(pool, count).await,
+        }
+    }
+
+    /// Number of open connections, idle or in use
+    pub fn size(&self) -> u32 {
+        match self {
+            DbPool::MySql(pool) => pool.size(),
+            DbPool::Postgres(pool) => pool.size(),
+            DbPool::Sqlite(pool) => pool.size(),
+        }
+    }
+
+    /// Number of open connections not currently checked out
+    pub fn num_idle(&self) -> usize {
+        match self {
+            DbPool::MySql(pool) => pool.num_idle(),
+            DbPool::Postgres(pool) => pool.num_idle(),
+            DbPool::Sqlite(pool) => pool.num_idle(),
+        }
+    }
+
+    /// Most connections the pool will open (`DB_MAX_CONNECTIONS`)
+    pub fn max_connections(&self) -> u32 {
+        match self {
+            DbPool::MySql(pool) => pool.options().get_max_connections(),
+            DbPool::Postgres(pool) => pool.options().get_max_connections(),
+            DbPool::Sqlite(pool) => pool.options().get_max_connections(),
+        }
+    }
+
+    /// Close every connection, waiting for checked-out ones to be returned
+    pub async fn close(&self) {
+        match self {
+            DbPool::MySql(pool) => pool.close().await,
+            DbPool::Postgres(pool) => pool.close().await,
+            DbPool::Sqlite(pool) => pool.close().await,
+        }
+    }
+}
+
+/// Up migrations in `migrator` missing from the database's applied list
+async fn count_pending<DB>(migrator: &Migrator, pool: &Pool<DB>) -> Result<usize, MigrateError>
+where
+    DB: Database,
+    DB::Connection: Migrate,
+{
+    let mut connection = pool.acquire().await?;
+    let applied: HashSet<i64> = connection
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+
+    Ok(migrator
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration() && !applied.contains(&migration.version))
+        .count())
+}
+
+/// Acquire `count` distinct connections, so the pool has to open that many
+async fn open_connections<DB: Database>(pool: &Pool<DB>, count: u32) -> Result<(), sqlx::Error> {
+    let mut held = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut connection = pool.acquire().await?;
+        connection.ping().await?;
+        held.push(connection);
+    }
+
+    Ok(())
+}
+
+/// Returns true when the error is a UNIQUE constraint violation
+/// (MySQL error 1062, Postgres SQLSTATE 23505, SQLite `SQLITE_CONSTRAINT_UNIQUE`).
+pub fn is_duplicate_entry(error: &sqlx::Error) -> bool {
+    error
+        .as_database_error()
+        .is_some_and(|e| e.is_unique_violation())
+}
+
+/// Returns true for errors that are worth retrying as-is: MySQL deadlocks
+/// (error 1213) and lock wait timeouts (error 1205)
+pub fn is_transient(error: &sqlx::Error) -> bool {
+    error
+        .as_database_error()
+        .and_then(|e| e.try_downcast_ref::<MySqlDatabaseError>())
+        .is_some_and(|e| matches!(e.number(), 1205 | 1213))
+}
+
+/// How often `with_retry` re-runs an operation that failed transiently
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,     // Retries after the first attempt; 0 disables retrying
+    pub base_delay: Duration, // Wait before the first retry, doubled before each later one
+}
+
+/// Run `operation`, re-running it with exponential backoff while it fails with a
+/// transient error (see `is_transient`) and retries remain.
+///
+/// The operation must be safe to repeat, e.g. a single statement or a whole
+/// transaction that rolled back.
+pub async fn with_retry<T, F, Fut>(policy: RetryPolicy, mut operation: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut delay = policy.base_delay;
+
+    for attempt in 1..=policy.max_retries {
+        match operation().await {
+            Err(e) if is_transient(&e) => {
+                tracing::warn!(
+                    attempt,
+                    max_retries = policy.max_retries,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %e,
+                    "Transient database error, retrying"
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    // 🔁 Last attempt; whatever it returns is final
+    operation().await
+}
+
+/// Escape `%`, `_` and the escape character itself so user input is matched literally
+/// by `LIKE ? ESCAPE '!'`
+pub fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '!' | '%' | '_') {
+            escaped.push('!');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
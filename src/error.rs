@@ -0,0 +1,214 @@
+use actix_web::error::JsonPayloadError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use std::collections::BTreeMap;
+use thiserror::Error;
+use validator::ValidationErrors;
+
+/// Application-wide error type returned by handlers.
+///
+/// Each variant maps to one HTTP status code and renders as a JSON body, so
+/// handlers can return `Result<HttpResponse, AppError>` and use `?`.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("validation failed: {0}")]
+    Validation(#[from] ValidationErrors),
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    Locked(String),
+
+    #[error("{0}")]
+    PayloadTooLarge(String),
+
+    #[error("{0}")]
+    UnprocessableEntity(String),
+
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+
+    /// Seconds until the client's rate limit lets another request through
+    #[error("too many requests")]
+    TooManyRequests(u64),
+
+    #[error("{0}")]
+    Timeout(String),
+
+    #[error("{0}")]
+    ServiceUnavailable(String),
+
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Validation(_) | AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Locked(_) => StatusCode::LOCKED,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Database(sqlx::Error::PoolTimedOut) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // 🛑 Server-side failures are logged but never leak details to the client
+        match self {
+            // 🚰 Its own event, so capacity alerts don't drown in other database errors
+            AppError::Database(sqlx::Error::PoolTimedOut) => tracing::warn!(
+                event = "db_pool_exhausted",
+                "No database connection became free within the acquire timeout"
+            ),
+            AppError::Database(_) | AppError::Internal(_) => tracing::error!("{}", self),
+            _ => {}
+        }
+
+        self.render(ErrorFormat::Json)
+    }
+}
+
+/// Envelope used for error bodies, from `ERROR_FORMAT` or the client's `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorFormat {
+    /// `{"error": "..."}`, or `{"errors": {...}}` for validation failures
+    #[default]
+    Json,
+    /// RFC 7807 `application/problem+json`
+    Problem,
+}
+
+impl ErrorFormat {
+    /// Map an `ERROR_FORMAT` value to a format; `None` for unknown names
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(ErrorFormat::Json),
+            "problem" | "problem+json" => Some(ErrorFormat::Problem),
+            _ => None,
+        }
+    }
+}
+
+impl AppError {
+    /// Render the error in `format`; `error_response` uses `ErrorFormat::Json`
+    /// and `negotiate_error_format` re-renders for clients that want problem details
+    pub fn render(&self, format: ErrorFormat) -> HttpResponse {
+        let status = self.status_code();
+        let (detail, errors) = match self {
+            // 🔍 Validation errors list every failing field's messages
+            AppError::Validation(errors) => ("validation failed", Some(flatten_validation_errors(errors))),
+            AppError::BadRequest(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::Locked(message)
+            | AppError::PayloadTooLarge(message)
+            | AppError::UnprocessableEntity(message)
+            | AppError::UnsupportedMediaType(message)
+            | AppError::Timeout(message)
+            | AppError::ServiceUnavailable(message) => (message.as_str(), None),
+            AppError::TooManyRequests(_) => ("too many requests", None),
+            AppError::Database(sqlx::Error::PoolTimedOut) => ("Service temporarily unavailable, please retry", None),
+            AppError::Database(_) | AppError::Internal(_) => ("Something went wrong", None),
+        };
+
+        let mut response = HttpResponse::build(status);
+        // ⏳ A busy pool frees up quickly, so tell clients when to come back instead of hammering it
+        if matches!(self, AppError::Database(sqlx::Error::PoolTimedOut)) {
+            response.insert_header((header::RETRY_AFTER, POOL_TIMEOUT_RETRY_AFTER_SECS.to_string()));
+        }
+        if let AppError::TooManyRequests(retry_after) = self {
+            response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+
+        match format {
+            ErrorFormat::Json => response.json(match errors {
+                Some(errors) => serde_json::json!({ "errors": errors }),
+                None => serde_json::json!({ "error": detail }),
+            }),
+            ErrorFormat::Problem => {
+                let mut problem = serde_json::json!({
+                    "type": "about:blank",
+                    "title": status.canonical_reason().unwrap_or("Error"),
+                    "status": status.as_u16(),
+                    "detail": detail,
+                });
+                // Extension member carrying the same per-field messages as the JSON shape
+                if let Some(errors) = errors {
+                    problem["errors"] = serde_json::json!(errors);
+                }
+
+                response.content_type(PROBLEM_JSON).json(problem)
+            }
+        }
+    }
+}
+
+/// `Retry-After` seconds sent with the 503 for an exhausted connection pool
+pub const POOL_TIMEOUT_RETRY_AFTER_SECS: u64 = 5;
+
+/// Media type of RFC 7807 problem details
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Flatten a validator report into `field -> [message, ...]`, sorted by field name.
+///
+/// Rules without a custom message fall back to their code (e.g. `"length"`).
+pub fn flatten_validation_errors(errors: &ValidationErrors) -> BTreeMap<String, Vec<String>> {
+    errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages = errors
+                .iter()
+                .map(|error| error.message.as_deref().unwrap_or(&error.code).to_string())
+                .collect();
+            (field.to_string(), messages)
+        })
+        .collect()
+}
+
+/// `web::JsonConfig` error handler, so bad bodies get the usual `{"error": ...}`
+/// JSON instead of Actix's plain-text errors
+pub fn json_payload_error(error: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let app_error = match &error {
+        JsonPayloadError::ContentType => {
+            AppError::UnsupportedMediaType("Content-Type must be application/json".to_string())
+        }
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            AppError::PayloadTooLarge("request body is too large".to_string())
+        }
+        // 🔍 Syntax errors say nothing useful; wrong fields or types name the problem
+        JsonPayloadError::Deserialize(e) if e.is_data() => AppError::BadRequest(format!("invalid JSON body: {}", e)),
+        JsonPayloadError::Deserialize(_) => AppError::BadRequest("malformed JSON body".to_string()),
+        _ => AppError::BadRequest("could not read request body".to_string()),
+    };
+
+    app_error.into()
+}
@@ -0,0 +1,151 @@
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use std::future::{ready, Ready};
+use std::ops::{Deref, DerefMut};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::error::AppError;
+use crate::maintenance::MaintenanceMode;
+
+/// The route's path parameter (e.g. `{id}` in `/users/{id}`), parsed as a UUID.
+///
+/// Malformed ids are rejected with a 400 before the handler runs, so handlers
+/// never touch the database with them.
+#[derive(Debug, Clone, Copy)]
+pub struct ValidatedUuid(pub Uuid);
+
+impl Deref for ValidatedUuid {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl FromRequest for ValidatedUuid {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // 🔍 Routes using this extractor have a single dynamic segment
+        let result = req
+            .match_info()
+            .iter()
+            .next()
+            .and_then(|(_, segment)| Uuid::parse_str(segment).ok())
+            .map(ValidatedUuid)
+            .ok_or_else(|| AppError::BadRequest("invalid id".to_string()));
+
+        ready(result)
+    }
+}
+
+/// Guard for handlers that change data: `503 {"error": "maintenance"}` while
+/// `MAINTENANCE_MODE` is on. Put it first so nothing else runs before the refusal.
+#[derive(Debug, Clone, Copy)]
+pub struct WritesAllowed;
+
+impl FromRequest for WritesAllowed {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let in_maintenance = req
+            .app_data::<web::Data<MaintenanceMode>>()
+            .is_some_and(|mode| mode.is_enabled());
+
+        ready(if in_maintenance {
+            Err(AppError::ServiceUnavailable("maintenance".to_string()))
+        } else {
+            Ok(WritesAllowed)
+        })
+    }
+}
+
+/// Longest `Idempotency-Key` accepted (the column width)
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// The optional `Idempotency-Key` request header.
+///
+/// Keys longer than 255 characters or containing anything but visible ASCII
+/// are rejected with a 400.
+#[derive(Debug, Clone)]
+pub struct IdempotencyKey(pub Option<String>);
+
+impl FromRequest for IdempotencyKey {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let Some(value) = req.headers().get("Idempotency-Key") else {
+            return ready(Ok(IdempotencyKey(None)));
+        };
+
+        let result = value
+            .to_str()
+            .ok()
+            .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN)
+            .filter(|key| key.chars().all(|c| c.is_ascii_graphic()))
+            .map(|key| IdempotencyKey(Some(key.to_string())))
+            .ok_or_else(|| {
+                AppError::BadRequest("Idempotency-Key must be 1 to 255 visible ASCII characters".to_string())
+            });
+
+        ready(result)
+    }
+}
+
+/// Tidy a request body's fields (trim, lowercase) before it is validated
+pub trait Normalize {
+    fn normalize(&mut self) {}
+}
+
+/// A JSON body that has been normalized and has passed its `#[validate]` rules.
+///
+/// Parsing goes through `web::Json`, so the app's `JsonConfig` size limit and
+/// error handler still apply; failing rules answer
+/// `400 {"errors": {field: [message, ...]}}` before the handler runs.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ValidatedJson<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate + Normalize + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = web::Json::<T>::from_request(req, payload);
+
+        Box::pin(async move {
+            let mut body = json.await?.into_inner();
+            body.normalize();
+            body.validate().map_err(AppError::Validation)?;
+            Ok(ValidatedJson(body))
+        })
+    }
+}
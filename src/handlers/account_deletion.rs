@@ -0,0 +1,102 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+use chrono::{Duration, Utc};
+
+use crate::auth::AuthenticatedUser;
+use crate::config::DeleteMode;
+use crate::error::AppError;
+use crate::extractors::WritesAllowed;
+use crate::mailer::{spawn_email, AccountEmail, Mailer};
+use crate::models::user::DeleteAccountConfirm;
+use crate::repository::UserRepository;
+use crate::tokens::generate_token;
+
+/// How long an account deletion token stays valid
+const ACCOUNT_DELETION_TOKEN_TTL_HOURS: i64 = 1;
+
+/// Handler to start deleting the caller's own account: emails a one-time
+/// token that `POST /users/me/delete-confirm` needs
+#[utoipa::path(
+    post, path = "/users/me/delete-request", tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 202, description = "Confirmation token emailed", body = crate::openapi::MessageResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "The token's user was deleted", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn request_account_deletion(
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,              // Reject the request with 401 unless a valid token is supplied
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+    mailer: Option<web::Data<Mailer>>,    // Inject the SMTP mailer, if SMTP_URL is set
+) -> Result<HttpResponse, AppError> {
+    let user = users
+        .find_by_id(&auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("user not found".to_string()))?;
+
+    // 🎟️ A new request replaces any earlier token, so only the latest email works
+    let token = generate_token();
+    let now = Utc::now();
+    users
+        .replace_account_deletion(&user.id, &token, now + Duration::hours(ACCOUNT_DELETION_TOKEN_TTL_HOURS), now)
+        .await?;
+
+    // Also logged so the flow can be completed on setups without SMTP_URL
+    tracing::debug!(user_id = %user.id, token = %token, "Account deletion token issued");
+
+    spawn_email(
+        mailer.map(|mailer| mailer.into_inner()),
+        user.id,
+        AccountEmail::AccountDeletion { email: user.email, token },
+    );
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "message": "Confirm the deletion with the token sent to your email address"
+    })))
+}
+
+/// Handler to delete the caller's own account with the emailed token
+#[utoipa::path(
+    post, path = "/users/me/delete-confirm", tag = "users",
+    request_body = DeleteAccountConfirm,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 204, description = "Account deleted (soft or hard per SOFT_DELETE)"),
+        (status = 400, description = "Invalid, expired or already used token", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "The token's user was deleted", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn confirm_account_deletion(
+    _writes: WritesAllowed,                // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,               // Reject the request with 401 unless a valid token is supplied
+    body: web::Json<DeleteAccountConfirm>, // Deserialize the emailed token
+    users: web::Data<dyn UserRepository>,  // Inject the user storage
+    mode: web::Data<DeleteMode>,           // Inject whether deletes are soft or hard
+) -> Result<HttpResponse, AppError> {
+    // ✅ Use up the token; it must be the caller's own and not expired
+    let now = Utc::now();
+    if !users.consume_account_deletion(&auth.user_id, &body.token, now).await? {
+        return Err(AppError::BadRequest("invalid or expired token".to_string()));
+    }
+
+    // 🗑️ Same soft or hard delete as `DELETE /users/{id}`
+    let deleted = match **mode {
+        DeleteMode::Soft => users.soft_delete(&auth.user_id, now).await?,
+        DeleteMode::Hard => users.delete(&auth.user_id).await?,
+    };
+
+    if !deleted {
+        return Err(AppError::NotFound("user not found".to_string()));
+    }
+
+    // 🚪 The access token that asked for the deletion stops working with it
+    users.revoke_token(&auth.jti, auth.expires_at).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,21 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+// Import the admin guard
+use crate::auth::{Admin, RequireRole};
+
+// Import the driver-agnostic connection pool
+use crate::db::DbPool;
+
+/// Handler reporting live connection pool usage, for diagnosing acquire timeouts (admins only)
+pub async fn pool_stats(
+    _admin: RequireRole<Admin>, // 401 without a valid token, 403 unless the caller is an admin
+    db: web::Data<DbPool>,      // Inject the database pool
+) -> HttpResponse {
+    // 🔌 `size == max` with `idle == 0` means every connection is checked out
+    HttpResponse::Ok().json(serde_json::json!({
+        "size": db.size(),
+        "idle": db.num_idle(),
+        "max": db.max_connections(),
+    }))
+}
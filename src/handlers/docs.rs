@@ -0,0 +1,50 @@
+// Import necessary modules from Actix-Web
+use actix_web::{http::header, HttpResponse};
+
+use std::sync::LazyLock;
+use utoipa::OpenApi;
+
+// Import the generated API description
+use crate::openapi::ApiDoc;
+
+/// Swagger UI page; the viewer's assets come from a CDN, the spec from this server
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>rust_learning API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/api-docs/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// The document is fixed at compile time, so it is rendered once
+static OPENAPI_JSON: LazyLock<String> =
+    LazyLock::new(|| ApiDoc::openapi().to_json().expect("Failed to serialize the OpenAPI document"));
+
+/// Handler serving the OpenAPI document (no auth required)
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(OPENAPI_JSON.as_str())
+}
+
+/// Lets the page run its inline bootstrap script and load the viewer from unpkg,
+/// which the app-wide policy forbids
+const SWAGGER_UI_CSP: &str = "default-src 'none'; script-src 'unsafe-inline' https://unpkg.com; \
+    style-src https://unpkg.com; img-src 'self' data:; connect-src 'self'; frame-ancestors 'none'";
+
+/// Handler serving an interactive Swagger UI for the OpenAPI document (no auth required)
+pub async fn swagger_ui() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CONTENT_SECURITY_POLICY, SWAGGER_UI_CSP))
+        .body(SWAGGER_UI_HTML)
+}
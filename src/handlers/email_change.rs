@@ -0,0 +1,102 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+// Import the `Validate` trait for input validation
+use validator::Validate;
+
+use chrono::{Duration, Utc};
+
+use crate::auth::AuthenticatedUser;
+use crate::blocklist::{DomainAllowlist, DomainBlocklist};
+use crate::db;
+use crate::error::AppError;
+use crate::extractors::{ValidatedUuid, WritesAllowed};
+use crate::mailer::{spawn_email, AccountEmail, Mailer};
+use crate::models::user::{normalize_email, ChangeEmailRequest, VerifyEmailQuery};
+use crate::repository::UserRepository;
+use crate::tokens::generate_token;
+
+/// How long an email change confirmation link stays valid
+const EMAIL_CHANGE_TOKEN_TTL_HOURS: i64 = 24;
+
+/// Handler to start changing the caller's email; the current address stays
+/// active until the new one is confirmed
+#[allow(clippy::too_many_arguments)] // Each dependency is its own actix extractor
+pub async fn request_email_change(
+    _writes: WritesAllowed,                        // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,                       // Reject the request with 401 unless a valid token is supplied
+    user_id: ValidatedUuid,                        // Extract the user id from the URL, 400 if it is not a UUID
+    mut body: web::Json<ChangeEmailRequest>,       // Deserialize the requested new email
+    users: web::Data<dyn UserRepository>,          // Inject the user storage
+    blocklist: Option<web::Data<DomainBlocklist>>, // Inject the disposable domain list, if one is configured
+    allowlist: Option<web::Data<DomainAllowlist>>, // Inject the only domains accounts may use, if configured
+    mailer: Option<web::Data<Mailer>>,             // Inject the SMTP mailer, if SMTP_URL is set
+) -> Result<HttpResponse, AppError> {
+    // 🛡️ Only the account owner may move its email
+    let user_id = user_id.to_string();
+    if auth.user_id != user_id {
+        return Err(AppError::Forbidden("forbidden".to_string()));
+    }
+
+    // ✉️ Normalize and validate the new address like registration does
+    body.email = normalize_email(&body.email);
+    body.validate()?;
+
+    // 🏢 Moving off an allowed domain would sidestep ALLOWED_EMAIL_DOMAINS
+    if allowlist.is_some_and(|allowlist| !allowlist.is_allowed(&body.email)) {
+        return Err(AppError::Forbidden("email domain not allowed".to_string()));
+    }
+
+    if blocklist.is_some_and(|blocklist| blocklist.is_blocked(&body.email)) {
+        return Err(AppError::BadRequest("disposable email not allowed".to_string()));
+    }
+
+    // 🚫 Refuse addresses that already belong to an account, including this one
+    if users.email_exists(&body.email).await? {
+        return Err(AppError::Conflict("email already registered".to_string()));
+    }
+
+    // 🎟️ Park the address as pending with a one-time confirmation token
+    let token = generate_token();
+    let expires_at = Utc::now() + Duration::hours(EMAIL_CHANGE_TOKEN_TTL_HOURS);
+    if !users.request_email_change(&user_id, &body.email, &token, expires_at).await? {
+        return Err(AppError::NotFound("user not found".to_string()));
+    }
+
+    // Also logged so the flow can be completed on setups without SMTP_URL
+    tracing::debug!(user_id = %user_id, token = %token, "Email change token issued");
+
+    // 📧 The link goes to the new address, proving the caller controls it
+    spawn_email(
+        mailer.map(|mailer| mailer.into_inner()),
+        user_id,
+        AccountEmail::EmailChange { email: body.email.clone(), token },
+    );
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "message": "Confirm the new email address to complete the change"
+    })))
+}
+
+/// Handler for the link sent to the new address to confirm an email change
+pub async fn confirm_email_change(
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    query: web::Query<VerifyEmailQuery>,  // Extract `token` from the query string
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // ✅ Swap in the pending email and consume the token, ignoring expired tokens
+    let confirmed = users.confirm_email_change(&query.token, Utc::now()).await.map_err(|e| {
+        // 🚫 Someone else took the address after the change was requested
+        if db::is_duplicate_entry(&e) {
+            AppError::Conflict("email already registered".to_string())
+        } else {
+            AppError::Database(e)
+        }
+    })?;
+
+    if !confirmed {
+        return Err(AppError::BadRequest("invalid or expired token".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Email changed successfully" })))
+}
@@ -0,0 +1,131 @@
+// Import necessary modules from Actix-Web
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+
+use futures_util::stream::{self, StreamExt};
+use std::borrow::Cow;
+
+// Import the auth extractors
+use crate::auth::{Admin, AuthenticatedUser, RequireRole};
+
+// Import the extractor that rejects malformed id path params
+use crate::extractors::ValidatedUuid;
+
+// Import the unified application error type
+use crate::error::AppError;
+
+// Import the user row the export is built from
+use crate::models::user::User;
+
+// Import the storage abstraction the handlers run their queries through
+use crate::repository::UserRepository;
+
+/// Users fetched per query while streaming the export
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// Column names, sent before the first page is fetched
+const EXPORT_HEADER: &[u8] = b"id,name,email,created_at\n";
+
+/// Handler to download every live user as CSV (admins only).
+///
+/// Rows are fetched and sent a page at a time, so memory use doesn't grow with
+/// the table. Soft-deleted users and password hashes are never included.
+pub async fn export_users_csv(
+    _admin: RequireRole<Admin>,           // 401 without a valid token, 403 unless the caller is an admin
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> HttpResponse {
+    let users = users.into_inner();
+
+    // 📄 Walk the table by id; `None` once the last (short) page has been sent
+    let pages = stream::try_unfold(Some(String::new()), move |cursor| {
+        let users = users.clone();
+        async move {
+            let Some(after) = cursor else {
+                return Ok(None);
+            };
+
+            let page = users.list_after(&after, EXPORT_PAGE_SIZE).await?;
+            if page.is_empty() {
+                return Ok(None);
+            }
+
+            let next = (page.len() as i64 == EXPORT_PAGE_SIZE).then(|| page[page.len() - 1].id.clone());
+            Ok(Some((encode_rows(&page)?, next)))
+        }
+    });
+
+    let body = stream::once(async { Ok::<_, AppError>(Bytes::from_static(EXPORT_HEADER)) })
+        .chain(pages)
+        .inspect(|chunk| {
+            // 🛑 The status line is already sent, so a failure can only cut the download short
+            if let Err(e) = chunk {
+                tracing::error!("User export aborted: {}", e);
+            }
+        });
+
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("users.csv".to_string())],
+        })
+        .streaming(body)
+}
+
+/// Encode one page as CSV rows, quoting fields that need it
+fn encode_rows(page: &[User]) -> Result<Bytes, AppError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    for user in page {
+        let created_at = user.created_at.to_rfc3339();
+        // 🧮 Names and emails are user input; keep spreadsheets from running them as formulas
+        let name = spreadsheet_safe(&user.name);
+        let email = spreadsheet_safe(&user.email);
+        writer
+            .write_record([user.id.as_str(), &name, &email, created_at.as_str()])
+            .map_err(|e| AppError::Internal(format!("Error encoding CSV row: {}", e)))?;
+    }
+
+    let rows = writer
+        .into_inner()
+        .map_err(|e| AppError::Internal(format!("Error encoding CSV rows: {}", e)))?;
+    Ok(Bytes::from(rows))
+}
+
+/// Prefix a cell that a spreadsheet would read as a formula with `'`, so it is shown as text
+fn spreadsheet_safe(value: &str) -> Cow<'_, str> {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{}", value))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Handler returning everything stored about one account as a JSON download,
+/// for data subject access requests (the account itself or an admin).
+///
+/// Soft-deleted accounts can still be exported by an admin. The password hash
+/// and one-time tokens are never included.
+pub async fn export_user_data(
+    auth: AuthenticatedUser,              // Reject the request with 401 unless a valid token is supplied
+    user_id: ValidatedUuid,               // Extract the user id from the URL, 400 if it is not a UUID
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // 🛡️ Only the account owner or an admin may read the full record
+    let user_id = user_id.to_string();
+    auth.require_self_or_admin(&user_id)?;
+
+    // 🗂️ Gather the profile and its related rows from every table
+    let export = users
+        .export_user(&user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("user not found".to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("user-{}.json", user_id))],
+        })
+        .json(export))
+}
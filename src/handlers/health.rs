@@ -0,0 +1,60 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+// Import the driver-agnostic connection pool
+use crate::db::DbPool;
+
+use std::time::Duration;
+
+/// How long the database checks may take before the service is reported unavailable
+const DB_PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Liveness probe: 200 whenever the process can answer at all (no auth required).
+///
+/// Deliberately ignores the database, so a DB outage never gets the pod restarted.
+pub async fn livez() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness probe: 200 only while the database answers and has every migration
+/// this binary ships applied (no auth required).
+///
+/// Also served as `/health` for existing load balancer checks.
+pub async fn readyz(db: web::Data<DbPool>) -> HttpResponse {
+    // 🩺 Ping the database, giving up after the timeout so a hung DB can't hang the probe
+    let ping = tokio::time::timeout(DB_PING_TIMEOUT, db.ping()).await;
+
+    match ping {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => {
+            tracing::error!("Health check query failed: {}", e);
+            return unavailable();
+        }
+        Err(_) => {
+            tracing::error!("Health check query timed out");
+            return unavailable();
+        }
+    }
+
+    // 📜 A new binary against an old schema would fail on the first query that needs a new column
+    match tokio::time::timeout(DB_PING_TIMEOUT, db.pending_migrations()).await {
+        Ok(Ok(0)) => HttpResponse::Ok().json(serde_json::json!({ "status": "ok", "migrations": "applied" })),
+        Ok(Ok(pending)) => {
+            tracing::error!(pending, "Database schema is behind this build; migrations are pending");
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "status": "unavailable", "migrations": "pending" }))
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Migration status check failed: {}", e);
+            unavailable()
+        }
+        Err(_) => {
+            tracing::error!("Migration status check timed out");
+            unavailable()
+        }
+    }
+}
+
+/// 503 for a database that can't be reached or queried
+fn unavailable() -> HttpResponse {
+    HttpResponse::ServiceUnavailable().json(serde_json::json!({ "status": "unavailable" }))
+}
@@ -0,0 +1,207 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde_json::value::RawValue;
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fmt;
+
+// Import UUID generator for user IDs
+use uuid::Uuid;
+
+// Import the `Validate` trait for input validation
+use validator::Validate;
+
+// Import application-level models
+use crate::models::user::{NewUser, RegisterRequest};
+
+// Import the normalization `register_user` applies before validating
+use crate::extractors::{Normalize, WritesAllowed};
+
+// Import the admin guard
+use crate::auth::{Admin, RequireRole};
+
+// Import the optional disposable email domain blocklist and corporate allowlist
+use crate::blocklist::{DomainAllowlist, DomainBlocklist};
+
+// Import database error helpers
+use crate::db;
+
+// Import the unified application error type and the validation report shape
+use crate::error::{flatten_validation_errors, AppError};
+
+// Import the shared Argon2 password hasher
+use crate::password::PasswordHasher;
+
+// Import the storage abstraction the handlers run their queries through
+use crate::repository::UserRepository;
+
+/// Most users `POST /users/bulk` accepts in one request (`IMPORT_MAX_USERS`)
+#[derive(Debug, Clone, Copy)]
+pub struct ImportLimit(pub usize);
+
+/// Body bytes allowed per imported user; registration fields are all short
+const IMPORT_BYTES_PER_USER: usize = 1024;
+
+impl ImportLimit {
+    /// Largest JSON body `POST /users/bulk` accepts, enough for a full batch
+    pub fn body_limit_bytes(&self) -> usize {
+        self.0.saturating_mul(IMPORT_BYTES_PER_USER)
+    }
+}
+
+/// Handler to create many users at once, e.g. when migrating from another system (admins only).
+///
+/// Each entry is checked like a registration. Entries that pass are inserted
+/// in one transaction as already verified. The response lists one result per
+/// entry, in request order.
+#[allow(clippy::too_many_arguments)] // Each dependency is its own actix extractor
+pub async fn import_users(
+    _writes: WritesAllowed,                        // 503 while MAINTENANCE_MODE is on
+    _admin: RequireRole<Admin>,                    // 401 without a valid token, 403 unless the caller is an admin
+    body: web::Json<Box<RawValue>>,                // Check the body is JSON within the size limit, without building it yet
+    limit: web::Data<ImportLimit>,                 // Inject the most entries one request may hold
+    users: web::Data<dyn UserRepository>,          // Inject the user storage
+    hasher: web::Data<PasswordHasher>,             // Inject the shared Argon2 hasher
+    blocklist: Option<web::Data<DomainBlocklist>>, // Inject the disposable domain list, if one is configured
+    allowlist: Option<web::Data<DomainAllowlist>>, // Inject the only domains that may register, if configured
+) -> Result<HttpResponse, AppError> {
+    // 🚫 Bound the Argon2 work and the transaction size, stopping at the first entry past the cap
+    let requests = parse_batch(body.get(), limit.0)?;
+
+    let mut results = Vec::with_capacity(requests.len());
+    let mut accepted = Vec::new();
+    let mut passwords = Vec::new();
+    let mut seen_emails = HashSet::new();
+    let mut seen_usernames = HashSet::new();
+
+    for (index, mut user) in requests.into_iter().enumerate() {
+        // ✉️ Normalize exactly like `register_user`
+        user.normalize();
+
+        // 🔍 Run the registration checks, also against earlier entries of this batch
+        let checked = match user.validate() {
+            Err(errors) => Err(AppError::Validation(errors)),
+            Ok(()) if allowlist.as_ref().is_some_and(|allowlist| !allowlist.is_allowed(&user.email)) => {
+                Err(AppError::Forbidden("email domain not allowed".to_string()))
+            }
+            Ok(()) if blocklist.as_ref().is_some_and(|blocklist| blocklist.is_blocked(&user.email)) => {
+                Err(AppError::BadRequest("disposable email not allowed".to_string()))
+            }
+            Ok(()) if seen_emails.contains(&user.email) || users.email_exists(&user.email).await? => {
+                Err(AppError::Conflict("email already registered".to_string()))
+            }
+            Ok(()) if seen_usernames.contains(&user.username) || users.username_exists(&user.username).await? => {
+                Err(AppError::Conflict("username already taken".to_string()))
+            }
+            Ok(()) => Ok(()),
+        };
+
+        if let Err(error) = checked {
+            results.push(item_error(index, error));
+            continue;
+        }
+
+        let id = Uuid::new_v4().to_string();
+        results.push(json!({ "index": index, "status": "created", "id": id }));
+        seen_emails.insert(user.email.clone());
+        seen_usernames.insert(user.username.clone());
+        passwords.push(user.password);
+        accepted.push(NewUser {
+            id,
+            name: user.name,
+            email: user.email,
+            username: user.username,
+            phone: user.phone,
+            password_hash: String::new(), // Filled in below
+        });
+    }
+
+    // 🔒 Only entries that will be inserted pay for hashing, on the blocking pool so
+    // a full batch of Argon2 runs doesn't stall every other request on this worker
+    let hasher = hasher.into_inner();
+    let hashes = web::block(move || passwords.iter().map(|password| hasher.hash(password)).collect::<Result<Vec<_>, _>>())
+        .await
+        .map_err(|e| AppError::Internal(format!("Password hashing task failed: {}", e)))??;
+    for (user, hash) in accepted.iter_mut().zip(hashes) {
+        user.password_hash = hash;
+    }
+
+    // 🛢️ All accepted entries go in together or not at all
+    users.import_users(&accepted).await.map_err(|e| {
+        // 🚫 Backstop for a registration racing the checks above
+        if db::is_duplicate_entry(&e) {
+            AppError::Conflict("email or username already registered".to_string())
+        } else {
+            AppError::Database(e)
+        }
+    })?;
+
+    tracing::info!(imported = accepted.len(), rejected = results.len() - accepted.len(), "Bulk user import finished");
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
+/// Deserialize the array of registrations, failing with 413 as soon as it holds more than `max`
+fn parse_batch(json: &str, max: usize) -> Result<Vec<RegisterRequest>, AppError> {
+    let exceeded = Cell::new(false);
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+
+    BoundedBatch { max, exceeded: &exceeded }
+        .deserialize(&mut deserializer)
+        .map_err(|e| {
+            if exceeded.get() {
+                AppError::PayloadTooLarge(format!("at most {} users can be imported at once", max))
+            } else if e.is_data() {
+                AppError::BadRequest(format!("invalid JSON body: {}", e))
+            } else {
+                AppError::BadRequest("malformed JSON body".to_string())
+            }
+        })
+}
+
+/// Collects array entries until there are more than `max`, then gives up on the rest
+struct BoundedBatch<'a> {
+    max: usize,
+    exceeded: &'a Cell<bool>,
+}
+
+impl<'de> DeserializeSeed<'de> for BoundedBatch<'_> {
+    type Value = Vec<RegisterRequest>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for BoundedBatch<'_> {
+    type Value = Vec<RegisterRequest>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of registrations")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut requests = Vec::new();
+        while let Some(request) = seq.next_element()? {
+            if requests.len() == self.max {
+                self.exceeded.set(true);
+                return Err(de::Error::custom("too many entries"));
+            }
+            requests.push(request);
+        }
+        Ok(requests)
+    }
+}
+
+/// Describe why entry `index` was skipped, in the same shape the single-user endpoints use
+fn item_error(index: usize, error: AppError) -> Value {
+    match error {
+        AppError::Validation(errors) => {
+            json!({ "index": index, "status": "error", "errors": flatten_validation_errors(&errors) })
+        }
+        other => json!({ "index": index, "status": "error", "error": other.to_string() }),
+    }
+}
@@ -0,0 +1,103 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+use chrono::Utc;
+
+use crate::auth::AuthenticatedUser;
+use crate::error::AppError;
+use crate::extractors::{ValidatedUuid, WritesAllowed};
+use crate::models::pagination::{PageSize, PaginationQuery};
+use crate::repository::UserRepository;
+
+/// Handler listing the caller's most recent successful logins, newest first
+#[utoipa::path(
+    get, path = "/users/{id}/logins", tag = "users",
+    params(("id" = String, Path, description = "User id (UUID)"), PaginationQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "One page of logins", body = crate::openapi::LoginPage),
+        (status = 400, description = "Malformed id or paging values", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Id is not the caller's", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn list_logins(
+    auth: AuthenticatedUser,              // Reject the request with 401 unless a valid token is supplied
+    user_id: ValidatedUuid,               // Extract the user id from the URL, 400 if it is not a UUID
+    query: web::Query<PaginationQuery>,   // Extract `limit` and `offset` from the query string
+    page_size: web::Data<PageSize>,       // Inject the default and largest page sizes
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // 🛡️ A login history is only shown to its owner
+    let user_id = user_id.to_string();
+    if auth.user_id != user_id {
+        return Err(AppError::Forbidden("forbidden".to_string()));
+    }
+
+    // 🔍 Reject out-of-range paging values
+    query.validate_with(**page_size)?;
+    let limit = query.limit(**page_size);
+    let offset = query.offset();
+
+    let logins = users.list_logins(&user_id, limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "logins": logins,
+        "limit": limit,
+        "offset": offset
+    })))
+}
+
+/// Handler listing the caller's live access tokens (not expired, not revoked), newest first
+#[utoipa::path(
+    get, path = "/users/me/sessions", tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The caller's sessions", body = crate::openapi::SessionList),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn list_sessions(
+    auth: AuthenticatedUser,              // Reject the request with 401 unless a valid token is supplied
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    let mut sessions = users.list_sessions(&auth.user_id, Utc::now()).await?;
+
+    // 📍 Mark the token this request came with, so clients don't offer to revoke it by accident
+    for session in &mut sessions {
+        session.current = session.jti == auth.jti;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "sessions": sessions })))
+}
+
+/// Handler revoking one of the caller's live access tokens
+#[utoipa::path(
+    delete, path = "/users/me/sessions/{jti}", tag = "users",
+    params(("jti" = String, Path, description = "Token id (UUID) from `GET /users/me/sessions`")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 400, description = "Malformed token id", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "No live session of the caller's with that id", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn revoke_session(
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,              // Reject the request with 401 unless a valid token is supplied
+    jti: ValidatedUuid,                   // Extract the token id from the URL, 400 if it is not a UUID
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // 🛡️ Only the caller's own sessions can be looked up, so other users' token ids are a 404 too
+    let session = users
+        .find_session(&auth.user_id, &jti.to_string(), Utc::now())
+        .await?
+        .ok_or_else(|| AppError::NotFound("session not found".to_string()))?;
+
+    // 🚪 Same revocation as `POST /logout`, kept until the token would have expired anyway
+    users.revoke_token(&session.jti, session.expires_at).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,16 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+// Import the driver-agnostic connection pool
+use crate::db::DbPool;
+
+// Import the shared Prometheus collectors
+use crate::metrics::Metrics;
+
+/// Handler for Prometheus scrapes (no auth required)
+pub async fn metrics(metrics: web::Data<Metrics>, db: web::Data<DbPool>) -> HttpResponse {
+    // 📊 Render every collector in the text exposition format
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render(&db))
+}
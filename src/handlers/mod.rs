@@ -1 +1,14 @@
-pub mod user;
\ No newline at end of file
+pub mod account_deletion;
+pub mod debug;
+pub mod docs;
+pub mod email_change;
+pub mod export;
+pub mod health;
+pub mod import;
+pub mod login_history;
+pub mod metrics;
+pub mod password_reset;
+pub mod two_factor;
+pub mod user;
+pub mod verification;
+pub mod version;
\ No newline at end of file
@@ -0,0 +1,73 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+// Import the `Validate` trait for input validation
+use validator::Validate;
+
+use chrono::{Duration, Utc};
+
+use crate::error::AppError;
+use crate::extractors::WritesAllowed;
+use crate::mailer::{spawn_email, AccountEmail, Mailer};
+use crate::models::user::{normalize_email, PasswordResetConfirm, PasswordResetRequest};
+use crate::password::PasswordHasher;
+use crate::repository::UserRepository;
+use crate::tokens::generate_token;
+
+/// How long a password reset link stays valid
+const RESET_TOKEN_TTL_HOURS: i64 = 1;
+
+/// Handler to start a password reset for the given email
+pub async fn request_password_reset(
+    _writes: WritesAllowed,                // 503 while MAINTENANCE_MODE is on
+    body: web::Json<PasswordResetRequest>, // Deserialize the email to reset
+    users: web::Data<dyn UserRepository>,  // Inject the user storage
+    mailer: Option<web::Data<Mailer>>,     // Inject the SMTP mailer, if SMTP_URL is set
+) -> Result<HttpResponse, AppError> {
+    let email = normalize_email(&body.email);
+
+    // 🔍 Look up the account; a missing one is not reported to avoid user enumeration
+    if let Some(user) = users.find_by_email(&email).await? {
+        // 🎟️ Issue a one-time reset token that expires after an hour; only the newest one works
+        let token = generate_token();
+        let now = Utc::now();
+        users
+            .replace_password_reset(&user.id, &token, now + Duration::hours(RESET_TOKEN_TTL_HOURS), now)
+            .await?;
+
+        // Also logged so the flow can be completed on setups without SMTP_URL
+        tracing::debug!(user_id = %user.id, token = %token, "Password reset token issued");
+
+        spawn_email(
+            mailer.map(|mailer| mailer.into_inner()),
+            user.id,
+            AccountEmail::PasswordReset { email: user.email, token },
+        );
+    }
+
+    // 📤 Same response whether or not the email exists
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "If the email is registered, a reset link has been sent"
+    })))
+}
+
+/// Handler to set a new password using a reset token
+pub async fn confirm_password_reset(
+    _writes: WritesAllowed,                // 503 while MAINTENANCE_MODE is on
+    body: web::Json<PasswordResetConfirm>, // Deserialize the token and new password
+    users: web::Data<dyn UserRepository>,  // Inject the user storage
+    hasher: web::Data<PasswordHasher>,     // Inject the shared Argon2 hasher
+) -> Result<HttpResponse, AppError> {
+    // 🔍 Enforce the same password rules as registration
+    body.validate()?;
+
+    // 🔒 Hash the new password, then store it if the token is still valid
+    let hashed_password = hasher.hash(&body.new_password)?;
+
+    // 🗑️ Tokens are single-use: a successful reset consumes every outstanding one for the user
+    if !users.reset_password(&body.token, &hashed_password, Utc::now()).await? {
+        return Err(AppError::BadRequest("invalid or expired token".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Password updated successfully" })))
+}
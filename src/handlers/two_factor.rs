@@ -0,0 +1,103 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+use crate::auth::AuthenticatedUser;
+use crate::error::AppError;
+use crate::extractors::WritesAllowed;
+use crate::models::user::TotpCodeRequest;
+use crate::repository::UserRepository;
+use crate::totp::{self, TotpCipher};
+
+/// Handler to start two-factor setup: generates a secret for the caller's
+/// authenticator app, which only takes effect once `POST /2fa/confirm` succeeds
+#[utoipa::path(
+    post, path = "/2fa/enable", tag = "auth",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Secret and otpauth:// URI to load into an authenticator", body = crate::openapi::TotpSetupResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "Two-factor login is not configured on this server", body = crate::openapi::ErrorResponse),
+        (status = 409, description = "Two-factor login is already enabled", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn enable_two_factor(
+    _writes: WritesAllowed,                 // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,                // Reject the request with 401 unless a valid token is supplied
+    users: web::Data<dyn UserRepository>,   // Inject the user storage
+    cipher: Option<web::Data<TotpCipher>>,  // Inject the secret encryption key, if TOTP_ENCRYPTION_KEY is set
+) -> Result<HttpResponse, AppError> {
+    let cipher = cipher.ok_or_else(|| AppError::NotFound("two-factor authentication is not available".to_string()))?;
+
+    let user = users
+        .find_credentials_by_id(&auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("user not found".to_string()))?;
+
+    if user.totp_enabled {
+        return Err(AppError::Conflict("two-factor authentication is already enabled".to_string()));
+    }
+
+    // 🔑 A fresh secret each time, so restarting setup discards an unconfirmed one
+    let secret = cipher.generate_secret().map_err(AppError::Internal)?;
+    let encrypted = cipher.encrypt(&user.id, &secret).map_err(AppError::Internal)?;
+    let generator = totp::totp(secret, &user.email).map_err(AppError::Internal)?;
+
+    // 🚫 Lost a race with a concurrent confirmation
+    if !users.start_totp_setup(&user.id, &encrypted).await? {
+        return Err(AppError::Conflict("two-factor authentication is already enabled".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "secret": generator.get_secret_base32(),
+        "otpauth_uri": generator.get_url()
+    })))
+}
+
+/// Handler to switch two-factor login on by proving the authenticator app
+/// produces the right codes
+#[utoipa::path(
+    post, path = "/2fa/confirm", tag = "auth",
+    request_body = TotpCodeRequest,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Two-factor login enabled", body = crate::openapi::MessageResponse),
+        (status = 400, description = "Setup was not started", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing token or wrong code", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "Two-factor login is not configured on this server", body = crate::openapi::ErrorResponse),
+        (status = 409, description = "Two-factor login is already enabled", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn confirm_two_factor(
+    _writes: WritesAllowed,                 // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,                // Reject the request with 401 unless a valid token is supplied
+    body: web::Json<TotpCodeRequest>,       // Deserialize the code shown by the authenticator
+    users: web::Data<dyn UserRepository>,   // Inject the user storage
+    cipher: Option<web::Data<TotpCipher>>,  // Inject the secret encryption key, if TOTP_ENCRYPTION_KEY is set
+) -> Result<HttpResponse, AppError> {
+    let cipher = cipher.ok_or_else(|| AppError::NotFound("two-factor authentication is not available".to_string()))?;
+
+    let user = users
+        .find_credentials_by_id(&auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("user not found".to_string()))?;
+
+    if user.totp_enabled {
+        return Err(AppError::Conflict("two-factor authentication is already enabled".to_string()));
+    }
+    let Some(stored) = &user.totp_secret else {
+        return Err(AppError::BadRequest("start two-factor setup with POST /2fa/enable first".to_string()));
+    };
+
+    // ✅ Only enable once the app is known to be in sync, or the user would be locked out
+    if !totp::verify_code(&cipher, &user.id, &user.email, stored, &body.code).map_err(AppError::Internal)? {
+        return Err(AppError::Unauthorized("invalid two-factor code".to_string()));
+    }
+
+    if !users.enable_totp(&user.id).await? {
+        return Err(AppError::NotFound("user not found".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Two-factor authentication enabled" })))
+}
@@ -1,121 +1,920 @@
 // Import necessary modules from Actix-Web
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{http::{header, StatusCode}, web, HttpRequest, HttpResponse};
 
-// Import MySQL connection pool from SQLx
-use sqlx::MySqlPool;
 
 // Import UUID generator for user IDs
 use uuid::Uuid;
 
-// Import Argon2 for password hashing and verification
-use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
-
-// Import helper for generating random salt
-use password_hash::SaltString;
-use rand::rngs::OsRng; // OS secure random number generator
-
 // Import the `Validate` trait for input validation
 use validator::Validate;
 
 // Import application-level models
-use crate::models::user::{RegisterRequest, User, LoginRequest};
+use crate::models::idempotency::{request_hash, StoredResponse};
+use crate::models::login_history::MAX_USER_AGENT_LEN;
+use crate::models::pagination::{link_header, CursorQuery, PageSize, PaginationQuery, UserCursor};
+use crate::models::user::{normalize_email, AccountStatus, normalize_name, normalize_username, MAX_PASSWORD_LEN, ChangePasswordRequest, FieldsQuery, NewUser, RegisterRequest, FilterUsersQuery, SearchUsersQuery, SetStatusRequest, SortUsersQuery, UpdateUserRequest, User, UserFilter, UserSort, LoginRequest};
+
+// Import the proxy-aware client address lookup
+use crate::client_ip::client_ip;
+
+// Import the JWT settings used to issue access tokens
+use crate::jwt::JwtConfig;
+
+// Import the extractor that guards authenticated routes
+use crate::auth::{Admin, AuthenticatedUser, RequireRole};
+
+// Import the extractor that rejects malformed id path params
+use crate::extractors::{IdempotencyKey, ValidatedJson, ValidatedUuid, WritesAllowed};
+
+// Import database error helpers and the transient-error retry settings
+use crate::db::{self, RetryPolicy};
+
+// Import the unified application error type
+use crate::error::AppError;
+
+// Import the optional disposable email domain blocklist and corporate allowlist
+use crate::blocklist::{DomainAllowlist, DomainBlocklist};
+
+// Import the optional HaveIBeenPwned lookup for breached passwords
+use crate::breach::BreachChecker;
+
+// Import the optional SMTP mailer for the welcome email
+use crate::mailer::{spawn_email, AccountEmail, Mailer};
+
+// Import the soft/hard delete switch
+use crate::config::DeleteMode;
+
+// Import the failed-login lockout settings
+use crate::lockout::LockoutPolicy;
+
+// Import the shared Argon2 password hasher
+use crate::password::PasswordHasher;
+
+// Import the storage abstraction the handlers run their queries through
+use crate::repository::UserRepository;
+
+// Import the TOTP code check for two-factor logins
+use crate::totp::{self, TotpCipher};
+
+// Import the one-time token generator and how long verification links last
+use crate::handlers::verification::VERIFICATION_TOKEN_TTL_HOURS;
+use crate::tokens::generate_token;
+
+// Import chrono for token expiry timestamps
+use chrono::{DateTime, Duration, Utc};
+
+/// How long a registration response is replayed for its `Idempotency-Key`
+const IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
+
+/// 400 for a PUT or PATCH body that tries to set `email`, which must go through verification
+fn reject_email_update(update: &UpdateUserRequest) -> Result<(), AppError> {
+    if update.email.is_some() {
+        return Err(AppError::BadRequest("email can't be changed here; use POST /users/{id}/email".to_string()));
+    }
+    Ok(())
+}
 
 /// Handler for user registration
+#[utoipa::path(
+    post, path = "/register", tag = "auth",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the first successful response to the same client and body for 24 hours")),
+    request_body = RegisterRequest,
+    responses(
+        (status = 201, description = "Account created; verify the email before logging in", body = User),
+        (status = 400, description = "Invalid fields, disposable email or breached password", body = crate::openapi::ValidationErrorResponse),
+        (status = 403, description = "Email domain not in ALLOWED_EMAIL_DOMAINS", body = crate::openapi::ErrorResponse),
+        (status = 409, description = "Email or username taken", body = crate::openapi::ErrorResponse),
+        (status = 422, description = "Idempotency-Key already used with a different body", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+#[allow(clippy::too_many_arguments)] // Each dependency is its own actix extractor
 pub async fn register_user(
-    user: web::Json<RegisterRequest>, // Deserialize and extract the request JSON into a validated RegisterRequest struct
-    db: web::Data<MySqlPool>,         // Inject the SQLx MySQL connection pool
-) -> impl Responder {
-    // 🔍 Validate user input using the validator crate
-    if let Err(validation_errors) = user.validate() {
-        // If validation fails, serialize the errors into JSON and return a 400 Bad Request
-        let error_json = serde_json::to_value(&validation_errors).unwrap();
-        return HttpResponse::BadRequest().json(error_json);
+    req: HttpRequest,                     // Access the client address the Idempotency-Key belongs to
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    user: ValidatedJson<RegisterRequest>, // Deserialize, normalize and validate the body (400 with the field errors on failure)
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+    hasher: web::Data<PasswordHasher>,    // Inject the shared Argon2 hasher
+    blocklist: Option<web::Data<DomainBlocklist>>, // Inject the disposable domain list, if one is configured
+    allowlist: Option<web::Data<DomainAllowlist>>, // Inject the only domains that may register, if configured
+    breaches: Option<web::Data<BreachChecker>>,    // Inject the breached password lookup, if HIBP_CHECK_ENABLED is on
+    retry: web::Data<RetryPolicy>,        // Inject how often to retry the insert on a deadlock
+    idempotency_key: IdempotencyKey,      // Optional `Idempotency-Key` header making retries safe
+    mailer: Option<web::Data<Mailer>>,    // Inject the SMTP mailer, if SMTP_URL is set
+) -> Result<HttpResponse, AppError> {
+    // 🔑 Keys are only unique per client, and only stand for the body first sent with them;
+    // the password is left out so nothing derived from it is stored
+    let client = client_ip(&req).map(|ip| ip.to_string()).unwrap_or_default();
+    let body_hash = request_hash(&serde_json::json!({
+        "name": user.name,
+        "email": user.email,
+        "username": user.username,
+        "phone": user.phone,
+    }));
+
+    // 🔁 A retry of a registration that already succeeded gets the original response back
+    if let Some(key) = &idempotency_key.0
+        && let Some(stored) = users.find_idempotent_response(&client, key, Utc::now()).await?
+    {
+        if stored.request_hash != body_hash {
+            return Err(AppError::UnprocessableEntity(
+                "Idempotency-Key was already used with a different request body".to_string(),
+            ));
+        }
+        return Ok(replay(stored));
+    }
+
+    // 🏢 Only the listed domains may register when an allowlist is configured
+    if allowlist.is_some_and(|allowlist| !allowlist.is_allowed(&user.email)) {
+        return Err(AppError::Forbidden("email domain not allowed".to_string()));
+    }
+
+    // 🗑️ Refuse throwaway inboxes when a blocklist is configured
+    if blocklist.is_some_and(|blocklist| blocklist.is_blocked(&user.email)) {
+        return Err(AppError::BadRequest("disposable email not allowed".to_string()));
+    }
+
+    // 🚫 Skip the Argon2 work when the email or username is obviously taken
+    if users.email_exists(&user.email).await? {
+        return Err(AppError::Conflict("email already registered".to_string()));
+    }
+    if users.username_exists(&user.username).await? {
+        return Err(AppError::Conflict("username already taken".to_string()));
+    }
+
+    // 🕳️ Refuse passwords already leaked elsewhere; an unreachable API lets them through
+    if let Some(breaches) = &breaches
+        && breaches.check(&user.password).await
+    {
+        return Err(AppError::BadRequest("password found in data breaches".to_string()));
     }
 
     // ✅ Generate a new UUID for the user
     let user_id = Uuid::new_v4();
 
-    // 🔐 Generate a random salt for password hashing
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default(); // Use default Argon2 parameters
-
-    // 🔒 Hash the user's password using Argon2 and the generated salt
-    let hashed_password = argon2
-        .hash_password(user.password.as_bytes(), &salt)
-        .unwrap()
-        .to_string(); // Convert the hash to a string to store in DB
-
-    // 🛢️ Insert the new user into the database
-    let result = sqlx::query("INSERT INTO users (id, name, email, password) VALUES (?, ?, ?, ?)")
-        .bind(user_id.to_string())  // Bind UUID
-        .bind(&user.name)           // Bind name
-        .bind(&user.email)          // Bind email
-        .bind(&hashed_password)     // Bind hashed password
-        .execute(db.get_ref())      // Execute query using DB connection
-        .await;
-
-    // 📤 Return response based on result
-    match result {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "message": "User registered successfully" })),
-        Err(e) => {
-            eprintln!("Error inserting user: {}", e); // Log error
-            HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Something went wrong" }))
+    // 🔒 Hash the user's password using Argon2 and a random salt
+    let hashed_password = hasher.hash(&user.password)?;
+
+    // ✉️ Issue an email verification token that expires after 24 hours
+    let token = generate_token();
+
+    let new_user = NewUser {
+        id: user_id.to_string(),
+        name: user.name.clone(),
+        email: user.email.clone(),
+        username: user.username.clone(),
+        phone: user.phone.clone(),
+        password_hash: hashed_password,
+    };
+    let expires_at = Utc::now() + Duration::hours(VERIFICATION_TOKEN_TTL_HOURS);
+
+    // 🛢️ Insert the user and its token atomically, so a failure can't leave an unverifiable account;
+    // a deadlocked transaction rolled back entirely, so it is safe to run again
+    let created = db::with_retry(**retry, || users.create_with_verification(&new_user, &token, expires_at))
+        .await
+        .map_err(|e| {
+            // 🚫 Backstop for a registration racing the checks above
+            if db::is_duplicate_entry(&e) {
+                AppError::Conflict("email or username already registered".to_string())
+            } else {
+                AppError::Database(e)
+            }
+        })?;
+
+    // Also logged so accounts can be verified on setups without SMTP_URL
+    tracing::debug!(user_id = %created.id, token = %token, "Email verification token issued");
+
+    // 📧 Welcome the new user with the verification link, without holding the response for SMTP;
+    // a failed send doesn't undo the signup
+    spawn_email(
+        mailer.map(|mailer| mailer.into_inner()),
+        created.id.clone(),
+        AccountEmail::Welcome { name: created.name.clone(), email: created.email.clone(), token },
+    );
+
+    let location = format!("/users/{}", created.id);
+
+    // 💾 Remember the response for retries with the same key; failing to is not worth failing the signup
+    if let Some(key) = &idempotency_key.0 {
+        let stored = StoredResponse {
+            request_hash: body_hash,
+            status_code: StatusCode::CREATED.as_u16().into(),
+            response_body: serde_json::to_string(&created)
+                .map_err(|e| AppError::Internal(format!("Error serializing user: {}", e)))?,
+            location: Some(location.clone()),
+        };
+        let now = Utc::now();
+        if let Err(e) = users
+            .save_idempotent_response(&client, key, &stored, now, now + Duration::hours(IDEMPOTENCY_KEY_TTL_HOURS))
+            .await
+        {
+            tracing::warn!(error = %e, "Failed to save idempotent registration response");
         }
     }
+
+    // 📤 Return the created user (never the password hash) with its location
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, location))
+        .json(created))
+}
+
+/// Rebuild a response saved under an `Idempotency-Key`
+fn replay(stored: StoredResponse) -> HttpResponse {
+    let status = u16::try_from(stored.status_code)
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .unwrap_or(StatusCode::OK);
+
+    let mut response = HttpResponse::build(status);
+    response.insert_header(("Idempotent-Replayed", "true"));
+    if let Some(location) = stored.location {
+        response.insert_header((header::LOCATION, location));
+    }
+
+    response.content_type("application/json").body(stored.response_body)
 }
 
 /// Handler for user login
+#[utoipa::path(
+    post, path = "/login", tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Access token", body = crate::openapi::TokenResponse),
+        (status = 400, description = "Password too long", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Invalid credentials, or a missing or wrong two-factor code", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Email not verified or account suspended", body = crate::openapi::ErrorResponse),
+        (status = 423, description = "Account temporarily locked", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+#[allow(clippy::too_many_arguments)] // Each dependency is its own actix extractor
 pub async fn login_user(
-    user: web::Json<LoginRequest>, // Deserialize JSON payload into LoginRequest
-    db: web::Data<MySqlPool>,      // Inject SQLx connection pool
-) -> impl Responder {
-    let email = &user.email;
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on; a login writes its history
+    req: HttpRequest,                     // Read the client address and User-Agent for the login history
+    user: web::Json<LoginRequest>,        // Deserialize JSON payload into LoginRequest
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+    lockout: web::Data<LockoutPolicy>,    // Inject the failed-login lockout policy
+    hasher: web::Data<PasswordHasher>,    // Inject the shared Argon2 hasher
+    jwt: web::Data<JwtConfig>,            // Inject the token signing settings
+    totp: Option<web::Data<TotpCipher>>,  // Inject the TOTP secret key, if TOTP_ENCRYPTION_KEY is set
+) -> Result<HttpResponse, AppError> {
     let password = &user.password;
+    let totp_code = user.totp_code.as_deref();
 
-    // 🔍 Query user by email (and fetch password hash)
-    let result = sqlx::query_as::<_, LoginRequest>(
-        "SELECT id, name, email, password FROM users WHERE email = ?"
-    )
-        .bind(email)
-        .fetch_one(db.get_ref())
-        .await;
-
-    // 🎯 Handle DB result
-    match result {
-        Ok(user) => {
-            // 🔐 Parse stored password hash string into PasswordHash
-            let parsed_hash = PasswordHash::new(&user.password).unwrap();
-
-            // ✅ Verify input password against stored hash
-            if Argon2::default()
-                .verify_password(password.as_bytes(), &parsed_hash)
-                .is_ok()
-            {
-                HttpResponse::Ok().json(serde_json::json!({ "message": "Login successful" }))
-            } else {
-                HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Invalid password" }))
-            }
+    // 📏 Refuse oversized passwords before spending any Argon2 work on them
+    if password.chars().count() > MAX_PASSWORD_LEN {
+        return Err(AppError::BadRequest(format!(
+            "Password must be at most {} characters long",
+            MAX_PASSWORD_LEN
+        )));
+    }
+
+    // 🔍 Usernames are alphanumeric, so an `@` means the identifier is an email
+    let user = if user.identifier.contains('@') {
+        users.find_by_email(&normalize_email(&user.identifier)).await?
+    } else {
+        users.find_by_username(&normalize_username(&user.identifier)).await?
+    };
+
+    // ⏱️ Unknown identifiers get the same 401 and still pay for an Argon2 verification so timing doesn't reveal them
+    let Some(user) = user else {
+        hasher.dummy_verify(password);
+        return Err(AppError::Unauthorized("invalid credentials".to_string()));
+    };
+
+    // 🔒 Refuse logins while the account is locked out
+    let now = Utc::now();
+    if user.locked_until.is_some_and(|until| until > now) {
+        return Err(AppError::Locked("account temporarily locked".to_string()));
+    }
+
+    // ✅ Verify input password against stored hash
+    if !hasher.verify(password, &user.password)? {
+        // 📈 Count the failure and lock the account once the threshold is reached
+        users
+            .record_failed_login(&user.id, lockout.max_failed_attempts, now + lockout.lock_duration)
+            .await?;
+
+        return Err(AppError::Unauthorized("invalid credentials".to_string()));
+    }
+
+    // 📧 Only accounts with a confirmed email may log in; refused before anything counts as a success
+    if !user.verified {
+        return Err(AppError::Forbidden("email not verified".to_string()));
+    }
+
+    // ⛔ Suspended accounts are only told so once they've proven the password
+    if user.status == AccountStatus::Suspended.as_str() {
+        return Err(AppError::Forbidden("account suspended".to_string()));
+    }
+
+    // 🔑 Accounts with two-factor login also need the authenticator's current code
+    if user.totp_enabled {
+        let (Some(cipher), Some(stored)) = (totp.as_deref(), user.totp_secret.as_deref()) else {
+            return Err(AppError::Internal("two-factor login is enabled but TOTP_ENCRYPTION_KEY is not set".to_string()));
+        };
+        let Some(code) = totp_code else {
+            return Err(AppError::Unauthorized("two-factor code required".to_string()));
+        };
+
+        // 📈 A wrong code counts towards the lockout, so the 6 digits can't be brute-forced
+        if !totp::verify_code(cipher, &user.id, &user.email, stored, code).map_err(AppError::Internal)? {
+            users
+                .record_failed_login(&user.id, lockout.max_failed_attempts, now + lockout.lock_duration)
+                .await?;
+
+            return Err(AppError::Unauthorized("invalid two-factor code".to_string()));
         }
-        Err(e) => {
-            eprintln!("Error fetching user: {}", e); // Log error
-            HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Login failed" }))
+    }
+
+    // ♻️ Upgrade hashes made under older ARGON2_* settings while the plaintext is at hand;
+    // the login goes ahead with the old hash if a new one can't be made
+    let new_hash = if hasher.needs_rehash(&user.password) {
+        hasher
+            .hash(password)
+            .inspect_err(|e| tracing::warn!(user_id = %user.id, error = %e, "Failed to upgrade the password hash"))
+            .ok()
+    } else {
+        None
+    };
+
+    // 🔓 The right password clears the failure counter and records the activity,
+    // together with the upgraded hash so neither write lands without the other
+    match new_hash {
+        Some(new_hash) => {
+            if users.record_successful_login_with_rehash(&user.id, now, &user.password, &new_hash).await? {
+                tracing::info!(user_id = %user.id, "Password hash upgraded to the current parameters");
+            }
         }
+        None => users.record_successful_login(&user.id, now).await?,
     }
+
+    // 🎟️ Issue a signed access token for the authenticated user
+    let (token, claims) = jwt
+        .encode_token(&user.id, &user.email, &user.role)
+        .map_err(|e| AppError::Internal(format!("Error signing token: {}", e)))?;
+
+    // 🕵️ Keep where the login came from so the owner can spot ones they don't recognize
+    let ip = client_ip(&req).map(|ip| ip.to_string());
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|agent| agent.chars().take(MAX_USER_AGENT_LEN).collect::<String>());
+    let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or(DateTime::<Utc>::MAX_UTC);
+    users
+        .record_login(&user.id, &claims.jti, ip.as_deref(), user_agent.as_deref(), now, expires_at)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "token": token,
+        "expires_in": jwt.expiry_secs()
+    })))
+}
+
+/// Handler to revoke the caller's access token
+#[utoipa::path(
+    post, path = "/logout", tag = "auth",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Token revoked", body = crate::openapi::MessageResponse),
+        (status = 401, description = "Missing, invalid or revoked token", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn logout_user(
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,              // Reject the request with 401 unless a valid token is supplied
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // 🚪 Remember the token id until the token would have expired on its own
+    users.revoke_token(&auth.jti, auth.expires_at).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "logged out" })))
 }
 
-/// Handler to fetch all users (for admin/debug purposes)
-pub async fn get_users(db: web::Data<MySqlPool>) -> impl Responder {
-    // 🧾 Query all users (omit password for security)
-    let users = sqlx::query_as::<_, User>("SELECT id, name, email FROM users")
-        .fetch_all(db.get_ref())
-        .await;
+/// Handler to fetch a page of users (admins only)
+#[utoipa::path(
+    get, path = "/users", tag = "users",
+    params(PaginationQuery, SortUsersQuery, FilterUsersQuery, FieldsQuery, CursorQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "One page of users, each cut to `fields` if given; \
+            a `UserCursorPage` without the headers when `cursor` is sent", body = crate::openapi::UserPage,
+            headers(
+                ("Link" = String, description = "`first`, `prev`, `next` and `last` page URLs; `prev`/`next` only when such a page exists"),
+                ("X-Total-Count" = i64, description = "Number of live users matching the filters, as in `total`"),
+            )),
+        (status = 400, description = "Invalid paging, sort, filter, fields or cursor parameters", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = crate::openapi::ErrorResponse),
+    )
+)]
+#[allow(clippy::too_many_arguments)] // Each dependency is its own actix extractor
+pub async fn get_users(
+    req: HttpRequest,                     // Read the path the page links are built from
+    _admin: RequireRole<Admin>,           // 401 without a valid token, 403 unless the caller is an admin
+    query: web::Query<PaginationQuery>,   // Extract `limit` and `offset` from the query string
+    order: web::Query<SortUsersQuery>,    // Extract `sort` from the same query string
+    filter: web::Query<FilterUsersQuery>, // Extract the optional `verified` and `status` filters from the same query string
+    fields: web::Query<FieldsQuery>,      // Extract the optional `fields` list from the same query string
+    cursor: web::Query<CursorQuery>,      // Extract the optional `cursor` from the same query string
+    page_size: web::Data<PageSize>,       // Inject the default and largest page sizes
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // 🔍 Validate the pagination parameters
+    query.validate_with(**page_size)?;
+    let limit = query.limit(**page_size);
+    let offset = query.offset();
+    let fields = fields.parse().map_err(AppError::BadRequest)?;
+    let filter = filter.parse().map_err(AppError::BadRequest)?;
 
-    // 📤 Return users in JSON or error
-    match users {
-        Ok(rows) => HttpResponse::Ok().json(rows),
-        Err(e) => {
-            eprintln!("Error fetching users: {}", e); // Log error
-            HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Could not fetch users" }))
+    // 🧭 A cursor walks the users oldest first, so it can't be mixed with offsets or other orders
+    if let Some(cursor) = &cursor.cursor {
+        if query.offset.is_some() || order.sort.is_some() {
+            return Err(AppError::BadRequest("cursor cannot be combined with offset or sort".to_string()));
         }
+        return users_after_cursor(&filter, cursor, limit, fields.as_deref(), &users).await;
+    }
+
+    // ↕️ Only whitelisted sort keys are accepted; newest first by default
+    let sort = match order.sort.as_deref() {
+        None => UserSort::default(),
+        Some(key) => UserSort::parse(key).ok_or_else(|| {
+            AppError::BadRequest("sort must be one of name, -name, created_at, -created_at".to_string())
+        })?,
+    };
+
+    // 🔢 Count the matching users so clients can build pagers
+    let total = users.count(&filter).await?;
+
+    // 🧾 Query one page of matching users (omit password for security)
+    let page = users.list(&filter, limit, offset, sort).await?;
+
+    // 📤 Return users in JSON, trimmed to the requested fields
+    let page = match &fields {
+        Some(fields) => serde_json::json!(page.iter().map(|user| user.select(fields)).collect::<Vec<_>>()),
+        None => serde_json::json!(page),
+    };
+
+    // 🔗 Page links keep the sort, filters and fields, rebuilt from the validated values
+    let mut base = req.path().to_string();
+    let params: Vec<String> = order
+        .sort
+        .iter()
+        .map(|sort| format!("sort={}", sort))
+        .chain(filter.query_params())
+        .chain(fields.iter().map(|fields| format!("fields={}", fields.join(","))))
+        .collect();
+    if !params.is_empty() {
+        base = format!("{}?{}", base, params.join("&"));
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::LINK, link_header(&base, limit, offset, total)))
+        .insert_header(("X-Total-Count", total.to_string()))
+        .json(serde_json::json!({
+            "users": page,
+            "total": total,
+            "limit": limit,
+            "offset": offset
+        })))
+}
+
+/// One cursor page of `get_users`; an empty `cursor` starts from the oldest user
+async fn users_after_cursor(
+    filter: &UserFilter,
+    cursor: &str,
+    limit: i64,
+    fields: Option<&[&'static str]>,
+    users: &web::Data<dyn UserRepository>,
+) -> Result<HttpResponse, AppError> {
+    // 🔓 Anything but a cursor from `next_cursor` is rejected rather than guessed at
+    let cursor = match cursor {
+        "" => None,
+        cursor => Some(UserCursor::decode(cursor).ok_or_else(|| AppError::BadRequest("invalid cursor".to_string()))?),
+    };
+
+    // 🧾 One extra row tells whether another page follows, without counting the table
+    let mut page = match &cursor {
+        Some(cursor) => users.list_after_cursor(filter, cursor, limit + 1).await?,
+        None => users.list(filter, limit + 1, 0, UserSort::CreatedAtAsc).await?,
+    };
+    let next_cursor = if page.len() as i64 > limit {
+        page.truncate(limit as usize);
+        page.last().map(|user| UserCursor { created_at: user.created_at, id: user.id.clone() }.encode())
+    } else {
+        None
+    };
+
+    let page = match fields {
+        Some(fields) => serde_json::json!(page.iter().map(|user| user.select(fields)).collect::<Vec<_>>()),
+        None => serde_json::json!(page),
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "users": page,
+        "limit": limit,
+        "next_cursor": next_cursor
+    })))
+}
+
+/// Handler to count users without paging through them (admins only)
+#[utoipa::path(
+    get, path = "/users/count", tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Number of live users", body = crate::openapi::CountResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn count_users(
+    _admin: RequireRole<Admin>,           // 401 without a valid token, 403 unless the caller is an admin
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // 🔢 Soft-deleted users are not counted
+    let count = users.count(&UserFilter::default()).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "count": count })))
+}
+
+/// Handler to search users by name or email, paginated like `get_users`
+#[utoipa::path(
+    get, path = "/users/search", tag = "users",
+    params(SearchUsersQuery, PaginationQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "One page of matching users", body = crate::openapi::UserPage),
+        (status = 400, description = "Missing search term or invalid paging", body = crate::openapi::ValidationErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn search_users(
+    _auth: AuthenticatedUser,               // Reject the request with 401 unless a valid token is supplied
+    search: web::Query<SearchUsersQuery>,   // Extract `q` from the query string
+    query: web::Query<PaginationQuery>,     // Extract `limit` and `offset` from the same query string
+    page_size: web::Data<PageSize>,         // Inject the default and largest page sizes
+    users: web::Data<dyn UserRepository>,   // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // 🔍 Validate the search term and pagination parameters
+    search.validate()?;
+    query.validate_with(**page_size)?;
+    let limit = query.limit(**page_size);
+    let offset = query.offset();
+
+    // 🔢 Count matching users so clients can build pagers
+    let total = users.count_matching(&search.q).await?;
+
+    // 🧾 Query one page of matching users (omit password for security)
+    let page = users.search(&search.q, limit, offset).await?;
+
+    // 📤 Return users in the same shape as `get_users`
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "users": page,
+        "total": total,
+        "limit": limit,
+        "offset": offset
+    })))
+}
+
+/// Handler to fetch the caller's own profile without knowing its id
+#[utoipa::path(
+    get, path = "/users/me", tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The caller's user record", body = User),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "The token's user was deleted", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn get_current_user(
+    auth: AuthenticatedUser,              // Reject the request with 401 unless a valid token is supplied
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // 🧾 Same lookup as `get_user_by_id`, keyed by the token's subject
+    let user = users
+        .find_by_id(&auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("user not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+/// Handler to fetch a single user by id
+#[utoipa::path(
+    get, path = "/users/{id}", tag = "users",
+    params(("id" = String, Path, description = "User id (UUID)"), FieldsQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The user, cut to `fields` if given", body = User),
+        (status = 400, description = "Malformed id or unknown field", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "No such user", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn get_user_by_id(
+    _auth: AuthenticatedUser,             // Reject the request with 401 unless a valid token is supplied
+    user_id: ValidatedUuid,               // Extract the user id from the URL, 400 if it is not a UUID
+    fields: web::Query<FieldsQuery>,      // Extract the optional `fields` list from the query string
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    let fields = fields.parse().map_err(AppError::BadRequest)?;
+
+    // 🧾 Query the user (omit password for security)
+    let user = users
+        .find_by_id(&user_id.to_string())
+        .await?
+        .ok_or_else(|| AppError::NotFound("user not found".to_string()))?;
+
+    // 📤 Return the user in JSON, trimmed to the requested fields
+    match fields {
+        Some(fields) => Ok(HttpResponse::Ok().json(user.select(&fields))),
+        None => Ok(HttpResponse::Ok().json(user)),
+    }
+}
+
+/// Handler to update a user's name and/or phone
+#[utoipa::path(
+    put, path = "/users/{id}", tag = "users",
+    params(("id" = String, Path, description = "User id (UUID)")),
+    request_body = UpdateUserRequest,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The updated user", body = User),
+        (status = 400, description = "Malformed id, invalid fields or an `email`", body = crate::openapi::ValidationErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Caller is neither this user nor an admin", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "No such user", body = crate::openapi::ErrorResponse),
+        (status = 409, description = "`version` is stale", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn update_user(
+    _writes: WritesAllowed,                 // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,                // Reject the request with 401 unless a valid token is supplied
+    user_id: ValidatedUuid,                 // Extract the user id from the URL, 400 if it is not a UUID
+    mut user: web::Json<UpdateUserRequest>, // Deserialize the JSON body with the fields to change
+    users: web::Data<dyn UserRepository>,   // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    let user_id = user_id.to_string();
+
+    // 🛡️ Users may only edit themselves; admins may edit anyone
+    auth.require_self_or_admin(&user_id)?;
+
+    // ✉️ Emails only change through the verified email-change flow
+    reject_email_update(&user)?;
+
+    // ✂️ Normalize the new name the same way registration does
+    user.name = user.name.as_deref().map(normalize_name);
+
+    // 🔍 Validate the provided fields using the validator crate
+    user.validate()?;
+
+    // 🛢️ Update only the fields that were supplied, keeping the others as they are
+    let updated = users
+        .update(&user_id, user.name.as_deref(), user.phone.as_ref().map(Option::as_deref), user.version)
+        .await?;
+    let updated = updated_or_conflict(updated, &user_id, user.version, &users).await?;
+
+    // 📤 Return the user as stored after the update
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+/// Handler to change only the fields present in the body (`PATCH`)
+#[utoipa::path(
+    patch, path = "/users/{id}", tag = "users",
+    params(("id" = String, Path, description = "User id (UUID)")),
+    request_body = UpdateUserRequest,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The updated user", body = User),
+        (status = 400, description = "Malformed id, empty body, invalid fields or an `email`", body = crate::openapi::ValidationErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Caller is neither this user nor an admin", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "No such user", body = crate::openapi::ErrorResponse),
+        (status = 409, description = "`version` is stale", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn patch_user(
+    _writes: WritesAllowed,                  // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,                 // Reject the request with 401 unless a valid token is supplied
+    user_id: ValidatedUuid,                  // Extract the user id from the URL, 400 if it is not a UUID
+    mut patch: web::Json<UpdateUserRequest>, // Deserialize the fields to change; absent ones stay as they are
+    users: web::Data<dyn UserRepository>,    // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    let user_id = user_id.to_string();
+
+    // 🛡️ Same rule as PUT: only the user themselves or an admin
+    auth.require_self_or_admin(&user_id)?;
+
+    // ✉️ Emails only change through the verified email-change flow
+    reject_email_update(&patch)?;
+
+    // 🚫 A patch has to change something
+    if patch.is_empty() {
+        return Err(AppError::BadRequest("no fields to update".to_string()));
+    }
+
+    // ✂️ Normalize the new name the same way registration does
+    patch.name = patch.name.as_deref().map(normalize_name);
+
+    // 🔍 Validate whichever fields were provided
+    patch.validate()?;
+
+    // 🛢️ Fields left out are kept unchanged; `"phone": null` clears the number
+    let updated = users
+        .update(&user_id, patch.name.as_deref(), patch.phone.as_ref().map(Option::as_deref), patch.version)
+        .await?;
+    let updated = updated_or_conflict(updated, &user_id, patch.version, &users).await?;
+
+    // 📤 Return the user as stored after the patch
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+/// Tell a stale `version` (409) apart from a missing user (404) when an update matched no row
+async fn updated_or_conflict(
+    updated: Option<User>,
+    user_id: &str,
+    version: Option<i32>,
+    users: &web::Data<dyn UserRepository>,
+) -> Result<User, AppError> {
+    if let Some(user) = updated {
+        return Ok(user);
+    }
+
+    if version.is_some() && users.find_by_id(user_id).await?.is_some() {
+        return Err(AppError::Conflict("version mismatch".to_string()));
+    }
+    Err(AppError::NotFound("user not found".to_string()))
+}
+
+/// Handler to delete a user by id (admins only)
+#[utoipa::path(
+    delete, path = "/users/{id}", tag = "users",
+    params(("id" = String, Path, description = "User id (UUID)")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 204, description = "User deleted (soft or hard, per SOFT_DELETE)"),
+        (status = 400, description = "Malformed id", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "No such user", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn delete_user(
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    _admin: RequireRole<Admin>,           // 401 without a valid token, 403 unless the caller is an admin
+    user_id: ValidatedUuid,               // Extract the user id from the URL, 400 if it is not a UUID
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+    mode: web::Data<DeleteMode>,          // Inject whether deletes are soft or hard
+) -> Result<HttpResponse, AppError> {
+    // 🗑️ Either hide the user (restorable) or remove the row for good
+    let deleted = match **mode {
+        DeleteMode::Soft => users.soft_delete(&user_id.to_string(), Utc::now()).await?,
+        DeleteMode::Hard => users.delete(&user_id.to_string()).await?,
+    };
+
+    if !deleted {
+        return Err(AppError::NotFound("user not found".to_string()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler to bring back a soft-deleted user (admins only)
+#[utoipa::path(
+    post, path = "/users/{id}/restore", tag = "users",
+    params(("id" = String, Path, description = "User id (UUID)")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The restored user", body = User),
+        (status = 400, description = "Malformed id", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "No soft-deleted user with this id", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn restore_user(
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    _admin: RequireRole<Admin>,           // 401 without a valid token, 403 unless the caller is an admin
+    user_id: ValidatedUuid,               // Extract the user id from the URL, 400 if it is not a UUID
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // ♻️ Clear `deleted_at`; unknown, live or hard-deleted users are all 404
+    let user = users
+        .restore(&user_id.to_string())
+        .await?
+        .ok_or_else(|| AppError::NotFound("deleted user not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+/// Handler to suspend or reactivate an account without deleting it (admins only)
+#[utoipa::path(
+    post, path = "/users/{id}/status", tag = "users",
+    params(("id" = String, Path, description = "User id (UUID)")),
+    request_body = SetStatusRequest,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The user with its new status", body = User),
+        (status = 400, description = "Malformed id or unknown status", body = crate::openapi::ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "No such user", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+pub async fn set_user_status(
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    _admin: RequireRole<Admin>,           // 401 without a valid token, 403 unless the caller is an admin
+    user_id: ValidatedUuid,               // Extract the user id from the URL, 400 if it is not a UUID
+    body: web::Json<SetStatusRequest>,    // Deserialize the new status, 400 unless `active` or `suspended`
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // ⛔ Suspended users keep their data but can't log in until reactivated
+    let user = users
+        .set_status(&user_id.to_string(), body.status.as_str())
+        .await?
+        .ok_or_else(|| AppError::NotFound("user not found".to_string()))?;
+
+    tracing::info!(user_id = %user.id, status = %user.status, "Account status changed");
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+/// Handler to change the caller's own password after re-checking the current one
+#[utoipa::path(
+    post, path = "/users/{id}/password", tag = "users",
+    params(("id" = String, Path, description = "User id (UUID)")),
+    request_body = ChangePasswordRequest,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Password changed", body = crate::openapi::MessageResponse),
+        (status = 400, description = "Malformed id, or weak or breached new password", body = crate::openapi::ValidationErrorResponse),
+        (status = 401, description = "Missing or invalid token, or the current password is wrong", body = crate::openapi::ErrorResponse),
+        (status = 403, description = "The id is not the caller's", body = crate::openapi::ErrorResponse),
+        (status = 404, description = "The token's user was deleted", body = crate::openapi::ErrorResponse),
+        (status = 423, description = "Account temporarily locked", body = crate::openapi::ErrorResponse),
+        (status = 503, description = "MAINTENANCE_MODE is on", body = crate::openapi::ErrorResponse),
+    )
+)]
+#[allow(clippy::too_many_arguments)] // Each dependency is its own actix extractor
+pub async fn change_password(
+    _writes: WritesAllowed,                  // 503 while MAINTENANCE_MODE is on
+    auth: AuthenticatedUser,                 // Reject the request with 401 unless a valid token is supplied
+    user_id: ValidatedUuid,                  // Extract the user id from the URL, 400 if it is not a UUID
+    body: web::Json<ChangePasswordRequest>,  // Deserialize the old and new passwords
+    users: web::Data<dyn UserRepository>,    // Inject the user storage
+    lockout: web::Data<LockoutPolicy>,       // Inject the failed-login lockout policy
+    hasher: web::Data<PasswordHasher>,       // Inject the shared Argon2 hasher
+    breaches: Option<web::Data<BreachChecker>>, // Inject the breached password lookup, if HIBP_CHECK_ENABLED is on
+) -> Result<HttpResponse, AppError> {
+    // 🛡️ Only the account owner may change its password, not even an admin
+    let user_id = user_id.to_string();
+    if auth.user_id != user_id {
+        return Err(AppError::Forbidden("forbidden".to_string()));
     }
+
+    // 🔍 Enforce the same password rules as registration
+    body.validate()?;
+
+    // 🔍 Fetch the stored password hash
+    let user = users
+        .find_credentials_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("user not found".to_string()))?;
+
+    // 🔒 A locked account can't be guessed at through this endpoint either
+    let now = Utc::now();
+    if user.locked_until.is_some_and(|until| until > now) {
+        return Err(AppError::Locked("account temporarily locked".to_string()));
+    }
+
+    // ✅ The caller must prove they know the current password
+    if !hasher.verify(&body.old_password, &user.password)? {
+        // 📈 Wrong guesses count towards the same lockout as failed logins
+        users
+            .record_failed_login(&user.id, lockout.max_failed_attempts, now + lockout.lock_duration)
+            .await?;
+
+        return Err(AppError::Unauthorized("Invalid password".to_string()));
+    }
+
+    // 🕳️ Same breach check as registration, after the cheaper checks have passed
+    if let Some(breaches) = &breaches
+        && breaches.check(&body.new_password).await
+    {
+        return Err(AppError::BadRequest("password found in data breaches".to_string()));
+    }
+
+    // 🔒 Hash and store the new password
+    let hashed_password = hasher.hash(&body.new_password)?;
+
+    users.update_password(&user.id, &hashed_password).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Password updated successfully" })))
 }
@@ -0,0 +1,64 @@
+// Import necessary modules from Actix-Web
+use actix_web::{web, HttpResponse};
+
+use chrono::{Duration, Utc};
+
+use crate::error::AppError;
+use crate::extractors::WritesAllowed;
+use crate::mailer::{spawn_email, AccountEmail, Mailer};
+use crate::models::user::{normalize_email, ResendVerificationRequest, VerifyEmailQuery};
+use crate::repository::UserRepository;
+use crate::tokens::generate_token;
+
+/// How long an email verification link stays valid
+pub const VERIFICATION_TOKEN_TTL_HOURS: i64 = 24;
+
+/// Handler for the link sent to confirm a new account's email
+pub async fn verify_email(
+    _writes: WritesAllowed,               // 503 while MAINTENANCE_MODE is on
+    query: web::Query<VerifyEmailQuery>,  // Extract `token` from the query string
+    users: web::Data<dyn UserRepository>, // Inject the user storage
+) -> Result<HttpResponse, AppError> {
+    // ✅ Mark the account verified and consume the token, ignoring expired tokens
+    if !users.verify_email(&query.token, Utc::now()).await? {
+        return Err(AppError::BadRequest("invalid or expired token".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Email verified successfully" })))
+}
+
+/// Handler to send a fresh verification link to an unverified account
+pub async fn resend_verification(
+    _writes: WritesAllowed,                     // 503 while MAINTENANCE_MODE is on
+    body: web::Json<ResendVerificationRequest>, // Deserialize the email to verify
+    users: web::Data<dyn UserRepository>,       // Inject the user storage
+    mailer: Option<web::Data<Mailer>>,          // Inject the SMTP mailer, if SMTP_URL is set
+) -> Result<HttpResponse, AppError> {
+    let email = normalize_email(&body.email);
+
+    // 🔍 Only unverified accounts get a link; neither a missing nor a verified one is reported
+    if let Some(user) = users.find_by_email(&email).await?
+        && !user.verified
+    {
+        // 🎟️ The new token replaces every earlier one, so only the latest email works
+        let token = generate_token();
+        let now = Utc::now();
+        users
+            .replace_email_verification(&user.id, &token, now + Duration::hours(VERIFICATION_TOKEN_TTL_HOURS), now)
+            .await?;
+
+        // Also logged so accounts can be verified on setups without SMTP_URL
+        tracing::debug!(user_id = %user.id, token = %token, "Email verification token reissued");
+
+        spawn_email(
+            mailer.map(|mailer| mailer.into_inner()),
+            user.id,
+            AccountEmail::Verification { email: user.email, token },
+        );
+    }
+
+    // 📤 Same response whether or not the email exists or is verified
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "If the email is registered and unverified, a new verification link has been sent"
+    })))
+}
@@ -0,0 +1,12 @@
+// Import necessary modules from Actix-Web
+use actix_web::HttpResponse;
+
+/// Handler reporting which build is running (no auth required)
+pub async fn version() -> HttpResponse {
+    // 🔖 All three values are baked in at compile time (see build.rs)
+    HttpResponse::Ok().json(serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("GIT_SHA"),
+        "build_time": env!("BUILD_TIME"),
+    }))
+}
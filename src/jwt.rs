@@ -0,0 +1,61 @@
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Claims carried inside every access token
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,   // User id
+    pub email: String, // User email
+    pub role: String,  // User role, checked by `RequireRole`
+    pub jti: String,   // Unique token id, recorded on logout to revoke the token
+    pub iat: u64,      // Issued at (unix seconds)
+    pub exp: u64,      // Expires at (unix seconds)
+}
+
+/// Signing keys and token lifetime, built once from `AppConfig` and shared via `web::Data`
+#[derive(Clone)]
+pub struct JwtConfig {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    expiry_secs: u64,
+}
+
+impl JwtConfig {
+    pub fn new(secret: &str, expiry_secs: u64) -> Self {
+        JwtConfig {
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            expiry_secs,
+        }
+    }
+
+    /// Token lifetime in seconds
+    pub fn expiry_secs(&self) -> u64 {
+        self.expiry_secs
+    }
+
+    /// Sign a new access token for the given user, returning it with its claims
+    pub fn encode_token(&self, user_id: &str, email: &str, role: &str) -> Result<(String, Claims), jsonwebtoken::errors::Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System clock is before the unix epoch")
+            .as_secs();
+
+        let claims = Claims {
+            sub: user_id.to_string(),
+            email: email.to_string(),
+            role: role.to_string(),
+            jti: uuid::Uuid::new_v4().to_string(),
+            iat: now,
+            exp: now + self.expiry_secs,
+        };
+
+        encode(&Header::default(), &claims, &self.encoding_key).map(|token| (token, claims))
+    }
+
+    /// Verify a token's signature and expiry and return its claims
+    pub fn decode_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        decode::<Claims>(token, &self.decoding_key, &Validation::default()).map(|data| data.claims)
+    }
+}
@@ -0,0 +1,29 @@
+pub mod app;
+pub mod auth;
+pub mod blocklist;
+pub mod breach;
+pub mod cleanup;
+pub mod cli;
+pub mod client_ip;
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod extractors;
+pub mod handlers;
+pub mod jwt;
+pub mod lockout;
+pub mod mailer;
+pub mod maintenance;
+pub mod metrics;
+pub mod middleware;
+pub mod models;
+pub mod openapi;
+pub mod password;
+pub mod repository;
+pub mod roles;
+pub mod telemetry;
+pub mod tls;
+pub mod tokens;
+pub mod totp;
+#[cfg(unix)]
+pub mod uds;
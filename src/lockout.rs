@@ -0,0 +1,9 @@
+use chrono::Duration;
+
+/// When and for how long an account is locked after repeated failed logins
+/// (`LOCKOUT_THRESHOLD` and `LOCKOUT_DURATION_MINS`)
+#[derive(Debug, Clone, Copy)]
+pub struct LockoutPolicy {
+    pub max_failed_attempts: i32,
+    pub lock_duration: Duration,
+}
@@ -1,44 +1,170 @@
-mod db;
-mod models;
-mod handlers;
-
-use actix_web::{web, App, HttpServer};
+use actix_web::{middleware::{from_fn, Compress}, App, HttpServer};
+use clap::Parser;
 use dotenvy::dotenv;
-use handlers::user::{register_user, get_users, login_user};
-
+use hello_resut_1::cli::{Cli, Command};
+use hello_resut_1::config::AppConfig;
+use hello_resut_1::models::user::RegisterRequest;
+use hello_resut_1::{app, cleanup, db, middleware, roles, telemetry};
+#[cfg(unix)]
+use hello_resut_1::{maintenance, uds};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let cli = Cli::parse(); // Parse arguments first so --help works without any configuration
     dotenv().ok(); // Load environment variables from .env file
+    // Set up structured logging; nothing can be logged before it, so a bad LOG_FORMAT goes to stderr
+    telemetry::init().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+
+    // Read every setting up front so a bad deployment fails before touching the database
+    let config = AppConfig::from_env().unwrap_or_else(|e| {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    });
+
+    if let Some(blocklist) = &config.disposable_domains {
+        tracing::info!(domains = blocklist.len(), "Loaded disposable email domain blocklist");
+    }
+
+    let db_pool = db::connect(&config.database).await; // Connect to the database
+
+    tracing::info!("Connected to the database");
 
-    let db_pool = db::connect().await; // Connect to the database
+    // Apply any pending schema migrations for the connected driver
 
-    println!("Connected to the database");
+    db_pool
+        .migrate()
+        .await
+        .expect("Failed to run database migrations");
+    tracing::info!("Database migrations applied");
 
-    // Create the database table if it doesn't exist in database
+    let state = app::AppState::new(&config, db_pool.clone());
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS users (
-            id VARCHAR(36) PRIMARY KEY,
-            name VARCHAR(255) NOT NULL,
-            email VARCHAR(255) NOT NULL UNIQUE
-        )",
-    )
-    .execute(&db_pool)
-    .await
-    .expect("Failed to create table");
-    println!("Database table created");
+    // 🧰 One-off commands share the configuration and database, then exit without serving
+    if let Some(Command::SeedAdmin { email, password, name, username }) = cli.command {
+        let request = RegisterRequest { name, email, username, phone: None, password };
+        let created = roles::create_admin(state.users.as_ref(), &state.hasher, request)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Failed to seed the admin account: {}", e);
+                std::process::exit(1);
+            });
 
-    println!("Starting server at http://127.0.1:8080");
+        if created {
+            tracing::info!("Admin account created");
+        } else {
+            tracing::info!("An account with this email or username already exists, skipping");
+        }
 
-    HttpServer::new(move || {
+        db_pool.close().await;
+        return Ok(());
+    }
+
+    // Promote ADMIN_EMAIL's account so there is always a way to reach admin-only routes
+    roles::seed_admin(state.users.as_ref(), config.admin_email.as_deref())
+        .await
+        .expect("Failed to seed the admin account");
+
+    // Periodically drop revoked tokens that have expired anyway
+    cleanup::spawn_revocation_cleanup(state.users.clone(), config.revocation_cleanup_interval);
+
+    // 🚧 Writes answer 503 until MAINTENANCE_MODE is turned off again and reloaded with SIGHUP
+    if config.maintenance_mode {
+        tracing::warn!("MAINTENANCE_MODE is on, refusing writes");
+    }
+    #[cfg(unix)]
+    maintenance::spawn_reload_on_sighup(state.maintenance.clone());
+
+    let cors = config.cors.clone();
+    let content_security_policy = config.content_security_policy.clone();
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(db_pool.clone())) // Pass the database pool to the app
-            .route("/register", web::post().to(register_user))
-            .route("/users", web::get().to(get_users))
-            .route("/login", web::post().to(login_user))
+            .wrap(from_fn(middleware::error_format::negotiate_error_format)) // problem+json errors on request; inside Compress as it swaps bodies
+            .wrap(Compress::default()) // gzip/brotli/zstd bodies for clients that send Accept-Encoding
+            .wrap(middleware::security_headers::security_headers(&content_security_policy)) // nosniff, DENY framing, no referrer, CSP
+            .wrap(middleware::cors::cors(&cors)) // Answer preflights and add CORS headers
+            .wrap(from_fn(middleware::logging::request_logger)) // Log every request with its status and latency
+            .wrap(from_fn(middleware::metrics::track_requests)) // Count requests and latency for /metrics
+            .wrap(from_fn(middleware::request_id::assign_request_id)) // Outermost, so the logger's span and every response get the id
+            .configure(|cfg| app::configure(cfg, &state))
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
-}
\ No newline at end of file
+    .shutdown_timeout(30) // Give in-flight requests up to 30 seconds to finish
+    .disable_signals();   // Signals are handled below so the pool can be closed afterwards
+
+    // 🧵 Size the worker pool to the container's CPU quota when WORKERS is set
+    let server = match config.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+
+    // 🔒 Terminate TLS in-process when a certificate is configured, otherwise serve plain HTTP
+    let server = if config.bind_tcp {
+        let address = (config.host.as_str(), config.port);
+        let (server, scheme) = match config.tls.clone() {
+            Some(tls) => (server.bind_rustls_0_23(address, tls)?, "https"),
+            None => (server.bind(address)?, "http"),
+        };
+        tracing::info!("Listening on {}://{}:{}", scheme, config.host, config.port);
+        server
+    } else {
+        server
+    };
+
+    // 🔌 The proxy in front of a Unix socket terminates TLS, so it always serves plain HTTP
+    #[cfg(unix)]
+    let server = match &config.bind_uds {
+        Some(path) => {
+            uds::remove_stale_socket(path).map_err(std::io::Error::other)?;
+            let server = server.bind_uds(path)?;
+            tracing::info!("Listening on unix:{}", path.display());
+            server
+        }
+        None => server,
+    };
+    let server = server.run();
+
+    tracing::info!("Starting server");
+
+    // 🛑 Stop accepting connections and drain in-flight requests on SIGTERM/SIGINT
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("shutting down gracefully");
+        handle.stop(true).await;
+    });
+
+    server.await?;
+
+    // Close the pool only after every worker has finished its requests
+    db_pool.close().await;
+    tracing::info!("Database pool closed");
+
+    Ok(())
+}
+
+/// Resolve when the process receives SIGINT (Ctrl+C) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
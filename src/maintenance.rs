@@ -0,0 +1,50 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::sync::Arc;
+
+#[cfg(unix)]
+use crate::config;
+
+/// Whether writes are refused with 503 (`MAINTENANCE_MODE`), shared by every
+/// worker so a reload takes effect everywhere at once
+#[derive(Debug, Default)]
+pub struct MaintenanceMode(AtomicBool);
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool) -> Self {
+        MaintenanceMode(AtomicBool::new(enabled))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Re-read `MAINTENANCE_MODE` every time the process gets SIGHUP.
+///
+/// Nothing outside can change a running process's environment, so `.env` is
+/// loaded again first, overriding what it set before. An invalid value is
+/// logged and leaves the current mode in place.
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(mode: Arc<MaintenanceMode>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .expect("Failed to listen for SIGHUP");
+
+        while hangups.recv().await.is_some() {
+            dotenvy::dotenv_override().ok();
+
+            match config::maintenance_mode_from_env() {
+                Ok(enabled) => {
+                    mode.set(enabled);
+                    tracing::info!(enabled, "Reloaded MAINTENANCE_MODE");
+                }
+                Err(e) => tracing::warn!("{}; keeping the current maintenance mode", e),
+            }
+        }
+    })
+}
@@ -0,0 +1,72 @@
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+use crate::db::DbPool;
+
+/// Prometheus collectors for the HTTP layer and the connection pool.
+///
+/// Each instance owns its registry, so separate apps (e.g. in tests) never
+/// share counts.
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    pool_active: IntGauge,
+    pool_idle: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled, by route and status"),
+            &["method", "path", "status"],
+        )
+        .expect("valid metric");
+        let latency = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency, by route"),
+            &["method", "path"],
+        )
+        .expect("valid metric");
+        let pool_active = IntGauge::new("db_pool_active_connections", "Database connections currently checked out")
+            .expect("valid metric");
+        let pool_idle = IntGauge::new("db_pool_idle_connections", "Open database connections waiting in the pool")
+            .expect("valid metric");
+
+        registry.register(Box::new(requests.clone())).expect("unique metric");
+        registry.register(Box::new(latency.clone())).expect("unique metric");
+        registry.register(Box::new(pool_active.clone())).expect("unique metric");
+        registry.register(Box::new(pool_idle.clone())).expect("unique metric");
+
+        Metrics { registry, requests, latency, pool_active, pool_idle }
+    }
+
+    /// Count one finished request; `path` should be the route pattern so ids don't explode the label set
+    pub fn observe_request(&self, method: &str, path: &str, status: u16, seconds: f64) {
+        self.requests
+            .with_label_values(&[method, path, &status.to_string()])
+            .inc();
+        self.latency.with_label_values(&[method, path]).observe(seconds);
+    }
+
+    /// Refresh the pool gauges and render everything in the Prometheus text format
+    pub fn render(&self, pool: &DbPool) -> String {
+        let size = pool.size() as i64;
+        let idle = pool.num_idle() as i64;
+        self.pool_active.set(size - idle);
+        self.pool_idle.set(idle);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Failed to encode metrics");
+
+        String::from_utf8(buffer).expect("Prometheus output is UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,53 @@
+use actix_cors::Cors;
+use actix_web::http::{header, Method};
+
+use crate::middleware::request_id::REQUEST_ID_HEADER;
+
+/// Cross-origin settings (`ALLOWED_ORIGINS`, `CORS_ALLOW_CREDENTIALS`, `CORS_MAX_AGE_SECS`)
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>, // Exact origins, or a single `*` for any origin
+    pub allow_credentials: bool,      // Let browsers send cookies; never combined with `*`
+    pub max_age_secs: Option<usize>,  // How long browsers may cache a preflight answer
+}
+
+impl CorsConfig {
+    /// Whether `ALLOWED_ORIGINS` lets in every origin
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+}
+
+/// Build the CORS middleware for the configured origins.
+///
+/// With no origins configured every cross-origin request is rejected;
+/// there is deliberately no `*` fallback, only an explicit `*` entry allows
+/// any origin. Preflight `OPTIONS` requests are answered by the middleware itself.
+pub fn cors(config: &CorsConfig) -> Cors {
+    let cors = if config.allows_any_origin() {
+        Cors::default().allow_any_origin()
+    } else {
+        config
+            .allowed_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+    };
+
+    let cors = cors
+        .allowed_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+        .allowed_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::HeaderName::from_static("idempotency-key"),
+            REQUEST_ID_HEADER,
+        ])
+        .expose_headers([
+            REQUEST_ID_HEADER, // Let browser clients read the id to quote it in bug reports
+            header::LINK,      // Pagination links and total of `GET /users`
+            header::HeaderName::from_static("x-total-count"),
+        ])
+        .max_age(config.max_age_secs);
+
+    // 🍪 `AppConfig::from_env` refuses credentials with `*`, which would let any site act as the user
+    if config.allow_credentials { cors.supports_credentials() } else { cors }
+}
@@ -0,0 +1,49 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+
+use crate::error::{AppError, ErrorFormat, PROBLEM_JSON};
+
+/// Re-render `AppError` responses as problem details when that is the preferred envelope.
+///
+/// Clients that list `application/problem+json` in `Accept` always get it;
+/// everyone else gets the `ERROR_FORMAT` default. Must run inside `Compress`,
+/// since it replaces the body.
+pub async fn negotiate_error_format(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let wants_problem = req
+        .headers()
+        .get_all(ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains(PROBLEM_JSON));
+    let format = if wants_problem {
+        ErrorFormat::Problem
+    } else {
+        req.app_data::<web::Data<ErrorFormat>>().map(|format| *format.get_ref()).unwrap_or_default()
+    };
+
+    let response = next.call(req).await?;
+    if format == ErrorFormat::Json {
+        return Ok(response.map_into_left_body());
+    }
+
+    // 🔁 Only errors raised as `AppError` are rewritten; other bodies pass through
+    let Some(mut rendered) = response.response().error().and_then(|e| e.as_error::<AppError>()).map(|e| e.render(format))
+    else {
+        return Ok(response.map_into_left_body());
+    };
+
+    // 📋 Keep headers set along the way, e.g. by extractors, minus the old body's
+    let (req, original) = response.into_parts();
+    for (name, value) in original.headers() {
+        if name != CONTENT_TYPE && name != CONTENT_LENGTH && !rendered.headers().contains_key(name) {
+            rendered.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+
+    Ok(ServiceResponse::new(req, rendered).map_into_right_body())
+}
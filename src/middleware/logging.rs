@@ -0,0 +1,78 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+use std::time::Instant;
+use tracing::Instrument;
+
+use crate::middleware::request_id::RequestId;
+
+/// Paths whose access log line drops to `debug` unless they fail (`LOG_EXCLUDE_PATHS`),
+/// e.g. the probes and scrapers that would otherwise flood the logs
+#[derive(Debug, Clone, Default)]
+pub struct LogExcludePaths(pub Vec<String>);
+
+impl LogExcludePaths {
+    /// Parse a comma-separated list of exact paths, each starting with `/`
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let paths: Vec<String> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        match paths.iter().find(|path| !path.starts_with('/')) {
+            Some(path) => Err(format!("LOG_EXCLUDE_PATHS entry {:?} must start with /", path)),
+            None => Ok(LogExcludePaths(paths)),
+        }
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.0.iter().any(|excluded| excluded == path)
+    }
+}
+
+/// Wrap every request in a tracing span and log its method, path, status and latency.
+///
+/// The span carries the `request_id` set by `assign_request_id` (empty when
+/// that middleware isn't installed), so every line logged while handling the
+/// request, handler errors included, can be matched to it. Requests to a
+/// `LogExcludePaths` path are logged at `debug` while they answer below 400.
+pub async fn request_logger(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone()).unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+    let quiet = req
+        .app_data::<web::Data<LogExcludePaths>>()
+        .is_some_and(|excluded| excluded.contains(req.path()));
+    let start = Instant::now();
+
+    // ⏱️ Run the rest of the chain inside the span so handler logs carry its fields
+    let result = next.call(req).instrument(span.clone()).await;
+    let latency_ms = start.elapsed().as_millis() as u64;
+
+    span.in_scope(|| match &result {
+        // 🔇 Routine hits on excluded paths only show up with debug logging; failures always do
+        Ok(response) if quiet && response.status().as_u16() < 400 => tracing::debug!(
+            status = response.status().as_u16(),
+            latency_ms,
+            "request completed"
+        ),
+        Ok(response) => tracing::info!(
+            status = response.status().as_u16(),
+            latency_ms,
+            "request completed"
+        ),
+        Err(e) => tracing::error!(error = %e, latency_ms, "request failed"),
+    });
+
+    result
+}
@@ -0,0 +1,27 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::time::Instant;
+
+use crate::metrics::Metrics;
+
+/// Record every request's route, status and latency in the shared `Metrics`
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let method = req.method().to_string();
+    let start = Instant::now();
+
+    let response = next.call(req).await?;
+
+    if let Some(metrics) = metrics {
+        // 🏷️ Label by route pattern (`/users/{id}`), not raw path, to bound the label set
+        let path = response.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+        metrics.observe_request(&method, &path, response.status().as_u16(), start.elapsed().as_secs_f64());
+    }
+
+    Ok(response)
+}
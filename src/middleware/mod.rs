@@ -0,0 +1,8 @@
+pub mod cors;
+pub mod error_format;
+pub mod logging;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security_headers;
+pub mod timeout;
\ No newline at end of file
@@ -0,0 +1,136 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use dashmap::DashMap;
+use std::future::{ready, Future, Ready};
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::client_ip::client_ip;
+use crate::error::AppError;
+
+/// Bucket count above which fully refilled (idle) buckets are evicted
+const EVICTION_THRESHOLD: usize = 10_000;
+
+/// Token bucket state for a single client IP
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Per-IP token bucket limiter shared by every worker
+pub struct RateLimiter {
+    buckets: DashMap<IpAddr, Bucket>,
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+impl RateLimiter {
+    /// Allow `per_minute` requests per IP, refilled continuously
+    pub fn new(per_minute: u32) -> Self {
+        let capacity = f64::from(per_minute.max(1));
+        RateLimiter {
+            buckets: DashMap::new(),
+            capacity,
+            refill_per_sec: capacity / 60.0,
+        }
+    }
+
+    /// Take one token for `ip`, or return how many seconds until one is available
+    fn check(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+
+        if self.buckets.len() > EVICTION_THRESHOLD {
+            self.evict_idle(now);
+        }
+
+        let mut bucket = self.buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            last_refill: now,
+        });
+
+        // ⏳ Refill the bucket for the time elapsed since the last request
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.refill_per_sec;
+            Err(wait.ceil() as u64)
+        }
+    }
+
+    /// Drop buckets that would be full by now; they carry no state worth keeping
+    fn evict_idle(&self, now: Instant) {
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens + elapsed * self.refill_per_sec < self.capacity
+        });
+    }
+}
+
+/// Middleware answering `429 Too Many Requests` once a client exhausts its quota
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        RateLimit { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // 🚦 Requests without a resolvable client IP are not limited
+        if let Some(ip) = client_ip(req.request())
+            && let Err(retry_after) = self.limiter.check(ip)
+        {
+            let response = req.error_response(AppError::TooManyRequests(retry_after));
+            return Box::pin(async move { Ok(response.map_into_right_body()) });
+        }
+
+        let service = self.service.clone();
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
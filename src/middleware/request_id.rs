@@ -0,0 +1,50 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+use uuid::Uuid;
+
+/// Header a caller may send its correlation id in, and that echoes it back
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming id that is kept; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Correlation id of the current request, stored in its extensions by `assign_request_id`
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Give every request a correlation id and echo it as `X-Request-Id`.
+///
+/// An incoming `X-Request-Id` is kept when it is at most 128 letters, digits,
+/// `-`, `_`, `.` or `:`, so an id from an upstream service follows the request
+/// through; anything else is replaced with a new UUID. `request_logger` puts
+/// the id on the request span, so every log line the request produces carries it.
+pub async fn assign_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let mut response = next.call(req).await?;
+
+    // 🏷️ Only characters valid in a header value get this far
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(response)
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
@@ -0,0 +1,18 @@
+use actix_web::http::header;
+use actix_web::middleware::DefaultHeaders;
+
+/// Policy used when `CONTENT_SECURITY_POLICY` is unset: the API only serves JSON,
+/// so nothing may be loaded or framed
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; frame-ancestors 'none'";
+
+/// Hardening headers added to every response.
+///
+/// Handlers can still send their own value for any of them; `DefaultHeaders`
+/// only fills in headers the response doesn't already have.
+pub fn security_headers(content_security_policy: &str) -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .add((header::X_FRAME_OPTIONS, "DENY"))
+        .add((header::REFERRER_POLICY, "no-referrer"))
+        .add((header::CONTENT_SECURITY_POLICY, content_security_policy.to_string()))
+}
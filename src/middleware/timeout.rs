@@ -0,0 +1,49 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::time::Duration;
+
+use crate::error::AppError;
+
+/// Longest a handler may take to produce its response (`REQUEST_TIMEOUT_SECS`)
+#[derive(Debug, Clone, Copy)]
+pub struct RequestTimeout(pub Duration);
+
+/// Answer with a `504` when the rest of the chain doesn't respond within `RequestTimeout`.
+///
+/// The timed-out future is dropped, which cancels whatever it was awaiting:
+/// sqlx gives up the in-flight query and discards its connection rather than
+/// returning a half-read one to the pool. Streamed bodies are not limited once
+/// the response has started.
+///
+/// The 504 is an ordinary `ServiceResponse` built from a clone of the request,
+/// so metrics, request ids, security headers and problem+json apply to it like
+/// to any other error. Actix can only route a request nobody else holds, so
+/// this must wrap routes (`Route::wrap`, see `app::timed`), not the `App`.
+pub async fn enforce_timeout(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(timeout) = req.app_data::<web::Data<RequestTimeout>>().map(|timeout| timeout.0) else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    let http_req = req.request().clone();
+    let method = req.method().clone();
+    let path = req.path().to_string();
+
+    match tokio::time::timeout(timeout, next.call(req)).await {
+        Ok(response) => response.map(ServiceResponse::map_into_left_body),
+        Err(_) => {
+            tracing::warn!(
+                method = %method,
+                path = %path,
+                timeout_ms = timeout.as_millis() as u64,
+                "Request timed out; its pending work was cancelled"
+            );
+
+            let timed_out = AppError::Timeout("request timed out".to_string());
+            Ok(ServiceResponse::from_err(timed_out, http_req).map_into_right_body())
+        }
+    }
+}
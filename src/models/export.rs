@@ -0,0 +1,46 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::models::login_history::LoginRecord;
+
+/// Everything stored about one account, returned by `GET /users/{id}/export`.
+///
+/// Secrets are left out: the password hash, the TOTP secret and the one-time
+/// tokens of the pending rows, which would otherwise let the reader act as the user.
+#[derive(Debug, Serialize)]
+pub struct UserDataExport {
+    pub profile: UserRecord,
+    pub logins: Vec<LoginRecord>,
+    pub email_verifications: Vec<PendingTokenRecord>,
+    pub password_resets: Vec<PendingTokenRecord>,
+    pub email_changes: Vec<PendingTokenRecord>,
+}
+
+/// Every column of the `users` row except `password` and `totp_secret`
+#[derive(Debug, Serialize, FromRow)]
+pub struct UserRecord {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    pub username: Option<String>,
+    pub pending_email: Option<String>,
+    pub phone: Option<String>,
+    pub role: String,
+    pub status: String,
+    pub version: i32,
+    pub verified: bool,
+    pub totp_enabled: bool,
+    pub failed_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// An outstanding verification, reset or email change link, without its token
+#[derive(Debug, Serialize, FromRow)]
+pub struct PendingTokenRecord {
+    pub expires_at: DateTime<Utc>,
+}
@@ -0,0 +1,18 @@
+use ring::digest::{digest, SHA256};
+use serde::Serialize;
+use sqlx::FromRow;
+
+/// A response saved under an `Idempotency-Key`, replayed verbatim when the key comes back
+#[derive(Debug, Clone, FromRow)]
+pub struct StoredResponse {
+    pub request_hash: String,      // `request_hash` of the body the response answered
+    pub status_code: i32,
+    pub response_body: String,     // Serialized JSON body
+    pub location: Option<String>,  // `Location` header, if the response had one
+}
+
+/// Hex SHA-256 of a request's JSON, so a key reused with another body can be told apart
+pub fn request_hash(body: &impl Serialize) -> String {
+    let json = serde_json::to_vec(body).unwrap_or_default();
+    digest(&SHA256, &json).as_ref().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
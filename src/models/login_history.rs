@@ -0,0 +1,28 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+/// Longest `User-Agent` kept per login (the column width); longer values are cut
+pub const MAX_USER_AGENT_LEN: usize = 512;
+
+/// One successful login, as listed at `GET /users/{id}/logins`
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct LoginRecord {
+    pub id: String,
+    pub ip: Option<String>,         // None when the client address couldn't be resolved
+    pub user_agent: Option<String>, // None when the client sent no readable User-Agent
+    pub created_at: DateTime<Utc>,
+}
+
+/// One live access token, as listed at `GET /users/me/sessions`
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct SessionRecord {
+    pub jti: String,                // Token id, used to revoke it at `DELETE /users/me/sessions/{jti}`
+    pub ip: Option<String>,         // None when the client address couldn't be resolved
+    pub user_agent: Option<String>, // None when the client sent no readable User-Agent
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    #[sqlx(skip)]
+    pub current: bool,              // Whether this is the token the listing was requested with
+}
@@ -1 +1,5 @@
-pub mod user;
\ No newline at end of file
+pub mod export;
+pub mod idempotency;
+pub mod login_history;
+pub mod pagination;
+pub mod user;
@@ -0,0 +1,109 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+pub const DEFAULT_LIMIT: i64 = 20;
+pub const MAX_LIMIT: i64 = 100;
+
+/// Page sizes of every `limit`/`offset` listing (`DEFAULT_PAGE_SIZE`, `MAX_PAGE_SIZE`)
+#[derive(Debug, Clone, Copy)]
+pub struct PageSize {
+    pub default: i64, // Used when a request sends no `limit`
+    pub max: i64,     // Larger `limit`s are rejected with 400
+}
+
+impl Default for PageSize {
+    fn default() -> Self {
+        PageSize { default: DEFAULT_LIMIT, max: MAX_LIMIT }
+    }
+}
+
+#[derive(Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaginationQuery {
+    /// Page size, 1 to `MAX_PAGE_SIZE` (default `DEFAULT_PAGE_SIZE`, 20)
+    pub limit: Option<i64>,
+
+    #[validate(range(min = 0, message = "offset must not be negative"))]
+    /// Rows to skip (default 0)
+    pub offset: Option<i64>,
+}
+
+impl PaginationQuery {
+    /// Validate the query, with `limit` bounded by the configured page size
+    pub fn validate_with(&self, size: PageSize) -> Result<(), ValidationErrors> {
+        let mut errors = self.validate().err().unwrap_or_default();
+
+        // The upper bound is only known at runtime, so it can't be a `#[validate(range)]`
+        if let Some(limit) = self.limit
+            && !(1..=size.max).contains(&limit)
+        {
+            let mut error = ValidationError::new("range");
+            error.message = Some(format!("limit must be between 1 and {}", size.max).into());
+            errors.add("limit", error);
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    pub fn limit(&self, size: PageSize) -> i64 {
+        self.limit.unwrap_or(size.default).min(size.max)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0)
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CursorQuery {
+    /// `next_cursor` of the previous page, or empty for the first; oldest users first, no `offset` or `sort`
+    pub cursor: Option<String>,
+}
+
+/// Where a cursor page of `GET /users` resumes: just after this `(created_at, id)`.
+///
+/// Handed to clients as base64url-encoded JSON, which they should treat as opaque.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: String,
+}
+
+impl UserCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("a cursor always serializes"))
+    }
+
+    /// `None` for anything `encode` didn't produce
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+/// GitHub-style `Link` header value for one page of `total` rows.
+///
+/// `base` is the page's path with any query parameters other than `limit`
+/// and `offset` (which are appended). `first` and `last` are always present;
+/// `prev` and `next` are left out on the first and last pages.
+pub fn link_header(base: &str, limit: i64, offset: i64, total: i64) -> String {
+    let separator = if base.contains('?') { '&' } else { '?' };
+    let link = |offset: i64, rel: &str| format!("<{}{}limit={}&offset={}>; rel=\"{}\"", base, separator, limit, offset, rel);
+    let last = if total > 0 { (total - 1) / limit * limit } else { 0 };
+
+    let mut links = vec![link(0, "first")];
+    if offset > 0 {
+        // Clamped so a page past the end points back at real rows
+        links.push(link((offset - limit).clamp(0, last), "prev"));
+    }
+    if offset + limit < total {
+        links.push(link(offset + limit, "next"));
+    }
+    links.push(link(last, "last"));
+
+    links.join(", ")
+}
@@ -1,29 +1,422 @@
+use chrono::{DateTime, Utc};
+use serde::de::IgnoredAny;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use sqlx::FromRow;
-use validator::Validate;
+use std::borrow::Cow;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
 
-#[derive(Deserialize, Validate)]
+use crate::extractors::Normalize;
+
+/// Longest password accepted anywhere, so nobody can make Argon2 chew on megabytes
+/// (keep in sync with the `length(max = ...)` rules below)
+pub const MAX_PASSWORD_LEN: usize = 128;
+
+#[derive(Deserialize, Validate, ToSchema)]
 pub struct RegisterRequest {
-    #[validate(length(min = 1, message = "Name is required"))]
+    #[validate(custom = "validate_name")]
     pub name: String,
 
     #[validate(email(message = "Invalid email address"))]
     pub email: String,
 
-    #[validate(length(min = 8, message = "Password must be at least 8 characters long"))]
+    #[validate(
+        length(min = 3, max = 30, message = "Username must be between 3 and 30 characters long"),
+        custom = "validate_username"
+    )]
+    pub username: String,
+
+    /// Optional contact number in E.164 form, e.g. `+14155550123`
+    #[validate(custom = "validate_phone")]
+    pub phone: Option<String>,
+
+    #[validate(
+        length(min = 8, message = "Password must be at least 8 characters long"),
+        length(max = 128, message = "Password must be at most 128 characters long"),
+        custom = "validate_password_strength"
+    )]
     pub password: String,
 }
 
-#[derive(Debug, Serialize, FromRow)]
+impl Normalize for RegisterRequest {
+    /// Case and whitespace variants of an email or username map to one account;
+    /// a name is stored trimmed, so all-whitespace fails validation
+    fn normalize(&mut self) {
+        self.name = normalize_name(&self.name);
+        self.email = normalize_email(&self.email);
+        self.username = normalize_username(&self.username);
+    }
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct ChangePasswordRequest {
+    #[validate(length(max = 128, message = "Password must be at most 128 characters long"))]
+    pub old_password: String,
+
+    #[validate(
+        length(min = 8, message = "Password must be at least 8 characters long"),
+        length(max = 128, message = "Password must be at most 128 characters long"),
+        custom = "validate_password_strength"
+    )]
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct UpdateUserRequest {
+    #[validate(custom = "validate_name")]
+    pub name: Option<String>,
+
+    /// Never accepted here: emails change through `POST /users/{id}/email` so the new address is verified first
+    #[serde(default)]
+    #[schema(ignore)]
+    pub email: Option<IgnoredAny>,
+
+    /// New contact number in E.164 form, e.g. `+14155550123`; `null` removes it.
+    /// Absent is `None`, `null` is `Some(None)`.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>, nullable)]
+    #[validate(custom = "validate_phone")]
+    pub phone: Option<Option<String>>,
+
+    /// The `version` the client last read; the update fails with 409 if the user has changed since
+    pub version: Option<i32>,
+}
+
+impl UpdateUserRequest {
+    /// True when the body names no field to change (`version` alone changes nothing)
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.phone.is_none()
+    }
+}
+
+#[derive(Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchUsersQuery {
+    #[validate(length(min = 1, message = "Search query is required"))]
+    /// Text to find in names or emails
+    pub q: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SortUsersQuery {
+    /// `name`, `-name`, `created_at` or `-created_at` (default)
+    pub sort: Option<String>,
+}
+
+/// Whitelisted orderings for `GET /users`; a leading `-` means descending
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UserSort {
+    NameAsc,
+    NameDesc,
+    CreatedAtAsc,
+    #[default]
+    CreatedAtDesc,
+}
+
+impl UserSort {
+    /// Map a `sort` query value to an ordering; `None` for unknown keys
+    pub fn parse(key: &str) -> Option<Self> {
+        match key {
+            "name" => Some(UserSort::NameAsc),
+            "-name" => Some(UserSort::NameDesc),
+            "created_at" => Some(UserSort::CreatedAtAsc),
+            "-created_at" => Some(UserSort::CreatedAtDesc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FilterUsersQuery {
+    /// `true` or `false`: only users who have or have not verified their email
+    pub verified: Option<String>,
+    /// `active` or `suspended`: only users with this account status
+    pub status: Option<String>,
+}
+
+impl FilterUsersQuery {
+    /// The filters to apply; `Err` describes the first value that isn't whitelisted
+    pub fn parse(&self) -> Result<UserFilter, String> {
+        let verified = match self.verified.as_deref() {
+            None => None,
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            Some(other) => return Err(format!("verified must be true or false, got {:?}", other)),
+        };
+        let status = match self.status.as_deref() {
+            None => None,
+            Some("active") => Some(AccountStatus::Active),
+            Some("suspended") => Some(AccountStatus::Suspended),
+            Some(other) => return Err(format!("status must be active or suspended, got {:?}", other)),
+        };
+
+        Ok(UserFilter { verified, status })
+    }
+}
+
+/// Conditions `GET /users` narrows the live users by; every one that is set must hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UserFilter {
+    pub verified: Option<bool>,
+    pub status: Option<AccountStatus>,
+}
+
+impl UserFilter {
+    /// The query string that reproduces these filters, e.g. `verified=false&status=active`
+    pub fn query_params(&self) -> Vec<String> {
+        self.verified
+            .map(|verified| format!("verified={}", verified))
+            .into_iter()
+            .chain(self.status.map(|status| format!("status={}", status.as_str())))
+            .collect()
+    }
+}
+
+/// Fields needed to insert a new user row
+#[derive(Debug)]
+pub struct NewUser {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    pub username: String,
+    pub phone: Option<String>,
+    pub password_hash: String,
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
 pub struct User {
     pub id: String,
     pub name: String,
     pub email: String,
+    pub username: Option<String>, // None for accounts created before usernames existed
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>, // None until the first successful login
+    pub status: String,                       // `active` or `suspended`
+    pub version: i32,                         // Bumped by every PUT/PATCH; send it back to update safely
+    pub phone: Option<String>,                // E.164 contact number, if one was given
 }
 
+/// `User` fields a `fields` query may name, in response order
+pub const USER_FIELDS: &[&str] =
+    &["id", "name", "email", "username", "phone", "role", "status", "version", "created_at", "updated_at", "last_login_at"];
 
-#[derive(Debug, sqlx::FromRow, Deserialize)]
+impl User {
+    /// Only the named fields, for sparse fieldset responses (`fields` must come from `FieldsQuery::parse`)
+    pub fn select(&self, fields: &[&'static str]) -> Map<String, Value> {
+        let Ok(Value::Object(mut all)) = serde_json::to_value(self) else {
+            return Map::new();
+        };
+
+        fields
+            .iter()
+            .filter_map(|field| all.remove(*field).map(|value| (field.to_string(), value)))
+            .collect()
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FieldsQuery {
+    /// Comma-separated `User` fields to return, e.g. `id,email` (default: all)
+    pub fields: Option<String>,
+}
+
+impl FieldsQuery {
+    /// The requested fields in `USER_FIELDS` order, or `None` for the whole record;
+    /// `Err` describes the first unknown or missing name
+    pub fn parse(&self) -> Result<Option<Vec<&'static str>>, String> {
+        let Some(list) = &self.fields else {
+            return Ok(None);
+        };
+
+        let requested: Vec<&str> = list.split(',').map(str::trim).filter(|field| !field.is_empty()).collect();
+        if requested.is_empty() {
+            return Err("fields must name at least one field".to_string());
+        }
+        if let Some(unknown) = requested.iter().find(|field| !USER_FIELDS.contains(field)) {
+            return Err(format!("unknown field {:?}; allowed fields are {}", unknown, USER_FIELDS.join(", ")));
+        }
+
+        Ok(Some(USER_FIELDS.iter().copied().filter(|field| requested.contains(field)).collect()))
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
+    /// Email or username; `email` is still accepted as the field name
+    #[serde(alias = "email")]
+    pub identifier: String,
+    pub password: String,
+    /// Current authenticator code; required once two-factor login is enabled
+    pub totp_code: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct UserCredentials {
+    pub id: String,
     pub email: String,
     pub password: String,
-}
\ No newline at end of file
+    pub role: String,
+    pub verified: bool,
+    pub failed_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub status: String,
+    pub totp_secret: Option<String>, // Encrypted; see `totp::TotpCipher`
+    pub totp_enabled: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+
+    #[validate(
+        length(min = 8, message = "Password must be at least 8 characters long"),
+        length(max = 128, message = "Password must be at most 128 characters long"),
+        custom = "validate_password_strength"
+    )]
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct ChangeEmailRequest {
+    #[validate(email(message = "Invalid email address"))]
+    pub email: String,
+}
+
+/// Whether an account may log in; suspension is the reversible alternative to deleting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    Active,
+    Suspended,
+}
+
+impl AccountStatus {
+    /// The value stored in `users.status`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Suspended => "suspended",
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SetStatusRequest {
+    pub status: AccountStatus,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeleteAccountConfirm {
+    pub token: String, // The one-time token emailed by `POST /users/me/delete-request`
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+/// Trim a display name so `"  Alice "` is stored as `"Alice"`
+pub fn normalize_name(name: &str) -> String {
+    name.trim().to_string()
+}
+
+/// Lowercase and trim an email so lookups and uniqueness don't depend on collation
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Lowercase and trim a username so `Alice` and `alice` are the same account
+pub fn normalize_username(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+/// Names must contain something other than whitespace
+pub fn validate_name(name: &str) -> Result<(), ValidationError> {
+    if name.trim().is_empty() {
+        let mut error = ValidationError::new("name_required");
+        error.message = Some(Cow::Borrowed("Name is required"));
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Deserialize a field that is in the body as `Some`, even when it is `null`,
+/// so `#[serde(default)]` leaves only an absent field as `None`
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Usernames are ASCII letters and digits only, so they can never look like an email
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    if !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        let mut error = ValidationError::new("username_alphanumeric");
+        error.message = Some(Cow::Borrowed("Username may only contain letters and digits"));
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Phone numbers must be E.164: a `+` followed by 7 to 15 digits, no spaces or dashes
+pub fn validate_phone(phone: &str) -> Result<(), ValidationError> {
+    let valid = phone
+        .strip_prefix('+')
+        .is_some_and(|digits| (7..=15).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()));
+    if !valid {
+        let mut error = ValidationError::new("phone_e164");
+        error.message = Some(Cow::Borrowed("Phone number must be in E.164 format, e.g. +14155550123"));
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Require at least one uppercase letter, lowercase letter, digit and symbol
+pub fn validate_password_strength(password: &str) -> Result<(), ValidationError> {
+    if !password.chars().any(|c| c.is_uppercase()) {
+        return Err(password_error("password_uppercase", "Password must contain at least one uppercase letter"));
+    }
+    if !password.chars().any(|c| c.is_lowercase()) {
+        return Err(password_error("password_lowercase", "Password must contain at least one lowercase letter"));
+    }
+    if !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err(password_error("password_digit", "Password must contain at least one digit"));
+    }
+    if !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+        return Err(password_error("password_symbol", "Password must contain at least one symbol"));
+    }
+
+    Ok(())
+}
+
+fn password_error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::Borrowed(message));
+    error
+}
@@ -0,0 +1,153 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+use crate::handlers::{account_deletion, login_history, two_factor, user};
+use crate::models::login_history::{LoginRecord, SessionRecord};
+use crate::models::user::{
+    AccountStatus, ChangePasswordRequest, DeleteAccountConfirm, LoginRequest, RegisterRequest, SetStatusRequest,
+    TotpCodeRequest, UpdateUserRequest, User,
+};
+
+/// The OpenAPI description served at `/api-docs/openapi.json`.
+///
+/// Covers registration, login and the `/users` endpoints; each handler
+/// documents itself with `#[utoipa::path]`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "rust_learning", description = "User accounts API"),
+    paths(
+        user::register_user,
+        user::login_user,
+        user::logout_user,
+        two_factor::enable_two_factor,
+        two_factor::confirm_two_factor,
+        user::get_users,
+        user::count_users,
+        user::search_users,
+        user::get_current_user,
+        user::get_user_by_id,
+        user::update_user,
+        user::patch_user,
+        user::delete_user,
+        account_deletion::request_account_deletion,
+        account_deletion::confirm_account_deletion,
+        user::restore_user,
+        user::set_user_status,
+        user::change_password,
+        login_history::list_logins,
+        login_history::list_sessions,
+        login_history::revoke_session,
+    ),
+    components(schemas(
+        RegisterRequest,
+        LoginRequest,
+        TotpCodeRequest,
+        UpdateUserRequest,
+        ChangePasswordRequest,
+        DeleteAccountConfirm,
+        SetStatusRequest,
+        AccountStatus,
+        User,
+        LoginRecord,
+        SessionRecord,
+        TokenResponse,
+        TotpSetupResponse,
+        LoginPage,
+        SessionList,
+        UserPage,
+        UserCursorPage,
+        CountResponse,
+        MessageResponse,
+        ErrorResponse,
+        ValidationErrorResponse,
+    )),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+/// Registers the `bearer_auth` scheme the protected paths refer to
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer_auth",
+                SecurityScheme::Http(Http::builder().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+            );
+        }
+    }
+}
+
+// The handlers build these bodies with `json!`; the structs only describe them
+
+/// Body of a successful `POST /login`
+#[derive(Serialize, ToSchema)]
+pub struct TokenResponse {
+    pub token: String,
+    pub expires_in: u64, // Seconds until the token expires
+}
+
+/// Body of a successful `POST /2fa/enable`
+#[derive(Serialize, ToSchema)]
+pub struct TotpSetupResponse {
+    pub secret: String,      // Base32, for typing into an authenticator by hand
+    pub otpauth_uri: String, // `otpauth://totp/...`, usually rendered as a QR code
+}
+
+/// One page of `GET /users` or `GET /users/search`
+#[derive(Serialize, ToSchema)]
+pub struct UserPage {
+    pub users: Vec<User>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// One page of `GET /users?cursor=...`, oldest first
+#[derive(Serialize, ToSchema)]
+pub struct UserCursorPage {
+    pub users: Vec<User>,
+    pub limit: i64,
+    pub next_cursor: Option<String>, // Pass back as `cursor` for the next page; null on the last one
+}
+
+/// One page of `GET /users/{id}/logins`, newest first
+#[derive(Serialize, ToSchema)]
+pub struct LoginPage {
+    pub logins: Vec<LoginRecord>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Body of `GET /users/me/sessions`, newest first
+#[derive(Serialize, ToSchema)]
+pub struct SessionList {
+    pub sessions: Vec<SessionRecord>,
+}
+
+/// Body of `GET /users/count`
+#[derive(Serialize, ToSchema)]
+pub struct CountResponse {
+    pub count: i64,
+}
+
+/// Plain acknowledgement, e.g. from `POST /logout`
+#[derive(Serialize, ToSchema)]
+pub struct MessageResponse {
+    pub message: String,
+}
+
+/// Every error except validation failures
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// A `400` for invalid input: each failing field with its messages
+#[derive(Serialize, ToSchema)]
+pub struct ValidationErrorResponse {
+    pub errors: BTreeMap<String, Vec<String>>,
+}
@@ -0,0 +1,117 @@
+// Import Argon2 for password hashing and verification
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher as _, PasswordVerifier, Version};
+
+// Import helper for generating random salt
+use password_hash::SaltString;
+use rand::rngs::OsRng; // OS secure random number generator
+
+use crate::error::AppError;
+
+/// Argon2 hasher built once at startup from the `ARGON2_*` settings (by
+/// default Argon2id v0x13 with 64 MiB and 3 passes, RFC 9106's second
+/// recommended option) and shared by every handler that hashes or verifies
+/// passwords, so both sides always agree on the parameters.
+///
+/// Hashes store their own variant, version and parameters, so passwords hashed
+/// under older settings keep verifying after the settings change. The pepper
+/// (`PASSWORD_PEPPER`) is not stored anywhere in the hash: changing or removing
+/// it makes every existing hash fail to verify.
+pub struct PasswordHasher {
+    argon2: Argon2<'static>,
+    algorithm: Algorithm, // Variant new hashes use, kept to spot hashes made with another
+    version: Version,     // Version new hashes use, likewise
+
+    /// Hash of a throwaway password, verified against when a login email is
+    /// unknown so that path costs the same Argon2 work as a wrong password
+    dummy_hash: String,
+}
+
+impl PasswordHasher {
+    /// Build a hasher for new hashes with this variant, version and parameters,
+    /// keyed with `pepper` if given
+    pub fn new(algorithm: Algorithm, version: Version, params: Params, pepper: Option<&str>) -> Self {
+        let argon2 = match pepper {
+            // 🌶️ The hasher lives for the whole process, so the secret can too
+            Some(pepper) => {
+                let secret: &'static [u8] = Box::leak(pepper.as_bytes().into());
+                Argon2::new_with_secret(secret, algorithm, version, params)
+                    .expect("Password pepper is too long for Argon2")
+            }
+            None => Argon2::new(algorithm, version, params),
+        };
+        let dummy_hash = hash_with(&argon2, "dummy-password-for-timing").expect("Failed to hash dummy password");
+
+        PasswordHasher { argon2, algorithm, version, dummy_hash }
+    }
+
+    /// Hash a plaintext password with a fresh random salt
+    pub fn hash(&self, password: &str) -> Result<String, AppError> {
+        hash_with(&self.argon2, password)
+    }
+
+    /// Check a plaintext password against a stored Argon2 hash
+    pub fn verify(&self, password: &str, stored_hash: &str) -> Result<bool, AppError> {
+        // 🔐 Parse stored password hash string into PasswordHash
+        let parsed_hash = PasswordHash::new(stored_hash)
+            .map_err(|e| AppError::Internal(format!("Error parsing stored password hash: {}", e)))?;
+
+        Ok(self
+            .argon2
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok())
+    }
+
+    /// Whether a stored hash was made with a different variant, version or
+    /// parameters than new hashes get, so it should be replaced by a fresh
+    /// hash of the same password. Unparseable hashes are left alone.
+    pub fn needs_rehash(&self, stored_hash: &str) -> bool {
+        let Ok(parsed_hash) = PasswordHash::new(stored_hash) else {
+            return false;
+        };
+        let Ok(params) = Params::try_from(&parsed_hash) else {
+            return false;
+        };
+        let current = self.argon2.params();
+        let output_len = |params: &Params| params.output_len().unwrap_or(Params::DEFAULT_OUTPUT_LEN);
+
+        Algorithm::try_from(parsed_hash.algorithm).ok() != Some(self.algorithm)
+            || parsed_hash.version.and_then(|v| Version::try_from(v).ok()) != Some(self.version)
+            || params.m_cost() != current.m_cost()
+            || params.t_cost() != current.t_cost()
+            || params.p_cost() != current.p_cost()
+            || output_len(&params) != output_len(current)
+    }
+
+    /// Burn the same Argon2 work as `verify` without a real account
+    pub fn dummy_verify(&self, password: &str) {
+        let _ = self.verify(password, &self.dummy_hash);
+    }
+}
+
+/// Parse an `ARGON2_VARIANT` value: `argon2i`, `argon2d` or `argon2id`
+pub fn parse_algorithm(name: &str) -> Option<Algorithm> {
+    match name.to_ascii_lowercase().as_str() {
+        "argon2i" => Some(Algorithm::Argon2i),
+        "argon2d" => Some(Algorithm::Argon2d),
+        "argon2id" => Some(Algorithm::Argon2id),
+        _ => None,
+    }
+}
+
+/// Parse an `ARGON2_VERSION` value: `0x13` (`19`) or the legacy `0x10` (`16`)
+pub fn parse_version(value: &str) -> Option<Version> {
+    match value.to_ascii_lowercase().as_str() {
+        "0x13" | "19" => Some(Version::V0x13),
+        "0x10" | "16" => Some(Version::V0x10),
+        _ => None,
+    }
+}
+
+fn hash_with(argon2: &Argon2<'static>, password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    argon2
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string()) // Convert the hash to a string to store in DB
+        .map_err(|e| AppError::Internal(format!("Error hashing password: {}", e)))
+}
@@ -0,0 +1,231 @@
+pub mod sql;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+use crate::db::DbPool;
+use crate::models::export::UserDataExport;
+use crate::models::idempotency::StoredResponse;
+use crate::models::login_history::{LoginRecord, SessionRecord};
+use crate::models::pagination::UserCursor;
+use crate::models::user::{NewUser, User, UserCredentials, UserFilter, UserSort};
+use sql::{MySqlUserRepository, PgUserRepository, SqliteUserRepository};
+
+/// Storage operations for user accounts, their one-time tokens, revoked access
+/// tokens and saved idempotent responses.
+///
+/// Lookups, listings and updates skip soft-deleted users.
+///
+/// Handlers depend on this trait instead of a concrete pool so they can be
+/// exercised against any implementation, including test doubles.
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    /// Insert a new user and return the stored row
+    async fn create(&self, user: &NewUser) -> Result<User, sqlx::Error>;
+
+    /// Insert a new user together with its email verification token in one
+    /// transaction, so neither row exists without the other
+    async fn create_with_verification(&self, user: &NewUser, token: &str, expires_at: DateTime<Utc>) -> Result<User, sqlx::Error>;
+
+    /// Insert already-verified users (e.g. migrated from another system) in one
+    /// transaction, a batch of rows per statement; any failure inserts none of them
+    async fn import_users(&self, users: &[NewUser]) -> Result<(), sqlx::Error>;
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<User>, sqlx::Error>;
+
+    /// Look up login credentials by email, ignoring letter case the same way
+    /// the unique index on `email_lower` does
+    async fn find_by_email(&self, email: &str) -> Result<Option<UserCredentials>, sqlx::Error>;
+
+    /// Whether any row, soft-deleted or not, already holds this email in any letter case
+    async fn email_exists(&self, email: &str) -> Result<bool, sqlx::Error>;
+
+    /// Look up login credentials by (normalized) username
+    async fn find_by_username(&self, username: &str) -> Result<Option<UserCredentials>, sqlx::Error>;
+
+    /// Whether any row, soft-deleted or not, already holds this (normalized) username
+    async fn username_exists(&self, username: &str) -> Result<bool, sqlx::Error>;
+
+    async fn find_credentials_by_id(&self, id: &str) -> Result<Option<UserCredentials>, sqlx::Error>;
+
+    /// `users.status` of a live user; `None` once the user is deleted
+    async fn account_status(&self, id: &str) -> Result<Option<String>, sqlx::Error>;
+
+    /// Page of live users matching `filter`, in `sort` order with `id` breaking ties
+    async fn list(&self, filter: &UserFilter, limit: i64, offset: i64, sort: UserSort) -> Result<Vec<User>, sqlx::Error>;
+
+    /// Number of live users matching `filter`
+    async fn count(&self, filter: &UserFilter) -> Result<i64, sqlx::Error>;
+
+    /// Up to `limit` live users with an id greater than `after`, ordered by id,
+    /// for walking the whole table in pages without OFFSET
+    async fn list_after(&self, after: &str, limit: i64) -> Result<Vec<User>, sqlx::Error>;
+
+    /// Page of live users matching `filter` created after `cursor`, oldest first with `id` breaking ties
+    async fn list_after_cursor(&self, filter: &UserFilter, cursor: &UserCursor, limit: i64) -> Result<Vec<User>, sqlx::Error>;
+
+    /// Page through users whose name or email contains `term` literally, by name with `id` breaking ties
+    async fn search(&self, term: &str, limit: i64, offset: i64) -> Result<Vec<User>, sqlx::Error>;
+
+    async fn count_matching(&self, term: &str) -> Result<i64, sqlx::Error>;
+
+    /// Overwrite the supplied fields and bump `version`; `None` when no live user
+    /// has this id or, if `version` is given, it no longer matches.
+    /// `phone` of `Some(None)` clears the number.
+    async fn update(
+        &self,
+        id: &str,
+        name: Option<&str>,
+        phone: Option<Option<&str>>,
+        version: Option<i32>,
+    ) -> Result<Option<User>, sqlx::Error>;
+
+    /// Remove a user; `false` when no user has this id
+    async fn delete(&self, id: &str) -> Result<bool, sqlx::Error>;
+
+    /// Hide a user from every lookup by stamping `deleted_at`; `false` when no
+    /// live user has this id
+    async fn soft_delete(&self, id: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+
+    /// Clear `deleted_at`; `None` when no soft-deleted user has this id
+    async fn restore(&self, id: &str) -> Result<Option<User>, sqlx::Error>;
+
+    /// Give the live, verified user with this (normalized) email a new role; `false`
+    /// when no such user has it, so an unconfirmed signup can't claim a role
+    async fn set_role(&self, email: &str, role: &str) -> Result<bool, sqlx::Error>;
+
+    /// Set a live user's `status` (`active` or `suspended`); `None` when no live user has this id
+    async fn set_status(&self, id: &str, status: &str) -> Result<Option<User>, sqlx::Error>;
+
+    /// Store a new (encrypted) TOTP secret awaiting confirmation, replacing an
+    /// unconfirmed one; `false` when the user is unknown or already has 2FA on
+    async fn start_totp_setup(&self, id: &str, encrypted_secret: &str) -> Result<bool, sqlx::Error>;
+
+    /// Start requiring TOTP codes at login; `false` unless a secret was stored first
+    async fn enable_totp(&self, id: &str) -> Result<bool, sqlx::Error>;
+
+    async fn update_password(&self, id: &str, password_hash: &str) -> Result<(), sqlx::Error>;
+
+    /// Count a failed login, locking the account until `lock_until` once
+    /// `max_attempts` consecutive failures are reached
+    async fn record_failed_login(&self, id: &str, max_attempts: i32, lock_until: DateTime<Utc>) -> Result<(), sqlx::Error>;
+
+    /// Stamp `last_login_at` and reset the failed-login counter and lock
+    async fn record_successful_login(&self, id: &str, now: DateTime<Utc>) -> Result<(), sqlx::Error>;
+
+    /// `record_successful_login` plus, in the same transaction, replacing the
+    /// password hash with an upgraded hash of the same password unless the
+    /// stored hash is no longer `old_hash` (the password changed meanwhile);
+    /// `false` when the hash was left alone
+    async fn record_successful_login_with_rehash(
+        &self,
+        id: &str,
+        now: DateTime<Utc>,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Append a successful login to the user's history, with the id and expiry of the token it issued
+    async fn record_login(
+        &self,
+        user_id: &str,
+        jti: &str,
+        ip: Option<&str>,
+        user_agent: Option<&str>,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// The user's logins, newest first
+    async fn list_logins(&self, user_id: &str, limit: i64, offset: i64) -> Result<Vec<LoginRecord>, sqlx::Error>;
+
+    /// The user's tokens that are neither expired nor revoked, newest first
+    async fn list_sessions(&self, user_id: &str, now: DateTime<Utc>) -> Result<Vec<SessionRecord>, sqlx::Error>;
+
+    /// One of the user's live tokens by id; `None` if it is another user's, expired or revoked
+    async fn find_session(&self, user_id: &str, jti: &str, now: DateTime<Utc>) -> Result<Option<SessionRecord>, sqlx::Error>;
+
+    async fn create_email_verification(&self, token: &str, user_id: &str, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error>;
+
+    /// Give the user `token` as its only verification link, dropping its older
+    /// links and every expired one along the way
+    async fn replace_email_verification(
+        &self,
+        user_id: &str,
+        token: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Mark the token's user verified and consume the token; `false` when the
+    /// token is unknown or expired or its user is soft-deleted
+    async fn verify_email(&self, token: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+
+    /// Record `new_email` as the user's pending email with a confirmation token,
+    /// replacing any earlier request; `false` when no live user has this id
+    async fn request_email_change(&self, user_id: &str, new_email: &str, token: &str, expires_at: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+
+    /// Make the token's pending email the user's email and consume the token;
+    /// `false` when the token is unknown or expired. Fails with a UNIQUE
+    /// violation if the address was taken in the meantime.
+    async fn confirm_email_change(&self, token: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+
+    /// Give the user `token` as its only account deletion token, dropping its
+    /// older tokens and every expired one along the way
+    async fn replace_account_deletion(
+        &self,
+        user_id: &str,
+        token: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Consume the user's deletion token; `false` when the token is unknown,
+    /// expired or belongs to someone else
+    async fn consume_account_deletion(&self, user_id: &str, token: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+
+    /// Everything stored about the user with this id, soft-deleted or not; `None` when there is no such row
+    async fn export_user(&self, id: &str) -> Result<Option<UserDataExport>, sqlx::Error>;
+
+    /// Give the user `token` as its only password reset token, dropping its
+    /// older tokens and every expired one along the way
+    async fn replace_password_reset(
+        &self,
+        user_id: &str,
+        token: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Store the new hash and consume every reset token of the token's user;
+    /// `false` when the token is unknown or expired or its user is soft-deleted
+    async fn reset_password(&self, token: &str, password_hash: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+
+    /// Reject the access token with this `jti` until it would have expired anyway
+    async fn revoke_token(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error>;
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool, sqlx::Error>;
+
+    /// Drop revocations whose tokens have expired; returns how many were removed
+    async fn delete_expired_revocations(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    /// The response saved under this client's `Idempotency-Key`, unless it has expired
+    async fn find_idempotent_response(&self, client: &str, key: &str, now: DateTime<Utc>) -> Result<Option<StoredResponse>, sqlx::Error>;
+
+    /// Save a response under this client's key until `expires_at`; a live response
+    /// already saved under the key is kept
+    async fn save_idempotent_response(&self, client: &str, key: &str, response: &StoredResponse, now: DateTime<Utc>, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error>;
+
+    /// Drop saved responses whose keys have expired; returns how many were removed
+    async fn delete_expired_idempotency_keys(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+}
+
+/// Build the `UserRepository` matching the pool's driver
+pub fn user_repository(pool: &DbPool) -> Arc<dyn UserRepository> {
+    match pool.clone() {
+        DbPool::MySql(pool) => Arc::new(MySqlUserRepository::new(pool)),
+        DbPool::Postgres(pool) => Arc::new(PgUserRepository::new(pool)),
+        DbPool::Sqlite(pool) => Arc::new(SqliteUserRepository::new(pool)),
+    }
+}
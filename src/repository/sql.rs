@@ -0,0 +1,944 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{MySqlPool, PgPool, SqlitePool};
+use std::borrow::Cow;
+use uuid::Uuid;
+
+use super::UserRepository;
+use crate::db::{escape_like, is_duplicate_entry};
+use crate::models::export::{PendingTokenRecord, UserDataExport, UserRecord};
+use crate::models::idempotency::StoredResponse;
+use crate::models::login_history::{LoginRecord, SessionRecord};
+use crate::models::pagination::UserCursor;
+use crate::models::user::{NewUser, User, UserCredentials, UserFilter, UserSort};
+
+/// MySQL and SQLite understand the `?` placeholders the queries are written with
+fn question_placeholders(query: &str) -> Cow<'_, str> {
+    Cow::Borrowed(query)
+}
+
+/// Rewrite `?` placeholders as `$1, $2, ...` for Postgres, leaving quoted literals alone
+fn numbered_placeholders(query: &str) -> Cow<'_, str> {
+    let mut rewritten = String::with_capacity(query.len() + 8);
+    let mut in_literal = false;
+    let mut index = 0;
+
+    for c in query.chars() {
+        match c {
+            '\'' => {
+                in_literal = !in_literal;
+                rewritten.push(c);
+            }
+            '?' if !in_literal => {
+                index += 1;
+                rewritten.push('$');
+                rewritten.push_str(&index.to_string());
+            }
+            _ => rewritten.push(c),
+        }
+    }
+
+    Cow::Owned(rewritten)
+}
+
+/// Rows per multi-row INSERT in `import_users`, well under every driver's bind parameter limit
+const IMPORT_BATCH_ROWS: usize = 100;
+
+/// `AND` conditions for the filters that are set, each a fixed fragment with its
+/// value bound by `bind_user_filter!` in the same order
+fn user_filter_conditions(filter: &UserFilter) -> String {
+    let mut conditions = String::new();
+    if filter.verified.is_some() {
+        conditions.push_str(" AND verified = ?");
+    }
+    if filter.status.is_some() {
+        conditions.push_str(" AND status = ?");
+    }
+    conditions
+}
+
+/// Bind the values behind `user_filter_conditions` to a query, in the same order
+macro_rules! bind_user_filter {
+    ($query:expr, $filter:expr) => {{
+        let mut query = $query;
+        if let Some(verified) = $filter.verified {
+            query = query.bind(verified);
+        }
+        if let Some(status) = $filter.status {
+            query = query.bind(status.as_str());
+        }
+        query
+    }};
+}
+
+/// Generate a `UserRepository` for one sqlx pool type.
+///
+/// Every backend shares the same SQL, written once with `?` placeholders and
+/// passed through `$placeholders` to match the driver's syntax. `$after_cursor`
+/// is the one condition that differs: "`(created_at, id)` is past the bound pair".
+macro_rules! sql_user_repository {
+    ($(#[$meta:meta])* $name:ident, $pool:ty, $placeholders:ident, $after_cursor:literal) => {
+        $(#[$meta])*
+        pub struct $name {
+            pool: $pool,
+        }
+
+        impl $name {
+            pub fn new(pool: $pool) -> Self {
+                $name { pool }
+            }
+
+            fn sql(query: &str) -> Cow<'_, str> {
+                $placeholders(query)
+            }
+        }
+
+        #[async_trait]
+        impl UserRepository for $name {
+            async fn create(&self, user: &NewUser) -> Result<User, sqlx::Error> {
+                sqlx::query(&Self::sql("INSERT INTO users (id, name, email, username, phone, password) VALUES (?, ?, ?, ?, ?, ?)"))
+                    .bind(&user.id)
+                    .bind(&user.name)
+                    .bind(&user.email)
+                    .bind(&user.username)
+                    .bind(&user.phone)
+                    .bind(&user.password_hash)
+                    .execute(&self.pool)
+                    .await?;
+
+                // Read back the stored row so callers get DB-generated timestamps
+                sqlx::query_as::<_, User>(&Self::sql("SELECT id, name, email, username, role, created_at, updated_at, last_login_at, status, version, phone FROM users WHERE id = ?"))
+                    .bind(&user.id)
+                    .fetch_one(&self.pool)
+                    .await
+            }
+
+            async fn create_with_verification(&self, user: &NewUser, token: &str, expires_at: DateTime<Utc>) -> Result<User, sqlx::Error> {
+                // Dropping `tx` on an early `?` rolls both inserts back
+                let mut tx = self.pool.begin().await?;
+
+                sqlx::query(&Self::sql("INSERT INTO users (id, name, email, username, phone, password) VALUES (?, ?, ?, ?, ?, ?)"))
+                    .bind(&user.id)
+                    .bind(&user.name)
+                    .bind(&user.email)
+                    .bind(&user.username)
+                    .bind(&user.phone)
+                    .bind(&user.password_hash)
+                    .execute(&mut *tx)
+                    .await?;
+
+                sqlx::query(&Self::sql("INSERT INTO email_verifications (token, user_id, expires_at) VALUES (?, ?, ?)"))
+                    .bind(token)
+                    .bind(&user.id)
+                    .bind(expires_at)
+                    .execute(&mut *tx)
+                    .await?;
+
+                let created = sqlx::query_as::<_, User>(&Self::sql("SELECT id, name, email, username, role, created_at, updated_at, last_login_at, status, version, phone FROM users WHERE id = ?"))
+                    .bind(&user.id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(created)
+            }
+
+            async fn import_users(&self, users: &[NewUser]) -> Result<(), sqlx::Error> {
+                // Dropping `tx` on an early `?` rolls every batch back
+                let mut tx = self.pool.begin().await?;
+
+                for batch in users.chunks(IMPORT_BATCH_ROWS) {
+                    let rows = vec!["(?, ?, ?, ?, ?, ?, TRUE)"; batch.len()].join(", ");
+                    let query = format!("INSERT INTO users (id, name, email, username, phone, password, verified) VALUES {}", rows);
+
+                    let sql = Self::sql(&query);
+                    let mut insert = sqlx::query(&sql);
+                    for user in batch {
+                        insert = insert
+                            .bind(&user.id)
+                            .bind(&user.name)
+                            .bind(&user.email)
+                            .bind(&user.username)
+                            .bind(&user.phone)
+                            .bind(&user.password_hash);
+                    }
+                    insert.execute(&mut *tx).await?;
+                }
+
+                tx.commit().await
+            }
+
+            async fn find_by_id(&self, id: &str) -> Result<Option<User>, sqlx::Error> {
+                sqlx::query_as::<_, User>(
+                    &Self::sql("SELECT id, name, email, username, role, created_at, updated_at, last_login_at, status, version, phone FROM users WHERE id = ? AND deleted_at IS NULL")
+                )
+                    .bind(id)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn find_by_email(&self, email: &str) -> Result<Option<UserCredentials>, sqlx::Error> {
+                sqlx::query_as::<_, UserCredentials>(
+                    &Self::sql("SELECT id, email, password, role, verified, failed_attempts, locked_until, status, totp_secret, totp_enabled FROM users \
+                     WHERE email_lower = LOWER(?) AND deleted_at IS NULL")
+                )
+                    .bind(email)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn email_exists(&self, email: &str) -> Result<bool, sqlx::Error> {
+                let count = sqlx::query_scalar::<_, i64>(&Self::sql("SELECT COUNT(*) FROM users WHERE email_lower = LOWER(?)"))
+                    .bind(email)
+                    .fetch_one(&self.pool)
+                    .await?;
+
+                Ok(count > 0)
+            }
+
+            async fn find_by_username(&self, username: &str) -> Result<Option<UserCredentials>, sqlx::Error> {
+                sqlx::query_as::<_, UserCredentials>(
+                    &Self::sql("SELECT id, email, password, role, verified, failed_attempts, locked_until, status, totp_secret, totp_enabled FROM users \
+                     WHERE username = ? AND deleted_at IS NULL")
+                )
+                    .bind(username)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn username_exists(&self, username: &str) -> Result<bool, sqlx::Error> {
+                let count = sqlx::query_scalar::<_, i64>(&Self::sql("SELECT COUNT(*) FROM users WHERE username = ?"))
+                    .bind(username)
+                    .fetch_one(&self.pool)
+                    .await?;
+
+                Ok(count > 0)
+            }
+
+            async fn find_credentials_by_id(&self, id: &str) -> Result<Option<UserCredentials>, sqlx::Error> {
+                sqlx::query_as::<_, UserCredentials>(
+                    &Self::sql("SELECT id, email, password, role, verified, failed_attempts, locked_until, status, totp_secret, totp_enabled FROM users \
+                     WHERE id = ? AND deleted_at IS NULL")
+                )
+                    .bind(id)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn account_status(&self, id: &str) -> Result<Option<String>, sqlx::Error> {
+                sqlx::query_scalar::<_, String>(&Self::sql("SELECT status FROM users WHERE id = ? AND deleted_at IS NULL"))
+                    .bind(id)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn list(&self, filter: &UserFilter, limit: i64, offset: i64, sort: UserSort) -> Result<Vec<User>, sqlx::Error> {
+                // ORDER BY can't be a bind parameter, so only whitelisted orderings are spliced in
+                let order = match sort {
+                    UserSort::NameAsc => "name ASC",
+                    UserSort::NameDesc => "name DESC",
+                    UserSort::CreatedAtAsc => "created_at ASC",
+                    UserSort::CreatedAtDesc => "created_at DESC",
+                };
+                let query = format!(
+                    "SELECT id, name, email, username, role, created_at, updated_at, last_login_at, status, version, phone FROM users \
+                     WHERE deleted_at IS NULL{} ORDER BY {}, id ASC LIMIT ? OFFSET ?",
+                    user_filter_conditions(filter),
+                    order
+                );
+
+                let query = Self::sql(&query);
+                bind_user_filter!(sqlx::query_as::<_, User>(&query), filter)
+                    .bind(limit)
+                    .bind(offset)
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn count(&self, filter: &UserFilter) -> Result<i64, sqlx::Error> {
+                let query = format!("SELECT COUNT(*) FROM users WHERE deleted_at IS NULL{}", user_filter_conditions(filter));
+                let query = Self::sql(&query);
+                bind_user_filter!(sqlx::query_scalar::<_, i64>(&query), filter)
+                    .fetch_one(&self.pool)
+                    .await
+            }
+
+            async fn list_after(&self, after: &str, limit: i64) -> Result<Vec<User>, sqlx::Error> {
+                sqlx::query_as::<_, User>(
+                    &Self::sql("SELECT id, name, email, username, role, created_at, updated_at, last_login_at, status, version, phone FROM users \
+                     WHERE deleted_at IS NULL AND id > ? ORDER BY id ASC LIMIT ?")
+                )
+                    .bind(after)
+                    .bind(limit)
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn list_after_cursor(&self, filter: &UserFilter, cursor: &UserCursor, limit: i64) -> Result<Vec<User>, sqlx::Error> {
+                let query = format!(
+                    "SELECT id, name, email, username, role, created_at, updated_at, last_login_at, status, version, phone FROM users \
+                     WHERE deleted_at IS NULL{} AND {} ORDER BY created_at ASC, id ASC LIMIT ?",
+                    user_filter_conditions(filter),
+                    $after_cursor
+                );
+
+                let query = Self::sql(&query);
+                bind_user_filter!(sqlx::query_as::<_, User>(&query), filter)
+                    .bind(cursor.created_at)
+                    .bind(&cursor.id)
+                    .bind(limit)
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn search(&self, term: &str, limit: i64, offset: i64) -> Result<Vec<User>, sqlx::Error> {
+                // Escape LIKE wildcards so a search for `100%` is matched literally
+                let pattern = format!("%{}%", escape_like(term));
+
+                sqlx::query_as::<_, User>(
+                    &Self::sql("SELECT id, name, email, username, role, created_at, updated_at, last_login_at, status, version, phone FROM users \
+                     WHERE deleted_at IS NULL AND (name LIKE ? ESCAPE '!' OR email LIKE ? ESCAPE '!') \
+                     ORDER BY name ASC, id ASC LIMIT ? OFFSET ?")
+                )
+                    .bind(&pattern)
+                    .bind(&pattern)
+                    .bind(limit)
+                    .bind(offset)
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn count_matching(&self, term: &str) -> Result<i64, sqlx::Error> {
+                let pattern = format!("%{}%", escape_like(term));
+
+                sqlx::query_scalar::<_, i64>(
+                    &Self::sql("SELECT COUNT(*) FROM users WHERE deleted_at IS NULL AND (name LIKE ? ESCAPE '!' OR email LIKE ? ESCAPE '!')")
+                )
+                    .bind(&pattern)
+                    .bind(&pattern)
+                    .fetch_one(&self.pool)
+                    .await
+            }
+
+            async fn update(
+                &self,
+                id: &str,
+                name: Option<&str>,
+                phone: Option<Option<&str>>,
+                version: Option<i32>,
+            ) -> Result<Option<User>, sqlx::Error> {
+                // COALESCE can't tell "keep" from "clear" for the phone, so it gets an explicit flag
+                let result = sqlx::query(
+                    &Self::sql("UPDATE users SET name = COALESCE(?, name), phone = CASE WHEN ? THEN ? ELSE phone END, \
+                     version = version + 1 WHERE id = ? AND deleted_at IS NULL AND (? IS NULL OR version = ?)")
+                )
+                    .bind(name)
+                    .bind(phone.is_some())
+                    .bind(phone.flatten())
+                    .bind(id)
+                    .bind(version)
+                    .bind(version)
+                    .execute(&self.pool)
+                    .await?;
+
+                if result.rows_affected() == 0 {
+                    return Ok(None);
+                }
+
+                self.find_by_id(id).await
+            }
+
+            async fn delete(&self, id: &str) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query(&Self::sql("DELETE FROM users WHERE id = ?"))
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn soft_delete(&self, id: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query(&Self::sql("UPDATE users SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL"))
+                    .bind(now)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn restore(&self, id: &str) -> Result<Option<User>, sqlx::Error> {
+                let result = sqlx::query(&Self::sql("UPDATE users SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL"))
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                if result.rows_affected() == 0 {
+                    return Ok(None);
+                }
+
+                self.find_by_id(id).await
+            }
+
+            async fn set_role(&self, email: &str, role: &str) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query(&Self::sql("UPDATE users SET role = ? WHERE email_lower = LOWER(?) AND verified = TRUE AND deleted_at IS NULL"))
+                    .bind(role)
+                    .bind(email)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn set_status(&self, id: &str, status: &str) -> Result<Option<User>, sqlx::Error> {
+                let result = sqlx::query(&Self::sql("UPDATE users SET status = ? WHERE id = ? AND deleted_at IS NULL"))
+                    .bind(status)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                if result.rows_affected() == 0 {
+                    return Ok(None);
+                }
+
+                self.find_by_id(id).await
+            }
+
+            async fn start_totp_setup(&self, id: &str, encrypted_secret: &str) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query(
+                    &Self::sql("UPDATE users SET totp_secret = ? WHERE id = ? AND totp_enabled = FALSE AND deleted_at IS NULL")
+                )
+                    .bind(encrypted_secret)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn enable_totp(&self, id: &str) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query(
+                    &Self::sql("UPDATE users SET totp_enabled = TRUE WHERE id = ? AND totp_secret IS NOT NULL AND deleted_at IS NULL")
+                )
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn update_password(&self, id: &str, password_hash: &str) -> Result<(), sqlx::Error> {
+                sqlx::query(&Self::sql("UPDATE users SET password = ? WHERE id = ?"))
+                    .bind(password_hash)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(())
+            }
+
+            async fn record_failed_login(&self, id: &str, max_attempts: i32, lock_until: DateTime<Utc>) -> Result<(), sqlx::Error> {
+                // Both CASEs read the pre-update counter (MySQL assigns left to right,
+                // so `locked_until` must come first), giving the same result everywhere
+                sqlx::query(
+                    &Self::sql("UPDATE users SET \
+                     locked_until = CASE WHEN failed_attempts + 1 >= ? THEN ? ELSE locked_until END, \
+                     failed_attempts = CASE WHEN failed_attempts + 1 >= ? THEN 0 ELSE failed_attempts + 1 END \
+                     WHERE id = ?")
+                )
+                    .bind(max_attempts)
+                    .bind(lock_until)
+                    .bind(max_attempts)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(())
+            }
+
+            async fn record_successful_login(&self, id: &str, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+                sqlx::query(&Self::sql("UPDATE users SET last_login_at = ?, failed_attempts = 0, locked_until = NULL WHERE id = ?"))
+                    .bind(now)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(())
+            }
+
+            async fn record_successful_login_with_rehash(
+                &self,
+                id: &str,
+                now: DateTime<Utc>,
+                old_hash: &str,
+                new_hash: &str,
+            ) -> Result<bool, sqlx::Error> {
+                let mut tx = self.pool.begin().await?;
+
+                sqlx::query(&Self::sql("UPDATE users SET last_login_at = ?, failed_attempts = 0, locked_until = NULL WHERE id = ?"))
+                    .bind(now)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                // Compare-and-swap on the old hash, so a password changed since it was read is kept
+                let rehashed = sqlx::query(&Self::sql("UPDATE users SET password = ? WHERE id = ? AND password = ?"))
+                    .bind(new_hash)
+                    .bind(id)
+                    .bind(old_hash)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(rehashed.rows_affected() > 0)
+            }
+
+            async fn record_login(
+                &self,
+                user_id: &str,
+                jti: &str,
+                ip: Option<&str>,
+                user_agent: Option<&str>,
+                now: DateTime<Utc>,
+                expires_at: DateTime<Utc>,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query(&Self::sql(
+                    "INSERT INTO login_history (id, user_id, jti, ip, user_agent, created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+                ))
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(user_id)
+                    .bind(jti)
+                    .bind(ip)
+                    .bind(user_agent)
+                    .bind(now)
+                    .bind(expires_at)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(())
+            }
+
+            async fn list_logins(&self, user_id: &str, limit: i64, offset: i64) -> Result<Vec<LoginRecord>, sqlx::Error> {
+                sqlx::query_as::<_, LoginRecord>(
+                    &Self::sql("SELECT id, ip, user_agent, created_at FROM login_history \
+                     WHERE user_id = ? ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?")
+                )
+                    .bind(user_id)
+                    .bind(limit)
+                    .bind(offset)
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn list_sessions(&self, user_id: &str, now: DateTime<Utc>) -> Result<Vec<SessionRecord>, sqlx::Error> {
+                // Logins from before tokens were recorded have no jti and can't be listed
+                sqlx::query_as::<_, SessionRecord>(
+                    &Self::sql("SELECT jti, ip, user_agent, created_at AS issued_at, expires_at FROM login_history \
+                     WHERE user_id = ? AND jti IS NOT NULL AND expires_at > ? \
+                     AND NOT EXISTS (SELECT 1 FROM revoked_tokens WHERE revoked_tokens.jti = login_history.jti) \
+                     ORDER BY created_at DESC, id DESC")
+                )
+                    .bind(user_id)
+                    .bind(now)
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn find_session(&self, user_id: &str, jti: &str, now: DateTime<Utc>) -> Result<Option<SessionRecord>, sqlx::Error> {
+                sqlx::query_as::<_, SessionRecord>(
+                    &Self::sql("SELECT jti, ip, user_agent, created_at AS issued_at, expires_at FROM login_history \
+                     WHERE user_id = ? AND jti = ? AND expires_at > ? \
+                     AND NOT EXISTS (SELECT 1 FROM revoked_tokens WHERE revoked_tokens.jti = login_history.jti)")
+                )
+                    .bind(user_id)
+                    .bind(jti)
+                    .bind(now)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn create_email_verification(&self, token: &str, user_id: &str, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+                sqlx::query(&Self::sql("INSERT INTO email_verifications (token, user_id, expires_at) VALUES (?, ?, ?)"))
+                    .bind(token)
+                    .bind(user_id)
+                    .bind(expires_at)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(())
+            }
+
+            async fn replace_email_verification(
+                &self,
+                user_id: &str,
+                token: &str,
+                expires_at: DateTime<Utc>,
+                now: DateTime<Utc>,
+            ) -> Result<(), sqlx::Error> {
+                // The old links stay valid if the insert fails
+                let mut tx = self.pool.begin().await?;
+
+                sqlx::query(&Self::sql("DELETE FROM email_verifications WHERE user_id = ? OR expires_at <= ?"))
+                    .bind(user_id)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+
+                sqlx::query(&Self::sql("INSERT INTO email_verifications (token, user_id, expires_at) VALUES (?, ?, ?)"))
+                    .bind(token)
+                    .bind(user_id)
+                    .bind(expires_at)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await
+            }
+
+            async fn verify_email(&self, token: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+                let mut tx = self.pool.begin().await?;
+
+                let user_id = sqlx::query_scalar::<_, String>(
+                    &Self::sql("SELECT user_id FROM email_verifications WHERE token = ? AND expires_at > ?")
+                )
+                    .bind(token)
+                    .bind(now)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+
+                let Some(user_id) = user_id else {
+                    return Ok(false);
+                };
+
+                // A token outliving its soft-deleted user verifies nothing
+                let result = sqlx::query(&Self::sql("UPDATE users SET verified = TRUE WHERE id = ? AND deleted_at IS NULL"))
+                    .bind(&user_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                if result.rows_affected() == 0 {
+                    return Ok(false);
+                }
+
+                sqlx::query(&Self::sql("DELETE FROM email_verifications WHERE token = ?"))
+                    .bind(token)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(true)
+            }
+
+            async fn request_email_change(&self, user_id: &str, new_email: &str, token: &str, expires_at: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+                let mut tx = self.pool.begin().await?;
+
+                let result = sqlx::query(&Self::sql("UPDATE users SET pending_email = ? WHERE id = ? AND deleted_at IS NULL"))
+                    .bind(new_email)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                if result.rows_affected() == 0 {
+                    return Ok(false);
+                }
+
+                // Only the latest request can be confirmed
+                sqlx::query(&Self::sql("DELETE FROM email_change_tokens WHERE user_id = ?"))
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                sqlx::query(&Self::sql("INSERT INTO email_change_tokens (token, user_id, expires_at) VALUES (?, ?, ?)"))
+                    .bind(token)
+                    .bind(user_id)
+                    .bind(expires_at)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(true)
+            }
+
+            async fn confirm_email_change(&self, token: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+                let mut tx = self.pool.begin().await?;
+
+                let user_id = sqlx::query_scalar::<_, String>(
+                    &Self::sql("SELECT user_id FROM email_change_tokens WHERE token = ? AND expires_at > ?")
+                )
+                    .bind(token)
+                    .bind(now)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+
+                let Some(user_id) = user_id else {
+                    return Ok(false);
+                };
+
+                let result = sqlx::query(&Self::sql("UPDATE users SET email = pending_email, pending_email = NULL \
+                     WHERE id = ? AND pending_email IS NOT NULL AND deleted_at IS NULL"))
+                    .bind(&user_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                sqlx::query(&Self::sql("DELETE FROM email_change_tokens WHERE user_id = ?"))
+                    .bind(&user_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn export_user(&self, id: &str) -> Result<Option<UserDataExport>, sqlx::Error> {
+                // One transaction keeps every read on a single connection
+                let mut tx = self.pool.begin().await?;
+
+                let profile = sqlx::query_as::<_, UserRecord>(
+                    &Self::sql("SELECT id, name, email, username, pending_email, phone, role, status, version, verified, totp_enabled, failed_attempts, \
+                     locked_until, created_at, updated_at, last_login_at, deleted_at FROM users WHERE id = ?")
+                )
+                    .bind(id)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+
+                let Some(profile) = profile else {
+                    return Ok(None);
+                };
+
+                let email_verifications = sqlx::query_as::<_, PendingTokenRecord>(
+                    &Self::sql("SELECT expires_at FROM email_verifications WHERE user_id = ? ORDER BY expires_at, token")
+                )
+                    .bind(id)
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+                let password_resets = sqlx::query_as::<_, PendingTokenRecord>(
+                    &Self::sql("SELECT expires_at FROM password_resets WHERE user_id = ? ORDER BY expires_at, token")
+                )
+                    .bind(id)
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+                let email_changes = sqlx::query_as::<_, PendingTokenRecord>(
+                    &Self::sql("SELECT expires_at FROM email_change_tokens WHERE user_id = ? ORDER BY expires_at, token")
+                )
+                    .bind(id)
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+                let logins = sqlx::query_as::<_, LoginRecord>(
+                    &Self::sql("SELECT id, ip, user_agent, created_at FROM login_history WHERE user_id = ? ORDER BY created_at DESC, id DESC")
+                )
+                    .bind(id)
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(Some(UserDataExport { profile, logins, email_verifications, password_resets, email_changes }))
+            }
+
+            async fn replace_account_deletion(
+                &self,
+                user_id: &str,
+                token: &str,
+                expires_at: DateTime<Utc>,
+                now: DateTime<Utc>,
+            ) -> Result<(), sqlx::Error> {
+                let mut tx = self.pool.begin().await?;
+
+                sqlx::query(&Self::sql("DELETE FROM account_deletion_tokens WHERE user_id = ? OR expires_at <= ?"))
+                    .bind(user_id)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+
+                sqlx::query(&Self::sql("INSERT INTO account_deletion_tokens (token, user_id, expires_at) VALUES (?, ?, ?)"))
+                    .bind(token)
+                    .bind(user_id)
+                    .bind(expires_at)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await
+            }
+
+            async fn consume_account_deletion(&self, user_id: &str, token: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+                // A single DELETE, so two concurrent confirmations can't both use the token
+                let result = sqlx::query(&Self::sql("DELETE FROM account_deletion_tokens WHERE token = ? AND user_id = ? AND expires_at > ?"))
+                    .bind(token)
+                    .bind(user_id)
+                    .bind(now)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn replace_password_reset(
+                &self,
+                user_id: &str,
+                token: &str,
+                expires_at: DateTime<Utc>,
+                now: DateTime<Utc>,
+            ) -> Result<(), sqlx::Error> {
+                let mut tx = self.pool.begin().await?;
+
+                sqlx::query(&Self::sql("DELETE FROM password_resets WHERE user_id = ? OR expires_at <= ?"))
+                    .bind(user_id)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+
+                sqlx::query(&Self::sql("INSERT INTO password_resets (token, user_id, expires_at) VALUES (?, ?, ?)"))
+                    .bind(token)
+                    .bind(user_id)
+                    .bind(expires_at)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await
+            }
+
+            async fn reset_password(&self, token: &str, password_hash: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+                let mut tx = self.pool.begin().await?;
+
+                let user_id = sqlx::query_scalar::<_, String>(
+                    &Self::sql("SELECT user_id FROM password_resets WHERE token = ? AND expires_at > ?")
+                )
+                    .bind(token)
+                    .bind(now)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+
+                let Some(user_id) = user_id else {
+                    return Ok(false);
+                };
+
+                // A soft-deleted user's password stays as it was
+                let result = sqlx::query(&Self::sql("UPDATE users SET password = ? WHERE id = ? AND deleted_at IS NULL"))
+                    .bind(password_hash)
+                    .bind(&user_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                if result.rows_affected() == 0 {
+                    return Ok(false);
+                }
+
+                // Tokens are single-use: drop this one and any other outstanding ones for the user
+                sqlx::query(&Self::sql("DELETE FROM password_resets WHERE user_id = ?"))
+                    .bind(&user_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(true)
+            }
+
+            async fn revoke_token(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+                // Logging out twice with the same token is not an error, so ignore duplicates
+                let result = sqlx::query(&Self::sql("INSERT INTO revoked_tokens (jti, expires_at) VALUES (?, ?)"))
+                    .bind(jti)
+                    .bind(expires_at)
+                    .execute(&self.pool)
+                    .await;
+
+                match result {
+                    Err(e) if !is_duplicate_entry(&e) => Err(e),
+                    _ => Ok(()),
+                }
+            }
+
+            async fn is_token_revoked(&self, jti: &str) -> Result<bool, sqlx::Error> {
+                let count = sqlx::query_scalar::<_, i64>(&Self::sql("SELECT COUNT(*) FROM revoked_tokens WHERE jti = ?"))
+                    .bind(jti)
+                    .fetch_one(&self.pool)
+                    .await?;
+
+                Ok(count > 0)
+            }
+
+            async fn delete_expired_revocations(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+                let result = sqlx::query(&Self::sql("DELETE FROM revoked_tokens WHERE expires_at <= ?"))
+                    .bind(now)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(result.rows_affected())
+            }
+
+            async fn find_idempotent_response(&self, client: &str, key: &str, now: DateTime<Utc>) -> Result<Option<StoredResponse>, sqlx::Error> {
+                sqlx::query_as::<_, StoredResponse>(
+                    &Self::sql("SELECT request_hash, status_code, response_body, location FROM idempotency_keys \
+                     WHERE client = ? AND idempotency_key = ? AND expires_at > ?")
+                )
+                    .bind(client)
+                    .bind(key)
+                    .bind(now)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn save_idempotent_response(&self, client: &str, key: &str, response: &StoredResponse, now: DateTime<Utc>, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+                // An expired row the cleanup task hasn't reached yet must not block the key's reuse
+                sqlx::query(&Self::sql("DELETE FROM idempotency_keys WHERE client = ? AND idempotency_key = ? AND expires_at <= ?"))
+                    .bind(client)
+                    .bind(key)
+                    .bind(now)
+                    .execute(&self.pool)
+                    .await?;
+
+                // A concurrent request that saved first wins, so ignore duplicates
+                let result = sqlx::query(&Self::sql("INSERT INTO idempotency_keys (client, idempotency_key, request_hash, status_code, response_body, location, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?)"))
+                    .bind(client)
+                    .bind(key)
+                    .bind(&response.request_hash)
+                    .bind(response.status_code)
+                    .bind(&response.response_body)
+                    .bind(&response.location)
+                    .bind(expires_at)
+                    .execute(&self.pool)
+                    .await;
+
+                match result {
+                    Err(e) if !is_duplicate_entry(&e) => Err(e),
+                    _ => Ok(()),
+                }
+            }
+
+            async fn delete_expired_idempotency_keys(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+                let result = sqlx::query(&Self::sql("DELETE FROM idempotency_keys WHERE expires_at <= ?"))
+                    .bind(now)
+                    .execute(&self.pool)
+                    .await?;
+
+                Ok(result.rows_affected())
+            }
+        }
+    };
+}
+
+sql_user_repository!(
+    /// `UserRepository` backed by a MySQL connection pool
+    MySqlUserRepository,
+    MySqlPool,
+    question_placeholders,
+    "(created_at, id) > (?, ?)"
+);
+
+sql_user_repository!(
+    /// `UserRepository` backed by a Postgres connection pool
+    PgUserRepository,
+    PgPool,
+    numbered_placeholders,
+    "(created_at, id) > (?, ?)"
+);
+
+sql_user_repository!(
+    /// `UserRepository` backed by a SQLite connection pool
+    SqliteUserRepository,
+    SqlitePool,
+    question_placeholders,
+    // Timestamps are text here, and a bound `DateTime` isn't written like the `CURRENT_TIMESTAMP`
+    // default, so both sides are normalized before they are compared
+    "(strftime('%Y-%m-%d %H:%M:%f', created_at), id) > (strftime('%Y-%m-%d %H:%M:%f', ?), ?)"
+);
@@ -0,0 +1,69 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::error::AppError;
+use crate::extractors::Normalize;
+use crate::models::user::{normalize_email, NewUser, RegisterRequest};
+use crate::password::PasswordHasher;
+use crate::repository::UserRepository;
+use crate::tokens::generate_token;
+
+/// Role allowed onto admin-only endpoints (new registrations get `user`)
+pub const ADMIN: &str = "admin";
+
+/// Promote the account named by `ADMIN_EMAIL` to admin, if the variable is set.
+///
+/// The account must already be registered and verified, so whoever signs up
+/// with the address first can't become admin before its owner confirms it.
+/// This runs at every startup, so it is also how a lost admin role is restored.
+pub async fn seed_admin(users: &dyn UserRepository, admin_email: Option<&str>) -> Result<(), sqlx::Error> {
+    let Some(email) = admin_email else {
+        return Ok(());
+    };
+    let email = normalize_email(email);
+
+    if users.set_role(&email, ADMIN).await? {
+        tracing::info!(email = %email, "Granted admin role");
+    } else {
+        tracing::warn!(email = %email, "ADMIN_EMAIL does not match any verified user");
+    }
+
+    Ok(())
+}
+
+/// Create a verified admin account, for bootstrapping a fresh deployment
+/// (`seed-admin`); returns `false` without changing anything when the email or
+/// username is already taken.
+///
+/// The account goes through the same normalization, validation and insert as
+/// `POST /register`; its verification token is consumed straight away.
+pub async fn create_admin(
+    users: &dyn UserRepository,
+    hasher: &PasswordHasher,
+    mut request: RegisterRequest,
+) -> Result<bool, AppError> {
+    request.normalize();
+    request.validate()?;
+
+    if users.email_exists(&request.email).await? || users.username_exists(&request.username).await? {
+        return Ok(false);
+    }
+
+    let token = generate_token();
+    let user = NewUser {
+        id: Uuid::new_v4().to_string(),
+        name: request.name,
+        email: request.email,
+        username: request.username,
+        phone: request.phone,
+        password_hash: hasher.hash(&request.password)?,
+    };
+    let now = Utc::now();
+
+    users.create_with_verification(&user, &token, now + Duration::hours(1)).await?;
+    users.verify_email(&token, now).await?;
+    users.set_role(&user.email, ADMIN).await?;
+
+    Ok(true)
+}
@@ -0,0 +1,67 @@
+use std::env;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// How log lines are written (`LOG_FORMAT`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl LogFormat {
+    /// Map a `LOG_FORMAT` value to a format; unset or empty means text
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("LOG_FORMAT must be json or text, got {:?}", value)),
+        }
+    }
+}
+
+/// Build the subscriber `init` installs, writing to `writer`.
+///
+/// JSON lines carry the event's fields and message at the top level and the
+/// enclosing request span (`request_id`, `method`, `path`) under `span`.
+pub fn subscriber<W>(format: LogFormat, filter: EnvFilter, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Json => Box::new(
+            tracing_subscriber::fmt()
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_env_filter(filter)
+                .with_writer(writer)
+                .finish(),
+        ),
+        LogFormat::Text => Box::new(tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer).finish()),
+    }
+}
+
+/// Install the global tracing subscriber, writing to stdout.
+///
+/// Verbosity comes from `RUST_LOG` (full filter syntax) or, failing that,
+/// `LOG_LEVEL` (e.g. `debug`), defaulting to `info`. `LOG_FORMAT` picks
+/// `text` (the default) or `json`; any other value is an error, returned
+/// before anything is installed since there is nowhere to log it yet.
+pub fn init() -> Result<(), String> {
+    let format = LogFormat::parse(&env::var("LOG_FORMAT").unwrap_or_default())?;
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let level = env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string());
+        EnvFilter::new(level)
+    });
+
+    subscriber(format, filter, std::io::stdout).init();
+    Ok(())
+}
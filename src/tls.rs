@@ -0,0 +1,53 @@
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Build the rustls server config from a PEM certificate chain and private key.
+///
+/// Only TLS 1.3 and TLS 1.2 are offered (rustls implements nothing older), so
+/// TLS 1.2 is the minimum version clients can negotiate.
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<ServerConfig, String> {
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13, &rustls::version::TLS12])
+        .map_err(|e| format!("Failed to configure TLS: {}", e))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| {
+            format!(
+                "{} and {} are not a usable certificate and key: {}",
+                cert_path.display(),
+                key_path.display(),
+                e
+            )
+        })
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("No PEM certificates found in {}", path.display()));
+    }
+
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    rustls_pemfile::private_key(&mut open(path)?)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+        .ok_or_else(|| format!("No PEM private key found in {}", path.display()))
+}
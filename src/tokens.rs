@@ -0,0 +1,14 @@
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+/// Length of generated one-time tokens (~190 bits of entropy)
+const TOKEN_LENGTH: usize = 32;
+
+/// Generate a random URL-safe token for one-time links (verification, resets)
+pub fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
@@ -0,0 +1,90 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use totp_rs::{Algorithm, TOTP};
+
+/// Issuer shown next to the account in authenticator apps
+pub const TOTP_ISSUER: &str = "rust_learning";
+
+/// Bytes of a generated TOTP secret (160 bits, as RFC 4226 recommends)
+const SECRET_LEN: usize = 20;
+
+/// Encrypts TOTP secrets at rest with the AES-256-GCM key from `TOTP_ENCRYPTION_KEY`.
+///
+/// Each secret is sealed with a fresh random nonce and bound to its user id,
+/// so a ciphertext copied onto another account fails to decrypt.
+pub struct TotpCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl TotpCipher {
+    /// Build the cipher from a base64 encoded 32-byte key
+    pub fn from_base64(encoded: &str) -> Result<Self, String> {
+        let bytes = STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| "TOTP_ENCRYPTION_KEY must be base64".to_string())?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| "TOTP_ENCRYPTION_KEY must decode to exactly 32 bytes".to_string())?;
+
+        Ok(TotpCipher { key: LessSafeKey::new(key), rng: SystemRandom::new() })
+    }
+
+    /// A new random secret for an authenticator app
+    pub fn generate_secret(&self) -> Result<Vec<u8>, String> {
+        let mut secret = vec![0; SECRET_LEN];
+        self.rng.fill(&mut secret).map_err(|_| "Failed to generate a TOTP secret".to_string())?;
+        Ok(secret)
+    }
+
+    /// Seal `secret` for `user_id` as base64 of nonce, ciphertext and tag
+    pub fn encrypt(&self, user_id: &str, secret: &[u8]) -> Result<String, String> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| "Failed to generate a nonce".to_string())?;
+
+        let mut sealed = secret.to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(user_id.as_bytes()), &mut sealed)
+            .map_err(|_| "Failed to encrypt the TOTP secret".to_string())?;
+
+        let mut stored = nonce.to_vec();
+        stored.extend_from_slice(&sealed);
+        Ok(STANDARD.encode(stored))
+    }
+
+    /// Open a value produced by `encrypt` for the same user
+    pub fn decrypt(&self, user_id: &str, stored: &str) -> Result<Vec<u8>, String> {
+        let bytes = STANDARD.decode(stored).map_err(|_| "Stored TOTP secret is not base64".to_string())?;
+        if bytes.len() < NONCE_LEN {
+            return Err("Stored TOTP secret is truncated".to_string());
+        }
+
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Stored TOTP nonce is invalid".to_string())?;
+        let mut sealed = sealed.to_vec();
+        let secret = self
+            .key
+            .open_in_place(nonce, Aad::from(user_id.as_bytes()), &mut sealed)
+            .map_err(|_| "Failed to decrypt the TOTP secret; was TOTP_ENCRYPTION_KEY changed?".to_string())?;
+
+        Ok(secret.to_vec())
+    }
+}
+
+/// The 6-digit, 30-second SHA-1 generator every common authenticator app supports.
+///
+/// One step of clock skew is accepted either way.
+pub fn totp(secret: Vec<u8>, account: &str) -> Result<TOTP, String> {
+    // `:` separates issuer and account in otpauth labels
+    let account = account.replace(':', "_");
+    TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, Some(TOTP_ISSUER.to_string()), account)
+        .map_err(|e| format!("Invalid TOTP parameters: {}", e))
+}
+
+/// Whether `code` is the current code for the secret `cipher` sealed for `user_id`
+pub fn verify_code(cipher: &TotpCipher, user_id: &str, account: &str, stored: &str, code: &str) -> Result<bool, String> {
+    let secret = cipher.decrypt(user_id, stored)?;
+    totp(secret, account)?
+        .check_current(code.trim())
+        .map_err(|e| format!("System clock is before the Unix epoch: {}", e))
+}
@@ -0,0 +1,27 @@
+use std::io::ErrorKind;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+/// Delete the socket file a previous run left at `path`, so `bind_uds` can create it again.
+///
+/// Anything that isn't a socket is left alone, and so is a socket another
+/// server still accepts connections on; both are reported as errors.
+pub fn remove_stale_socket(path: &Path) -> Result<(), String> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Cannot inspect {}: {}", path.display(), e)),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(format!("{} exists and is not a socket; refusing to replace it", path.display()));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(format!("{} is in use by another running server", path.display()));
+    }
+
+    std::fs::remove_file(path).map_err(|e| format!("Cannot remove stale socket {}: {}", path.display(), e))?;
+    tracing::info!(path = %path.display(), "Removed stale Unix socket");
+    Ok(())
+}
@@ -0,0 +1,3050 @@
+//! End-to-end tests that drive the full Actix app against an in-memory SQLite database
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_http::Request;
+use actix_web::http::{header, StatusCode};
+use actix_web::middleware::{from_fn, Compress};
+use actix_web::{test, web, App, HttpResponse};
+use chrono::Duration;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+use hello_resut_1::app::{configure, AppState};
+use hello_resut_1::blocklist::{DomainAllowlist, DomainBlocklist};
+use hello_resut_1::breach::BreachChecker;
+use hello_resut_1::client_ip::ProxyConfig;
+use hello_resut_1::config::DeleteMode;
+use hello_resut_1::db::{is_duplicate_entry, DbPool, RetryPolicy};
+use hello_resut_1::error::ErrorFormat;
+use hello_resut_1::handlers::import::ImportLimit;
+use hello_resut_1::jwt::JwtConfig;
+use hello_resut_1::lockout::LockoutPolicy;
+use hello_resut_1::mailer::Mailer;
+use hello_resut_1::maintenance::MaintenanceMode;
+use hello_resut_1::metrics::Metrics;
+use hello_resut_1::models::pagination::PageSize;
+use hello_resut_1::models::user::{NewUser, RegisterRequest};
+use hello_resut_1::middleware::error_format::negotiate_error_format;
+use hello_resut_1::middleware::logging::{request_logger, LogExcludePaths};
+use hello_resut_1::middleware::request_id::{assign_request_id, RequestId, REQUEST_ID_HEADER};
+use hello_resut_1::middleware::rate_limit::RateLimiter;
+use hello_resut_1::middleware::timeout::{enforce_timeout, RequestTimeout};
+use hello_resut_1::middleware::cors::{cors, CorsConfig};
+use hello_resut_1::middleware::security_headers::{security_headers, DEFAULT_CONTENT_SECURITY_POLICY};
+use hello_resut_1::password::PasswordHasher;
+use hello_resut_1::totp::TotpCipher;
+use hello_resut_1::{repository, roles};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const PASSWORD: &str = "Sup3r-secret!";
+
+/// Build app state over a fresh, migrated in-memory database
+async fn test_state() -> (AppState, SqlitePool) {
+    // A single never-recycled connection keeps the in-memory database alive for the whole test
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+
+    let db_pool = DbPool::Sqlite(pool.clone());
+    db_pool.migrate().await.expect("Failed to run migrations");
+
+    let state = AppState {
+        users: repository::user_repository(&db_pool),
+        db_pool,
+        request_timeout: std::time::Duration::from_secs(30),
+        import_timeout: std::time::Duration::from_secs(600),
+        log_exclude_paths: Arc::new(LogExcludePaths::default()),
+        import_limit: ImportLimit(1000),
+        page_size: PageSize::default(),
+        db_retry: RetryPolicy { max_retries: 3, base_delay: std::time::Duration::from_millis(1) },
+        lockout: LockoutPolicy {
+            max_failed_attempts: 5,
+            lock_duration: Duration::minutes(15),
+        },
+        // Minimal Argon2 cost keeps the suite fast; the parameters don't change behaviour
+        hasher: Arc::new(PasswordHasher::new(
+            argon2::Algorithm::Argon2id,
+            argon2::Version::V0x13,
+            argon2::Params::new(1024, 1, 1, None).unwrap(),
+            None,
+        )),
+        jwt: Arc::new(JwtConfig::new("integration-test-secret", 3600)),
+        proxy: Arc::new(ProxyConfig::default()),
+        auth_limiter: Arc::new(RateLimiter::new(1_000)),
+        resend_limiter: Arc::new(RateLimiter::new(1_000)),
+        metrics: Arc::new(Metrics::new()),
+        delete_mode: DeleteMode::Soft,
+        maintenance: Arc::new(MaintenanceMode::default()),
+        error_format: ErrorFormat::Json,
+        disposable_domains: None,
+        allowed_email_domains: None,
+        totp: None,
+        breach_checker: None,
+        mailer: None,
+    };
+
+    (state, pool)
+}
+
+/// Registration payload whose username is the email's local part
+fn register_body(email: &str) -> Value {
+    let username = email.trim().split('@').next().unwrap();
+    json!({ "name": "Alice", "email": email, "username": username, "password": PASSWORD })
+}
+
+/// Register `email` and confirm it with the stored verification token, returning the user id
+async fn sign_up<S, B>(app: &S, pool: &SqlitePool, email: &str) -> String
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post().uri("/register").set_json(register_body(email)).to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = test::read_body_json(resp).await;
+    let user_id = created["id"].as_str().unwrap().to_string();
+
+    let token: String = sqlx::query_scalar("SELECT token FROM email_verifications WHERE user_id = ?")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    let req = test::TestRequest::get().uri(&format!("/verify?token={}", token)).to_request();
+    assert_eq!(test::call_service(app, req).await.status(), StatusCode::OK);
+
+    user_id
+}
+
+/// Log in and return an `Authorization` header carrying the issued token
+async fn login<S, B>(app: &S, email: &str) -> (header::HeaderName, String)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": email, "password": PASSWORD }))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+
+    (header::AUTHORIZATION, format!("Bearer {}", body["token"].as_str().unwrap()))
+}
+
+#[actix_web::test]
+async fn register_login_fetch_and_delete_user() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    // Register: 201 with the created user and its location
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(register_body("  Alice@Example.com "))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let location = resp.headers().get(header::LOCATION).unwrap().to_str().unwrap().to_string();
+    let created: Value = test::read_body_json(resp).await;
+    let user_id = created["id"].as_str().unwrap().to_string();
+    assert_eq!(created["email"], "alice@example.com");
+    assert_eq!(location, format!("/users/{}", user_id));
+    assert!(created.get("password").is_none());
+
+    // Login before verifying the email is refused
+    let credentials = json!({ "email": "alice@example.com", "password": PASSWORD });
+    let req = test::TestRequest::post().uri("/login").set_json(&credentials).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // Verify using the token stored at registration
+    let token: String = sqlx::query_scalar("SELECT token FROM email_verifications WHERE user_id = ?")
+        .bind(&user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let req = test::TestRequest::get().uri(&format!("/verify?token={}", token)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Login now succeeds and returns an access token
+    let req = test::TestRequest::post().uri("/login").set_json(&credentials).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    let access_token = body["token"].as_str().unwrap().to_string();
+    let bearer = (header::AUTHORIZATION, format!("Bearer {}", access_token));
+
+    // Fetch the user by id
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(bearer.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let fetched: Value = test::read_body_json(resp).await;
+    assert_eq!(fetched["name"], "Alice");
+    assert!(created["last_login_at"].is_null());
+    assert!(fetched["last_login_at"].is_string());
+    assert_eq!(fetched["role"], "user");
+
+    // Deleting is admin-only
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(bearer.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    assert!(state.users.set_role("admin@example.com", "admin").await.unwrap());
+    let admin = login(&app, "admin@example.com").await;
+
+    // Delete, after which the user is gone and its token no longer works
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(admin.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(admin)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(bearer)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn duplicate_email_returns_conflict() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("bob@example.com")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("BOB@example.com")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "email already registered");
+}
+
+#[actix_web::test]
+async fn email_case_is_ignored_by_the_database_too() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let user_id = sign_up(&app, &pool, "dana@example.com").await;
+
+    // A write that skips the application's normalization still can't duplicate the address
+    let result = sqlx::query("INSERT INTO users (id, name, email, username, password) VALUES (?, ?, ?, ?, ?)")
+        .bind("00000000-0000-4000-8000-000000000001")
+        .bind("Dana")
+        .bind("Dana@Example.COM")
+        .bind("dana2")
+        .bind("not-a-hash")
+        .execute(&pool)
+        .await;
+    assert!(result.is_err_and(|e| is_duplicate_entry(&e)));
+
+    // A row stored with mixed case is still found by the normalized login lookup
+    sqlx::query("UPDATE users SET email = ? WHERE id = ?")
+        .bind("Dana@Example.COM")
+        .bind(&user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    login(&app, "dana@example.com").await;
+}
+
+#[actix_web::test]
+async fn wrong_password_and_unknown_email_look_the_same() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("carol@example.com")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    for credentials in [
+        json!({ "email": "carol@example.com", "password": "Wr0ng-password!" }),
+        json!({ "email": "nobody@example.com", "password": PASSWORD }),
+    ] {
+        let req = test::TestRequest::post().uri("/login").set_json(&credentials).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "invalid credentials");
+    }
+}
+
+#[actix_web::test]
+async fn protected_routes_require_a_token() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::get().uri("/users").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "unauthorized" }));
+}
+
+#[actix_web::test]
+async fn listing_and_counting_users_require_the_admin_role() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "dave@example.com").await;
+    let user = login(&app, "dave@example.com").await;
+    let req = test::TestRequest::get().uri("/users/count").insert_header(user.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::get().uri("/users").insert_header(user).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "forbidden" }));
+
+    // The role is read from the token, so it applies from the next login
+    assert!(state.users.set_role("dave@example.com", "admin").await.unwrap());
+    let admin = login(&app, "dave@example.com").await;
+    let req = test::TestRequest::get().uri("/users").insert_header(admin.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["total"], 1);
+
+    let req = test::TestRequest::get().uri("/users/count").insert_header(admin).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "count": 1 }));
+}
+
+#[actix_web::test]
+async fn malformed_user_id_is_rejected() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "erin@example.com").await;
+    let bearer = login(&app, "erin@example.com").await;
+    let req = test::TestRequest::get().uri("/users/not-a-uuid").insert_header(bearer.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "invalid id" }));
+
+    // Every {id} route shares the extractor
+    for req in [test::TestRequest::patch(), test::TestRequest::put()] {
+        let req = req.uri("/users/not-a-uuid").insert_header(bearer.clone()).set_json(json!({ "name": "Erin" }));
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[actix_web::test]
+async fn logout_revokes_the_token() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "frank@example.com").await;
+    let bearer = login(&app, "frank@example.com").await;
+
+    let req = test::TestRequest::post().uri("/logout").insert_header(bearer.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "message": "logged out" }));
+
+    // The same token no longer authenticates anything, including another logout
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(bearer.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post().uri("/logout").insert_header(bearer).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    // A fresh login is unaffected
+    let bearer = login(&app, "frank@example.com").await;
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(bearer)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    // Once the revoked token has expired, the cleanup removes its row
+    let later = chrono::Utc::now() + Duration::days(1);
+    assert_eq!(state.users.delete_expired_revocations(later).await.unwrap(), 1);
+}
+
+#[actix_web::test]
+async fn oversized_passwords_and_bodies_are_rejected() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let long_password = format!("Aa1!{}", "a".repeat(125));
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(json!({ "name": "Gina", "email": "gina@example.com", "username": "gina", "password": long_password }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["errors"]["password"][0], "Password must be at most 128 characters long");
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "gina@example.com", "password": long_password }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Password must be at most 128 characters long");
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "gina@example.com", "password": "a".repeat(64 * 1024) }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[actix_web::test]
+async fn patch_changes_only_the_given_fields() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "hank@example.com").await;
+    let bearer = login(&app, "hank@example.com").await;
+    let uri = format!("/users/{}", user_id);
+
+    let req = test::TestRequest::patch().uri(&uri).insert_header(bearer.clone()).set_json(json!({ "name": "Henry" })).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["name"], "Henry");
+    assert_eq!(body["email"], "hank@example.com");
+
+    let req = test::TestRequest::patch().uri(&uri).insert_header(bearer.clone()).set_json(json!({})).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "no fields to update");
+
+    // Emails only change through POST /users/{id}/email, which verifies the new address
+    for req in [test::TestRequest::patch(), test::TestRequest::put()] {
+        let req = req.uri(&uri).insert_header(bearer.clone()).set_json(json!({ "name": "Hank", "email": "hank@evil.example" }));
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "email can't be changed here; use POST /users/{id}/email");
+    }
+    let stored = state.users.find_by_id(&user_id).await.unwrap().unwrap();
+    assert_eq!((stored.name.as_str(), stored.email.as_str()), ("Henry", "hank@example.com"));
+
+    // Patching anyone else is refused before the id is even looked up
+    let missing = format!("/users/{}", uuid::Uuid::new_v4());
+    let req = test::TestRequest::patch().uri(&missing).insert_header(bearer).set_json(json!({ "name": "Nobody" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    let req = test::TestRequest::patch().uri(&uri).set_json(json!({ "name": "Nobody" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    // Admins may patch anyone, and a missing user is then a 404
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+    let req = test::TestRequest::patch().uri(&missing).insert_header(admin.clone()).set_json(json!({ "name": "Nobody" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::patch().uri(&uri).insert_header(admin).set_json(json!({ "name": "Hal" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn phone_numbers_are_optional_and_validated() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("iris@example.com")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["phone"], Value::Null);
+    let uri = format!("/users/{}", body["id"].as_str().unwrap());
+
+    let mut register = register_body("jordan@example.com");
+    register["phone"] = json!("+44 20 7946 0958");
+    let req = test::TestRequest::post().uri("/register").set_json(&register).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["errors"]["phone"][0], "Phone number must be in E.164 format, e.g. +14155550123");
+
+    register["phone"] = json!("+442079460958");
+    let req = test::TestRequest::post().uri("/register").set_json(&register).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["phone"], "+442079460958");
+
+    // PATCH sets the number and leaves it alone when omitted
+    sqlx::query("UPDATE users SET verified = TRUE").execute(&pool).await.unwrap();
+    let bearer = login(&app, "iris@example.com").await;
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(bearer.clone())
+        .set_json(json!({ "phone": "+14155550123" }))
+        .to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["phone"], "+14155550123");
+
+    let req = test::TestRequest::patch().uri(&uri).insert_header(bearer.clone()).set_json(json!({ "name": "Iris" })).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["phone"], "+14155550123");
+
+    let req = test::TestRequest::patch().uri(&uri).insert_header(bearer.clone()).set_json(json!({ "phone": "4155550123" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+    // An explicit null removes the number, through PATCH and PUT alike
+    let req = test::TestRequest::patch().uri(&uri).insert_header(bearer.clone()).set_json(json!({ "phone": null })).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["phone"], Value::Null);
+
+    let req = test::TestRequest::patch().uri(&uri).insert_header(bearer.clone()).set_json(json!({ "phone": "+14155550123" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::put().uri(&uri).insert_header(bearer).set_json(json!({ "phone": null })).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["phone"], Value::Null);
+}
+
+#[actix_web::test]
+async fn stale_versions_are_rejected_with_conflict() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "ivy@example.com").await;
+    let bearer = login(&app, "ivy@example.com").await;
+    let uri = format!("/users/{}", user_id);
+
+    // Two clients read version 0; the first write wins and bumps it
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(bearer.clone())
+        .set_json(json!({ "name": "Ivy", "version": 0 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["version"], 1);
+
+    for req in [
+        test::TestRequest::patch().uri(&uri).set_json(json!({ "name": "Ivy B", "version": 0 })),
+        test::TestRequest::put().uri(&uri).set_json(json!({ "name": "Ivy B", "version": 0 })),
+    ] {
+        let resp = test::call_service(&app, req.insert_header(bearer.clone()).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, json!({ "error": "version mismatch" }));
+    }
+
+    let req = test::TestRequest::put()
+        .uri(&uri)
+        .insert_header(bearer.clone())
+        .set_json(json!({ "name": "Ivy C", "version": 1 }))
+        .to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!((body["name"].as_str(), body["version"].as_i64()), (Some("Ivy C"), Some(2)));
+
+    // Without a version the write is unconditional, and a missing user is still a 404
+    let req = test::TestRequest::patch().uri(&uri).insert_header(bearer.clone()).set_json(json!({ "name": "Ivy D" })).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["version"], 3);
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+    let missing = format!("/users/{}", uuid::Uuid::new_v4());
+    let req = test::TestRequest::patch()
+        .uri(&missing)
+        .insert_header(admin)
+        .set_json(json!({ "name": "Nobody", "version": 0 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn users_can_only_update_themselves_unless_admin() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let owner_id = sign_up(&app, &pool, "owner@example.com").await;
+    sign_up(&app, &pool, "other@example.com").await;
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let other = login(&app, "other@example.com").await;
+    let admin = login(&app, "admin@example.com").await;
+    let uri = format!("/users/{}", owner_id);
+
+    let req = test::TestRequest::put().uri(&uri).set_json(json!({ "name": "Mallory" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::put().uri(&uri).insert_header(other).set_json(json!({ "name": "Mallory" })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(state.users.find_by_id(&owner_id).await.unwrap().unwrap().name, "Alice");
+
+    let req = test::TestRequest::put().uri(&uri).insert_header(admin).set_json(json!({ "name": "Olive" })).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["name"], "Olive");
+}
+
+#[actix_web::test]
+async fn metrics_count_requests_by_route_pattern() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(
+        App::new()
+            .wrap(actix_web::middleware::from_fn(hello_resut_1::middleware::metrics::track_requests))
+            .configure(|cfg| configure(cfg, &state)),
+    )
+    .await;
+
+    for _ in 0..2 {
+        let req = test::TestRequest::get().uri(&format!("/users/{}", uuid::Uuid::new_v4())).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // No token needed to scrape
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get(header::CONTENT_TYPE).unwrap().to_str().unwrap().starts_with("text/plain"));
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+    assert!(body.contains(r#"http_requests_total{method="GET",path="/users/{id}",status="401"} 2"#));
+    assert!(body.contains(r#"http_request_duration_seconds_count{method="GET",path="/users/{id}"} 2"#));
+    assert!(body.contains("db_pool_active_connections"));
+    assert!(body.contains("db_pool_idle_connections"));
+}
+
+#[actix_web::test]
+async fn soft_deleted_users_are_hidden_until_restored() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "ivy@example.com").await;
+    sign_up(&app, &pool, "root@example.com").await;
+    state.users.set_role("root@example.com", "admin").await.unwrap();
+    let admin = login(&app, "root@example.com").await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(admin.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+    // The row survives but the account can't log in and isn't listed
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE id = ? AND deleted_at IS NOT NULL")
+        .bind(&user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 1);
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "ivy@example.com", "password": PASSWORD }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get().uri("/users").insert_header(admin.clone()).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["total"], 1);
+
+    // Restoring brings the account back, and only works once
+    let restore = format!("/users/{}/restore", user_id);
+    let req = test::TestRequest::post().uri(&restore).insert_header(admin.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["email"], "ivy@example.com");
+
+    let req = test::TestRequest::post().uri(&restore).insert_header(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    login(&app, "ivy@example.com").await;
+}
+
+#[actix_web::test]
+async fn hard_delete_mode_removes_the_row() {
+    let (mut state, pool) = test_state().await;
+    state.delete_mode = DeleteMode::Hard;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "jack@example.com").await;
+    sign_up(&app, &pool, "root@example.com").await;
+    state.users.set_role("root@example.com", "admin").await.unwrap();
+    let admin = login(&app, "root@example.com").await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(admin.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/restore", user_id))
+        .insert_header(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn users_delete_their_own_account_with_an_emailed_token() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let user_id = sign_up(&app, &pool, "kim@example.com").await;
+    let other_id = sign_up(&app, &pool, "lee@example.com").await;
+    let auth = login(&app, "kim@example.com").await;
+    let other = login(&app, "lee@example.com").await;
+
+    let req = test::TestRequest::post().uri("/users/me/delete-request").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let request_token = |auth: (header::HeaderName, String), user_id: String| {
+        let app = &app;
+        let pool = &pool;
+        async move {
+            let req = test::TestRequest::post().uri("/users/me/delete-request").insert_header(auth).to_request();
+            assert_eq!(test::call_service(app, req).await.status(), StatusCode::ACCEPTED);
+            sqlx::query_scalar::<_, String>("SELECT token FROM account_deletion_tokens WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_one(pool)
+                .await
+                .unwrap()
+        }
+    };
+    let confirm = |auth: (header::HeaderName, String), token: String| {
+        test::TestRequest::post()
+            .uri("/users/me/delete-confirm")
+            .insert_header(auth)
+            .set_json(json!({ "token": token }))
+            .to_request()
+    };
+
+    // Requesting again replaces the first token
+    let first = request_token(auth.clone(), user_id.clone()).await;
+    let token = request_token(auth.clone(), user_id.clone()).await;
+    assert_ne!(first, token);
+    let resp = test::call_service(&app, confirm(auth.clone(), first)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // Another account can't spend the token, and expired tokens don't work
+    let resp = test::call_service(&app, confirm(other.clone(), token.clone())).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let other_token = request_token(other.clone(), other_id.clone()).await;
+    sqlx::query("UPDATE account_deletion_tokens SET expires_at = ? WHERE token = ?")
+        .bind(chrono::Utc::now() - Duration::minutes(1))
+        .bind(&other_token)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let resp = test::call_service(&app, confirm(other, other_token)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, confirm(auth.clone(), token.clone())).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    // Soft-deleted by default, and the bearer token that confirmed it is revoked
+    let deleted: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE id = ? AND deleted_at IS NOT NULL")
+        .bind(&user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+    let resp = test::call_service(&app, confirm(auth, token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn users_can_be_sorted_by_whitelisted_keys() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "admin@example.com").await; // Named "Alice"
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+
+    for (name, email) in [("Carl", "carl@example.com"), ("Bea", "bea@example.com")] {
+        state
+            .users
+            .create(&NewUser {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: name.to_string(),
+                email: email.to_string(),
+                username: name.to_lowercase(),
+                phone: None,
+                password_hash: "unused".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
+    for (sort, expected) in [("name", ["Alice", "Bea", "Carl"]), ("-name", ["Carl", "Bea", "Alice"])] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/users?sort={}", sort))
+            .insert_header(admin.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        let names: Vec<&str> = body["users"].as_array().unwrap().iter().map(|u| u["name"].as_str().unwrap()).collect();
+        assert_eq!(names, expected, "sort={}", sort);
+    }
+
+    // Anything off the whitelist is a 400, not a silently ignored or interpolated value
+    let req = test::TestRequest::get()
+        .uri("/users?sort=password")
+        .insert_header(admin)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "sort must be one of name, -name, created_at, -created_at");
+}
+
+#[actix_web::test]
+async fn user_pages_carry_link_and_total_count_headers() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+    for i in 0..4 {
+        sign_up(&app, &pool, &format!("page{}@example.com", i)).await;
+    }
+
+    let links = |uri: &str| {
+        let req = test::TestRequest::get().uri(uri).insert_header(admin.clone()).to_request();
+        let app = &app;
+        async move {
+            let resp = test::call_service(app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers().get("X-Total-Count").unwrap(), "5");
+            resp.headers().get(header::LINK).unwrap().to_str().unwrap().to_string()
+        }
+    };
+
+    // A middle page links every way, keeping the sort and fields
+    assert_eq!(
+        links("/users?limit=2&offset=2&sort=-name&fields=id,email").await,
+        concat!(
+            r#"</users?sort=-name&fields=id,email&limit=2&offset=0>; rel="first", "#,
+            r#"</users?sort=-name&fields=id,email&limit=2&offset=0>; rel="prev", "#,
+            r#"</users?sort=-name&fields=id,email&limit=2&offset=4>; rel="next", "#,
+            r#"</users?sort=-name&fields=id,email&limit=2&offset=4>; rel="last""#,
+        )
+    );
+
+    // No `prev` on the first page and no `next` on the last
+    assert_eq!(
+        links("/users?limit=2").await,
+        r#"</users?limit=2&offset=0>; rel="first", </users?limit=2&offset=2>; rel="next", </users?limit=2&offset=4>; rel="last""#
+    );
+    assert_eq!(
+        links("/users?limit=2&offset=4").await,
+        r#"</users?limit=2&offset=0>; rel="first", </users?limit=2&offset=2>; rel="prev", </users?limit=2&offset=4>; rel="last""#
+    );
+}
+
+#[actix_web::test]
+async fn emails_differing_only_in_case_are_the_same_account() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    sign_up(&app, &pool, "casey@example.com").await;
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(json!({ "name": "Casey", "email": " CASEY@Example.com ", "username": "casey2", "password": PASSWORD }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "email already registered" }));
+
+    // Logins are normalized the same way
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "Casey@EXAMPLE.com", "password": PASSWORD }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn search_matches_wildcard_characters_literally() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    sign_up(&app, &pool, "searcher@example.com").await;
+    let auth = login(&app, "searcher@example.com").await;
+
+    for (i, name) in ["100% Cotton", "1000 Cotton", "a_b", "axb", "Wow!", "Wow"].into_iter().enumerate() {
+        state
+            .users
+            .create(&NewUser {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: name.to_string(),
+                email: format!("user{}@example.com", i),
+                username: format!("user{}", i),
+                phone: None,
+                password_hash: "unused".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
+    // `%`, `_` and the `!` escape character only ever match themselves
+    for (q, expected) in [("100%25", "100% Cotton"), ("a_b", "a_b"), ("Wow!", "Wow!")] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/users/search?q={}", q))
+            .insert_header(auth.clone())
+            .to_request();
+        let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+        let names: Vec<&str> = body["users"].as_array().unwrap().iter().map(|user| user["name"].as_str().unwrap()).collect();
+        assert_eq!(names, [expected], "{}", q);
+        assert_eq!(body["total"], 1, "{}", q);
+    }
+}
+
+#[actix_web::test]
+async fn paging_visits_every_user_exactly_once_despite_ties() {
+    let (mut state, pool) = test_state().await;
+    state.page_size = PageSize { default: 4, max: 5 };
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+    for i in 0..22 {
+        state
+            .users
+            .create(&NewUser {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: "Same Name".to_string(),
+                email: format!("same{}@example.com", i),
+                username: format!("same{}", i),
+                phone: None,
+                password_hash: "unused".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+    // Identical names and timestamps leave only `id` to order the rows
+    sqlx::query("UPDATE users SET created_at = (SELECT MIN(created_at) FROM users)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // Odd page sizes so the last page is partial
+    for path in ["/users?sort=name", "/users?sort=-created_at", "/users/search?q=same"] {
+        let mut seen = std::collections::HashSet::new();
+        let mut offset = 0;
+        loop {
+            let req = test::TestRequest::get()
+                .uri(&format!("{}&limit=3&offset={}", path, offset))
+                .insert_header(admin.clone())
+                .to_request();
+            let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+            let page = body["users"].as_array().unwrap();
+            if page.is_empty() {
+                break;
+            }
+            for user in page {
+                assert!(seen.insert(user["id"].as_str().unwrap().to_string()), "{} repeated a user", path);
+            }
+            offset += 3;
+        }
+        let expected = if path.starts_with("/users/search") { 22 } else { 23 };
+        assert_eq!(seen.len(), expected, "{} skipped users", path);
+    }
+
+    // DEFAULT_PAGE_SIZE applies without a `limit` and MAX_PAGE_SIZE caps it
+    let req = test::TestRequest::get().uri("/users").insert_header(admin.clone()).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["limit"], 4);
+    assert_eq!(body["users"].as_array().unwrap().len(), 4);
+
+    let req = test::TestRequest::get().uri("/users?limit=6").insert_header(admin).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["errors"]["limit"][0], "limit must be between 1 and 5");
+}
+
+#[actix_web::test]
+async fn cursor_pages_walk_users_oldest_first_and_survive_inserts() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+    for i in 0..6 {
+        sign_up(&app, &pool, &format!("cursor{}@example.com", i)).await;
+    }
+    // Everyone ties on created_at, so `id` alone orders this batch
+    sqlx::query("UPDATE users SET created_at = '2026-01-01 00:00:00'").execute(&pool).await.unwrap();
+    let mut expected: Vec<String> = sqlx::query_scalar("SELECT id FROM users").fetch_all(&pool).await.unwrap();
+    expected.sort();
+
+    let page = |cursor: String| {
+        let req = test::TestRequest::get()
+            .uri(&format!("/users?limit=3&fields=id&cursor={}", cursor))
+            .insert_header(admin.clone())
+            .to_request();
+        let app = &app;
+        async move {
+            let resp = test::call_service(app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert!(resp.headers().get("X-Total-Count").is_none());
+            let body: Value = test::read_body_json(resp).await;
+            let ids: Vec<String> = body["users"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|user| user["id"].as_str().unwrap().to_string())
+                .collect();
+            (ids, body["next_cursor"].as_str().map(str::to_string))
+        }
+    };
+
+    let (first, next) = page(String::new()).await;
+    assert_eq!(first, expected[..3]);
+
+    // A user signing up mid-walk is newer than every cursor, so it shows up at the end instead of shifting pages
+    sign_up(&app, &pool, "late@example.com").await;
+    let (second, next) = page(next.unwrap()).await;
+    assert_eq!(second, expected[3..6]);
+    let (third, next) = page(next.unwrap()).await;
+    assert_eq!(third.len(), 2);
+    assert_eq!(third[0], expected[6]);
+    assert_eq!(next, None);
+
+    for uri in ["/users?cursor=not-a-cursor", "/users?cursor=&offset=3", "/users?cursor=&sort=name"] {
+        let req = test::TestRequest::get().uri(uri).insert_header(admin.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn users_can_be_filtered_by_verified_and_status() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+    let suspended = sign_up(&app, &pool, "suspended@example.com").await;
+    sqlx::query("UPDATE users SET status = 'suspended' WHERE id = ?").bind(&suspended).execute(&pool).await.unwrap();
+    let unverified = sign_up(&app, &pool, "unverified@example.com").await;
+    sqlx::query("UPDATE users SET verified = FALSE WHERE id = ?").bind(&unverified).execute(&pool).await.unwrap();
+
+    let emails = |uri: &str| {
+        let req = test::TestRequest::get().uri(uri).insert_header(admin.clone()).to_request();
+        let app = &app;
+        async move {
+            let resp = test::call_service(app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let link = resp.headers().get(header::LINK).map(|link| link.to_str().unwrap().to_string());
+            let body: Value = test::read_body_json(resp).await;
+            let emails: Vec<String> =
+                body["users"].as_array().unwrap().iter().map(|user| user["email"].as_str().unwrap().to_string()).collect();
+            (emails, body["total"].as_i64(), link)
+        }
+    };
+
+    let (found, total, link) = emails("/users?status=suspended&sort=name").await;
+    assert_eq!(found, ["suspended@example.com"]);
+    assert_eq!(total, Some(1));
+    assert!(link.unwrap().contains("sort=name&status=suspended"));
+
+    let (found, total, _) = emails("/users?verified=true&status=active&sort=name").await;
+    assert_eq!(found, ["admin@example.com"]);
+    assert_eq!(total, Some(1));
+
+    let (found, total, _) = emails("/users?verified=false&limit=1&offset=0").await;
+    assert_eq!(found, ["unverified@example.com"]);
+    assert_eq!(total, Some(1));
+
+    // Sign-ups within the same second tie on created_at, so the cursor order between them is the ids'
+    let (mut found, total, _) = emails("/users?status=active&cursor=").await;
+    found.sort();
+    assert_eq!(found, ["admin@example.com", "unverified@example.com"]);
+    assert_eq!(total, None);
+
+    for uri in ["/users?verified=yes", "/users?status=banned", "/users?status=Active"] {
+        let req = test::TestRequest::get().uri(uri).insert_header(admin.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn maintenance_mode_refuses_writes_but_keeps_reads() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+
+    state.maintenance.set(true);
+    let user_uri = format!("/users/{}", user_id);
+    let writes = [
+        test::TestRequest::post().uri("/register").set_json(register_body("newcomer@example.com")),
+        test::TestRequest::post()
+            .uri("/login")
+            .set_json(json!({ "email": "admin@example.com", "password": PASSWORD })),
+        test::TestRequest::put().uri(&user_uri).set_json(json!({ "name": "Renamed" })),
+        test::TestRequest::patch().uri(&user_uri).set_json(json!({ "name": "Renamed" })),
+        test::TestRequest::delete().uri(&user_uri),
+        test::TestRequest::post().uri("/logout"),
+        test::TestRequest::post().uri("/password-reset/request").set_json(json!({ "email": "admin@example.com" })),
+        test::TestRequest::post()
+            .uri("/password-reset/confirm")
+            .set_json(json!({ "token": "unused", "new_password": "N3w-secret!" })),
+        test::TestRequest::post()
+            .uri(&format!("{}/password", user_uri))
+            .set_json(json!({ "old_password": PASSWORD, "new_password": "N3w-secret!" })),
+        test::TestRequest::post().uri(&format!("{}/email", user_uri)).set_json(json!({ "email": "moved@example.com" })),
+        test::TestRequest::post().uri(&format!("{}/status", user_uri)).set_json(json!({ "status": "suspended" })),
+        test::TestRequest::post().uri(&format!("{}/restore", user_uri)),
+        test::TestRequest::post().uri("/users/me/delete-request"),
+        test::TestRequest::post().uri("/users/bulk").set_json(json!([register_body("bulk@example.com")])),
+        test::TestRequest::post().uri("/2fa/enable"),
+        test::TestRequest::post().uri("/verify/resend").set_json(json!({ "email": "admin@example.com" })),
+        test::TestRequest::get().uri("/verify?token=unused"),
+    ];
+    for req in writes {
+        let req = req.insert_header(admin.clone()).to_request();
+        let uri = req.uri().to_string();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE, "{}", uri);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, json!({ "error": "maintenance" }));
+    }
+
+    // Reads, including with tokens issued before the window, and probes keep working
+    for uri in ["/users", user_uri.as_str(), "/health"] {
+        let req = test::TestRequest::get().uri(uri).insert_header(admin.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK, "{}", uri);
+    }
+    let req = test::TestRequest::get().uri(&user_uri).insert_header(admin.clone()).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["name"], "Alice");
+
+    state.maintenance.set(false);
+    login(&app, "admin@example.com").await;
+}
+
+#[actix_web::test]
+async fn breached_passwords_are_refused_when_hibp_is_enabled() {
+    const BREACHED: &str = "Pwned-passw0rd!";
+
+    // Stand-in for the range API that knows just one leaked password
+    let server = actix_web::HttpServer::new(|| {
+        App::new().route(
+            "/range/{prefix}",
+            web::get().to(|prefix: web::Path<String>| async move {
+                let digest = format!("{:X}", Sha1::digest(BREACHED.as_bytes()));
+                let body = if digest.starts_with(prefix.as_str()) { format!("{}:7\r\n", &digest[5..]) } else { String::new() };
+                HttpResponse::Ok().body(body)
+            }),
+        )
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let url = format!("http://{}", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+
+    let (mut state, pool) = test_state().await;
+    state.breach_checker = Some(Arc::new(BreachChecker::new(&url, std::time::Duration::from_secs(5))));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let mut body = register_body("leaky@example.com");
+    body["password"] = json!(BREACHED);
+    let req = test::TestRequest::post().uri("/register").set_json(body).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "password found in data breaches");
+
+    let user_id = sign_up(&app, &pool, "careful@example.com").await;
+    let bearer = login(&app, "careful@example.com").await;
+    let change = |new_password: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/users/{}/password", user_id))
+            .insert_header(bearer.clone())
+            .set_json(json!({ "old_password": PASSWORD, "new_password": new_password }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, change(BREACHED)).await.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::call_service(&app, change("Unl3aked-secret!")).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn repeated_failed_logins_lock_the_account_until_it_expires() {
+    let (mut state, pool) = test_state().await;
+    state.lockout.max_failed_attempts = 3;
+    state.lockout.lock_duration = Duration::minutes(15);
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    sign_up(&app, &pool, "guessed@example.com").await;
+
+    let login_with = |password: &str| {
+        test::TestRequest::post()
+            .uri("/login")
+            .set_json(json!({ "email": "guessed@example.com", "password": password }))
+            .to_request()
+    };
+
+    let before = chrono::Utc::now();
+    for _ in 0..3 {
+        assert_eq!(test::call_service(&app, login_with("Wr0ng-guess!")).await.status(), StatusCode::UNAUTHORIZED);
+    }
+    let after = chrono::Utc::now();
+
+    // The third failure starts a 15 minute lock that even the right password can't get past
+    let resp = test::call_service(&app, login_with(PASSWORD)).await;
+    assert_eq!(resp.status(), StatusCode::LOCKED);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "account temporarily locked" }));
+
+    let locked_until: chrono::DateTime<chrono::Utc> =
+        sqlx::query_scalar("SELECT locked_until FROM users WHERE email = 'guessed@example.com'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(locked_until >= before + Duration::minutes(15) && locked_until <= after + Duration::minutes(15));
+
+    // Once the lock has run out the account works again
+    sqlx::query("UPDATE users SET locked_until = ? WHERE email = 'guessed@example.com'")
+        .bind(chrono::Utc::now() - Duration::seconds(1))
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(test::call_service(&app, login_with(PASSWORD)).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn password_changes_need_the_owners_token_and_count_towards_lockout() {
+    let (mut state, pool) = test_state().await;
+    state.lockout.max_failed_attempts = 2;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "owner@example.com").await;
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let owner = login(&app, "owner@example.com").await;
+    let admin = login(&app, "admin@example.com").await;
+    let uri = format!("/users/{}/password", user_id);
+    let change = |old_password: &str| json!({ "old_password": old_password, "new_password": "N3w-secret-pass!" });
+
+    let req = test::TestRequest::post().uri(&uri).set_json(change(PASSWORD)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::post().uri(&uri).insert_header(admin).set_json(change(PASSWORD)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    // Wrong current passwords lock the account, after which even the right one is refused
+    for _ in 0..2 {
+        let req = test::TestRequest::post().uri(&uri).insert_header(owner.clone()).set_json(change("Wr0ng-guess!")).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+    let req = test::TestRequest::post().uri(&uri).insert_header(owner).set_json(change(PASSWORD)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::LOCKED);
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "owner@example.com", "password": PASSWORD }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::LOCKED);
+}
+
+#[actix_web::test]
+async fn unverified_logins_are_not_recorded_as_successes() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("pending@example.com")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    for (password, status) in [("Wr0ng-guess!", StatusCode::UNAUTHORIZED), (PASSWORD, StatusCode::FORBIDDEN)] {
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .set_json(json!({ "email": "pending@example.com", "password": password }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), status);
+    }
+
+    // The refused login neither cleared the failure counter nor counted as activity
+    let (failed_attempts, last_login_at): (i32, Option<String>) =
+        sqlx::query_as("SELECT failed_attempts, last_login_at FROM users WHERE email = 'pending@example.com'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!((failed_attempts, last_login_at), (1, None));
+}
+
+#[actix_web::test]
+async fn failed_verification_insert_rolls_back_the_user() {
+    let (state, _pool) = test_state().await;
+    let expires_at = chrono::Utc::now() + Duration::hours(24);
+    let new_user = |email: &str| NewUser {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: "Kim".to_string(),
+        email: email.to_string(),
+        username: email.split('@').next().unwrap().to_string(),
+        phone: None,
+        password_hash: "unused".to_string(),
+    };
+
+    state
+        .users
+        .create_with_verification(&new_user("kim@example.com"), "same-token", expires_at)
+        .await
+        .unwrap();
+
+    // Reusing the token violates its primary key after the user row was inserted
+    let result = state
+        .users
+        .create_with_verification(&new_user("lee@example.com"), "same-token", expires_at)
+        .await;
+    assert!(result.is_err());
+    assert!(!state.users.email_exists("lee@example.com").await.unwrap());
+}
+
+#[actix_web::test]
+async fn login_upgrades_hashes_made_with_older_parameters() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let user_id = sign_up(&app, &pool, "erin@example.com").await;
+
+    // Pretend the password was hashed before the Argon2 cost was raised
+    let older = PasswordHasher::new(
+        argon2::Algorithm::Argon2i,
+        argon2::Version::V0x13,
+        argon2::Params::new(512, 1, 1, None).unwrap(),
+        None,
+    );
+    sqlx::query("UPDATE users SET password = ? WHERE id = ?")
+        .bind(older.hash(PASSWORD).unwrap())
+        .bind(&user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    login(&app, "erin@example.com").await;
+    let stored: String = sqlx::query_scalar("SELECT password FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(stored.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"), "{}", stored);
+    assert!(!state.hasher.needs_rehash(&stored));
+
+    // The upgraded hash still logs in
+    login(&app, "erin@example.com").await;
+}
+
+#[actix_web::test]
+async fn login_accepts_a_username_or_an_email() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "Mona@example.com").await;
+
+    // Usernames are normalized like emails, and `email` still works as the field name
+    for credentials in [
+        json!({ "identifier": "MONA", "password": PASSWORD }),
+        json!({ "identifier": "mona@EXAMPLE.com", "password": PASSWORD }),
+        json!({ "email": "mona@example.com", "password": PASSWORD }),
+    ] {
+        let req = test::TestRequest::post().uri("/login").set_json(&credentials).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK, "{}", credentials);
+    }
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}", user_id))
+        .insert_header(login(&app, "mona@example.com").await)
+        .to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["username"], "mona");
+
+    // Unknown usernames and wrong passwords look the same
+    for credentials in [
+        json!({ "identifier": "nobody", "password": PASSWORD }),
+        json!({ "identifier": "mona", "password": "Wr0ng-password!" }),
+    ] {
+        let req = test::TestRequest::post().uri("/login").set_json(&credentials).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "invalid credentials");
+    }
+
+    // Taken and malformed usernames are rejected at registration
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(json!({ "name": "Mo", "email": "mo@example.com", "username": "Mona", "password": PASSWORD }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "username already taken");
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(json!({ "name": "Mo", "email": "mo@example.com", "username": "mo_1", "password": PASSWORD }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["errors"]["username"][0], "Username may only contain letters and digits");
+}
+
+#[actix_web::test]
+async fn disposable_domains_are_blocked_when_configured() {
+    let (mut state, _pool) = test_state().await;
+    let blocklist = DomainBlocklist::load(std::path::Path::new("tests/fixtures/disposable_domains.txt")).unwrap();
+    assert_eq!(blocklist.len(), 2);
+    state.disposable_domains = Some(Arc::new(blocklist));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    // Subdomains of a listed domain are refused too
+    for email in ["spam@mailinator.com", "Spam2@GUERRILLAMAIL.COM", "spam3@mail.mailinator.com"] {
+        let req = test::TestRequest::post().uri("/register").set_json(register_body(email)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", email);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, json!({ "error": "disposable email not allowed" }));
+    }
+
+    for email in ["real@example.com", "lookalike@notmailinator.com"] {
+        let req = test::TestRequest::post().uri("/register").set_json(register_body(email)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED, "{}", email);
+    }
+}
+
+#[actix_web::test]
+async fn only_allowlisted_domains_may_register_when_configured() {
+    let (mut state, _pool) = test_state().await;
+    state.allowed_email_domains = Some(Arc::new(DomainAllowlist::parse(" Example.com, @corp.example ,").unwrap()));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    // Subdomains aren't covered by their parent, and lookalikes don't match either
+    for email in ["ann@gmail.com", "ann@eu.example.com", "ann@example.com.evil.test", "ann@notexample.com"] {
+        let req = test::TestRequest::post().uri("/register").set_json(register_body(email)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{}", email);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, json!({ "error": "email domain not allowed" }));
+    }
+
+    for email in ["bob@EXAMPLE.com", "  cyd@Corp.Example "] {
+        let req = test::TestRequest::post().uri("/register").set_json(register_body(email)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED, "{}", email);
+    }
+
+    assert!(DomainAllowlist::parse(" , ").is_err());
+}
+
+#[actix_web::test]
+async fn large_responses_are_compressed_on_request() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(
+        App::new()
+            .wrap(Compress::default())
+            .configure(|cfg| configure(cfg, &state)),
+    )
+    .await;
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+
+    for i in 0..50 {
+        state
+            .users
+            .create(&NewUser {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: format!("User {}", i),
+                email: format!("user{}@example.com", i),
+                username: format!("user{}", i),
+                phone: None,
+                password_hash: "unused".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/users?limit=100")
+        .insert_header(admin.clone())
+        .insert_header((header::ACCEPT_ENCODING, "gzip"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+
+    // Clients that don't ask for compression get the plain body
+    let req = test::TestRequest::get().uri("/users?limit=100").insert_header(admin).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["users"].as_array().unwrap().len(), 51);
+
+    // Probes and scrapers keep working whether or not they negotiate an encoding
+    for uri in ["/health", "/metrics"] {
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK, "{}", uri);
+
+        let req = test::TestRequest::get().uri(uri).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn readiness_follows_the_database_but_liveness_does_not() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    for uri in ["/livez", "/readyz", "/health"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK, "{}", uri);
+    }
+
+    // A schema missing this build's latest migration is not ready either
+    let latest: i64 = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations").fetch_one(&pool).await.unwrap();
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = ?").bind(latest).execute(&pool).await.unwrap();
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "status": "unavailable", "migrations": "pending" }));
+
+    let req = test::TestRequest::get().uri("/livez").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    // With the database gone the pod stops taking traffic but isn't restarted
+    pool.close().await;
+
+    let req = test::TestRequest::get().uri("/livez").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    for uri in ["/readyz", "/health"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE, "{}", uri);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, json!({ "status": "unavailable" }));
+    }
+}
+
+#[actix_web::test]
+async fn version_reports_the_build() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::get().uri("/version").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!body["git_sha"].as_str().unwrap().is_empty());
+    assert!(chrono::DateTime::parse_from_rfc3339(body["build_time"].as_str().unwrap()).is_ok());
+}
+
+#[actix_web::test]
+async fn names_are_trimmed_and_blank_names_rejected() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(json!({ "name": "  Nora  ", "email": "nora@example.com", "username": "nora", "password": PASSWORD }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+    let nora_id = state.users.find_by_email("nora@example.com").await.unwrap().unwrap().id;
+    assert_eq!(state.users.find_by_id(&nora_id).await.unwrap().unwrap().name, "Nora");
+    sqlx::query("UPDATE users SET verified = TRUE").execute(&pool).await.unwrap();
+    let bearer = login(&app, "nora@example.com").await;
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(json!({ "name": " \t ", "email": "blank@example.com", "username": "blank", "password": PASSWORD }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["errors"]["name"], json!(["Name is required"]));
+
+    // Updates normalize the same way
+    let uri = format!("/users/{}", nora_id);
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(bearer.clone())
+        .set_json(json!({ "name": " Nora B. " }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["name"], "Nora B.");
+
+    let req = test::TestRequest::patch().uri(&uri).insert_header(bearer.clone()).set_json(json!({ "name": "   " })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::put().uri(&uri).insert_header(bearer).set_json(json!({ "name": "   " })).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn bulk_import_reports_a_result_per_entry() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+
+    let batch = json!([
+        register_body("olga@example.com"),
+        register_body("admin@example.com"),
+        { "name": "Bad", "email": "not-an-email", "username": "bad", "password": PASSWORD },
+        register_body("OLGA@example.com"),
+        register_body("pete@example.com"),
+    ]);
+    let req = test::TestRequest::post()
+        .uri("/users/bulk")
+        .insert_header(admin.clone())
+        .set_json(&batch)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    let results = body.as_array().unwrap();
+    let statuses: Vec<&str> = results.iter().map(|r| r["status"].as_str().unwrap()).collect();
+    assert_eq!(statuses, ["created", "error", "error", "error", "created"]);
+    assert_eq!(results[1]["error"], "email already registered");
+    assert_eq!(results[2]["errors"]["email"], json!(["Invalid email address"]));
+    assert_eq!(results[3]["error"], "email already registered"); // Taken earlier in the same batch
+
+    // Imported accounts are verified and can log in straight away
+    let bearer = login(&app, "pete@example.com").await;
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}", results[4]["id"].as_str().unwrap()))
+        .insert_header(bearer.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    // Only admins may import
+    let req = test::TestRequest::post().uri("/users/bulk").insert_header(bearer).set_json(json!([])).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let oversized: Vec<Value> = (0..1001).map(|i| register_body(&format!("u{}@example.com", i))).collect();
+    let req = test::TestRequest::post()
+        .uri("/users/bulk")
+        .insert_header(admin.clone())
+        .set_json(&oversized)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "at most 1000 users can be imported at once");
+
+    // A malformed entry still rejects the whole body
+    let req = test::TestRequest::post()
+        .uri("/users/bulk")
+        .insert_header(admin)
+        .set_json(json!([register_body("quinn@example.com"), { "name": "No email" }]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["error"].as_str().unwrap().starts_with("invalid JSON body: missing field"), "{}", body);
+}
+
+#[actix_web::test]
+async fn bulk_import_gets_its_own_timeout() {
+    let (mut state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+
+    // Hashing the batch takes longer than any other request may
+    state.request_timeout = std::time::Duration::from_millis(50);
+    state.import_timeout = std::time::Duration::from_secs(60);
+    state.hasher = Arc::new(PasswordHasher::new(
+        argon2::Algorithm::Argon2id,
+        argon2::Version::V0x13,
+        argon2::Params::new(8 * 1024, 2, 1, None).unwrap(),
+        None,
+    ));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let batch: Vec<Value> = (0..8).map(|i| register_body(&format!("slow{}@example.com", i))).collect();
+    let started = std::time::Instant::now();
+    let req = test::TestRequest::post().uri("/users/bulk").insert_header(admin.clone()).set_json(batch).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(started.elapsed() > state.request_timeout, "the batch should outlast REQUEST_TIMEOUT_SECS");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let imported: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE email LIKE 'slow%'").fetch_one(&pool).await.unwrap();
+    assert_eq!(imported, 8);
+
+    // A batch past IMPORT_TIMEOUT_SECS still gets the 504
+    state.import_timeout = std::time::Duration::from_millis(50);
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let batch: Vec<Value> = (0..8).map(|i| register_body(&format!("late{}@example.com", i))).collect();
+    let req = test::TestRequest::post().uri("/users/bulk").insert_header(admin).set_json(batch).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[actix_web::test]
+async fn bulk_import_cap_is_configurable() {
+    let (mut state, pool) = test_state().await;
+    state.import_limit = ImportLimit(2);
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+
+    let batch: Vec<Value> = (0..3).map(|i| register_body(&format!("cap{}@example.com", i))).collect();
+    let req = test::TestRequest::post()
+        .uri("/users/bulk")
+        .insert_header(admin.clone())
+        .set_json(&batch)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "at most 2 users can be imported at once" }));
+
+    // The body limit shrinks with the cap, so padding alone can't make a request huge
+    let padded = json!([{ "name": "x".repeat(4096), "email": "pad@example.com", "username": "pad", "password": PASSWORD }]);
+    let req = test::TestRequest::post().uri("/users/bulk").insert_header(admin).set_json(&padded).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[actix_web::test]
+async fn csv_export_streams_every_live_user() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "admin@example.com").await;
+    state.users.set_role("admin@example.com", "admin").await.unwrap();
+    let admin = login(&app, "admin@example.com").await;
+
+    // More than one page, including a name that needs quoting and ones a spreadsheet would evaluate
+    let formulas = ["=1+1", "+cmd|calc", "-2", "@SUM(A1)", "\tTab"];
+    let imported: Vec<NewUser> = (0..600)
+        .map(|i| NewUser {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: match i {
+                0 => "Smith, Jo".to_string(),
+                2..=6 => formulas[i - 2].to_string(),
+                _ => format!("User {}", i),
+            },
+            email: format!("user{}@example.com", i),
+            username: format!("user{}", i),
+            phone: None,
+            password_hash: "secret-hash".to_string(),
+        })
+        .collect();
+    state.users.import_users(&imported).await.unwrap();
+    state.users.soft_delete(&imported[1].id, chrono::Utc::now()).await.unwrap();
+
+    let req = test::TestRequest::get().uri("/users/export.csv").insert_header(admin).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "text/csv; charset=utf-8");
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines[0], "id,name,email,created_at");
+    assert_eq!(lines.len(), 1 + 600); // Admin plus 599 live imports
+    assert!(body.contains(&format!("{},\"Smith, Jo\",user0@example.com,", imported[0].id)));
+    assert!(!body.contains("user1@example.com"));
+    assert!(!body.contains("secret-hash"));
+    for (i, cell) in ["'=1+1", "'+cmd|calc", "'-2", "'@SUM(A1)", "'\tTab"].into_iter().enumerate() {
+        assert!(body.contains(&format!("{},{},user{}@example.com,", imported[i + 2].id, cell, i + 2)), "{:?}", cell);
+    }
+
+    // Support staff only, never regular users
+    sign_up(&app, &pool, "staff@example.com").await;
+    let bearer = login(&app, "staff@example.com").await;
+    let req = test::TestRequest::get().uri("/users/export.csv").insert_header(bearer).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn openapi_document_describes_the_user_endpoints() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::get().uri("/api-docs/openapi.json").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let spec: Value = test::read_body_json(resp).await;
+
+    for path in ["/register", "/login", "/users", "/users/{id}", "/users/{id}/password"] {
+        assert!(spec["paths"][path].is_object(), "{} is undocumented", path);
+    }
+    let register = &spec["paths"]["/register"]["post"];
+    assert_eq!(register["requestBody"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/RegisterRequest");
+    assert_eq!(
+        register["responses"]["400"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/ValidationErrorResponse"
+    );
+    assert!(spec["components"]["schemas"]["ErrorResponse"]["properties"]["error"].is_object());
+    assert!(spec["components"]["securitySchemes"]["bearer_auth"].is_object());
+
+    let req = test::TestRequest::get().uri("/swagger-ui").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains("/api-docs/openapi.json"));
+}
+
+#[actix_web::test]
+async fn invalid_registrations_are_rejected_before_the_handler_runs() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    // Every failing field is reported at once, after the same normalization the handler would apply
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .insert_header(("Idempotency-Key", "first-try"))
+        .set_json(json!({ "name": "  ", "email": " NOPE ", "username": " ab ", "password": "short" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    let errors = body["errors"].as_object().unwrap();
+    assert_eq!(errors.keys().collect::<Vec<_>>(), ["email", "name", "password", "username"]);
+
+    // Nothing was stored, not even the idempotent response
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&pool).await.unwrap();
+    assert_eq!(users, 0);
+    assert!(state.users.find_idempotent_response("", "first-try", chrono::Utc::now()).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn bad_json_bodies_get_json_errors() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .insert_header((header::CONTENT_TYPE, "text/plain"))
+        .set_payload(register_body("kim@example.com").to_string())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "Content-Type must be application/json" }));
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .insert_header((header::CONTENT_TYPE, "application/json"))
+        .set_payload(r#"{"name": "Kim", "email": "#)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "malformed JSON body" }));
+
+    // Well-formed JSON of the wrong shape says which field is the problem
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(json!({ "name": "Kim" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["error"].as_str().unwrap().starts_with("invalid JSON body: missing field `email`"));
+}
+
+#[actix_web::test]
+async fn seeded_admin_can_log_in_straight_away() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let request = |email: &str, password: &str| RegisterRequest {
+        name: "Admin".to_string(),
+        email: email.to_string(),
+        username: "admin".to_string(),
+        phone: None,
+        password: password.to_string(),
+    };
+
+    assert!(roles::create_admin(state.users.as_ref(), &state.hasher, request("Root@Example.com", PASSWORD)).await.unwrap());
+
+    let admin = login(&app, "root@example.com").await;
+    let req = test::TestRequest::get().uri("/users/count").insert_header(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    // Running the seed again leaves the existing account alone
+    assert!(!roles::create_admin(state.users.as_ref(), &state.hasher, request("root@example.com", PASSWORD)).await.unwrap());
+
+    // The registration password rules still apply
+    let weak = roles::create_admin(state.users.as_ref(), &state.hasher, request("other@example.com", "short")).await;
+    assert!(weak.is_err());
+}
+
+#[actix_web::test]
+async fn admin_email_only_promotes_verified_live_accounts() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let role = || async {
+        sqlx::query_scalar::<_, String>("SELECT role FROM users WHERE email = 'boss@example.com'")
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+
+    // Whoever registers the address first gets nothing until it is confirmed
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("boss@example.com")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+    roles::seed_admin(state.users.as_ref(), Some("Boss@Example.com")).await.unwrap();
+    assert_eq!(role().await, "user");
+
+    sqlx::query("UPDATE users SET verified = TRUE").execute(&pool).await.unwrap();
+    roles::seed_admin(state.users.as_ref(), Some("Boss@Example.com")).await.unwrap();
+    assert_eq!(role().await, "admin");
+
+    // A deleted account isn't handed the role again
+    sqlx::query("UPDATE users SET role = 'user', deleted_at = CURRENT_TIMESTAMP").execute(&pool).await.unwrap();
+    roles::seed_admin(state.users.as_ref(), Some("boss@example.com")).await.unwrap();
+    assert_eq!(role().await, "user");
+}
+
+#[actix_web::test]
+async fn registration_retries_with_an_idempotency_key_replay_the_response() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let register = |key: &str| {
+        test::TestRequest::post()
+            .uri("/register")
+            .insert_header(("Idempotency-Key", key.to_string()))
+            .set_json(register_body("lena@example.com"))
+            .to_request()
+    };
+
+    let first = test::call_service(&app, register("signup-1")).await;
+    assert_eq!(first.status(), StatusCode::CREATED);
+    let location = first.headers().get(header::LOCATION).unwrap().clone();
+    assert!(first.headers().get("Idempotent-Replayed").is_none());
+    let created: Value = test::read_body_json(first).await;
+
+    // Same key: the stored response comes back instead of a duplicate-email error
+    let retry = test::call_service(&app, register("signup-1")).await;
+    assert_eq!(retry.status(), StatusCode::CREATED);
+    assert_eq!(retry.headers().get(header::LOCATION).unwrap(), &location);
+    assert_eq!(retry.headers().get("Idempotent-Replayed").unwrap(), "true");
+    let replayed: Value = test::read_body_json(retry).await;
+    assert_eq!(replayed, created);
+
+    // A new key is a new attempt
+    let resp = test::call_service(&app, register("signup-2")).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let resp = test::call_service(&app, register(&"k".repeat(256))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn idempotency_keys_are_scoped_to_the_client_and_body() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let register = |peer: &str, body: Value| {
+        test::TestRequest::post()
+            .uri("/register")
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("Idempotency-Key", "signup"))
+            .set_json(body)
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, register("203.0.113.5:4000", register_body("mara@example.com"))).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    // The same key with another body is a client bug, not a retry
+    let resp = test::call_service(&app, register("203.0.113.5:4000", register_body("other@example.com"))).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "Idempotency-Key was already used with a different request body" }));
+
+    // Another client picking the same key gets no one else's response
+    let resp = test::call_service(&app, register("198.51.100.7:4000", register_body("mara@example.com"))).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert!(resp.headers().get("Idempotent-Replayed").is_none());
+
+    let resp = test::call_service(&app, register("198.51.100.7:4000", register_body("ines@example.com"))).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn responses_carry_security_headers() {
+    let (state, _pool) = test_state().await;
+    let app = test::init_service(
+        App::new()
+            .wrap(security_headers(DEFAULT_CONTENT_SECURITY_POLICY))
+            .configure(|cfg| configure(cfg, &state)),
+    )
+    .await;
+
+    // Errors too, not just successful responses
+    for uri in ["/livez", "/users/not-a-uuid"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        let headers = resp.headers();
+        assert_eq!(headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff", "{}", uri);
+        assert_eq!(headers.get(header::X_FRAME_OPTIONS).unwrap(), "DENY", "{}", uri);
+        assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "no-referrer", "{}", uri);
+        assert_eq!(headers.get(header::CONTENT_SECURITY_POLICY).unwrap(), DEFAULT_CONTENT_SECURITY_POLICY, "{}", uri);
+    }
+
+    // The Swagger UI page keeps the looser policy it needs to load its assets
+    let req = test::TestRequest::get().uri("/swagger-ui").to_request();
+    let resp = test::call_service(&app, req).await;
+    let csp = resp.headers().get(header::CONTENT_SECURITY_POLICY).unwrap().to_str().unwrap();
+    assert!(csp.contains("https://unpkg.com"));
+}
+
+#[actix_web::test]
+async fn cors_preflights_advertise_credentials_and_max_age_when_configured() {
+    let (state, _pool) = test_state().await;
+    let preflight = || {
+        test::TestRequest::default()
+            .method(actix_web::http::Method::OPTIONS)
+            .uri("/users/me")
+            .insert_header((header::ORIGIN, "https://app.example"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+            .to_request()
+    };
+
+    let config = CorsConfig {
+        allowed_origins: vec!["https://app.example".to_string()],
+        allow_credentials: true,
+        max_age_secs: Some(600),
+    };
+    let app = test::init_service(App::new().wrap(cors(&config)).configure(|cfg| configure(cfg, &state))).await;
+    let resp = test::call_service(&app, preflight()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let headers = resp.headers();
+    assert_eq!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://app.example");
+    assert_eq!(headers.get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(), "true");
+    assert_eq!(headers.get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+
+    // Neither header is sent by default
+    let config = CorsConfig { allowed_origins: vec!["https://app.example".to_string()], ..CorsConfig::default() };
+    let app = test::init_service(App::new().wrap(cors(&config)).configure(|cfg| configure(cfg, &state))).await;
+    let resp = test::call_service(&app, preflight()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+    assert!(resp.headers().get(header::ACCESS_CONTROL_MAX_AGE).is_none());
+}
+
+#[actix_web::test]
+async fn email_changes_take_effect_only_once_confirmed() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "max@example.com").await;
+    let other_id = sign_up(&app, &pool, "nia@example.com").await;
+    let bearer = login(&app, "max@example.com").await;
+    let change = |id: &str, email: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/users/{}/email", id))
+            .insert_header(bearer.clone())
+            .set_json(json!({ "email": email }))
+            .to_request()
+    };
+
+    // Nobody can move another account's email, or take an address in use
+    assert_eq!(test::call_service(&app, change(&other_id, "evil@example.com")).await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(test::call_service(&app, change(&user_id, "NIA@example.com")).await.status(), StatusCode::CONFLICT);
+
+    assert_eq!(test::call_service(&app, change(&user_id, "Max.New@example.com")).await.status(), StatusCode::ACCEPTED);
+
+    // Until confirmed, the old address is still the one that works
+    login(&app, "max@example.com").await;
+    let req = test::TestRequest::get().uri(&format!("/users/{}", user_id)).insert_header(bearer.clone()).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["email"], "max@example.com");
+
+    let token: String = sqlx::query_scalar("SELECT token FROM email_change_tokens WHERE user_id = ?")
+        .bind(&user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let req = test::TestRequest::get().uri(&format!("/verify-email-change?token={}", token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    login(&app, "max.new@example.com").await;
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "max@example.com", "password": PASSWORD }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    // Tokens are single-use
+    let req = test::TestRequest::get().uri(&format!("/verify-email-change?token={}", token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn pool_stats_are_admin_only() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "ops@example.com").await;
+    let user = login(&app, "ops@example.com").await;
+    let req = test::TestRequest::get().uri("/debug/pool").insert_header(user).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    state.users.set_role("ops@example.com", "admin").await.unwrap();
+    let admin = login(&app, "ops@example.com").await;
+    let req = test::TestRequest::get().uri("/debug/pool").insert_header(admin).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["size"], 1);
+    assert_eq!(body["max"], 1);
+    assert!(body["idle"].as_u64().unwrap() <= 1);
+}
+
+#[actix_web::test]
+async fn suspended_accounts_cannot_log_in_until_reactivated() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "kim@example.com").await;
+    let user = login(&app, "kim@example.com").await;
+    sign_up(&app, &pool, "root@example.com").await;
+    state.users.set_role("root@example.com", "admin").await.unwrap();
+    let admin = login(&app, "root@example.com").await;
+    let status = format!("/users/{}/status", user_id);
+
+    // Only admins may change it, and only to a known status
+    let req = test::TestRequest::post()
+        .uri(&status)
+        .insert_header(user)
+        .set_json(json!({ "status": "suspended" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::post()
+        .uri(&status)
+        .insert_header(admin.clone())
+        .set_json(json!({ "status": "banished" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri(&status)
+        .insert_header(admin.clone())
+        .set_json(json!({ "status": "suspended" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "suspended");
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "kim@example.com", "password": PASSWORD }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "account suspended" }));
+
+    // A wrong password still gets the generic answer
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "kim@example.com", "password": "Wrong-pass-1" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post()
+        .uri(&status)
+        .insert_header(admin.clone())
+        .set_json(json!({ "status": "active" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    login(&app, "kim@example.com").await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/status", uuid::Uuid::new_v4()))
+        .insert_header(admin)
+        .set_json(json!({ "status": "active" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn users_can_export_their_own_data() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "lee@example.com").await;
+    let other_id = sign_up(&app, &pool, "max@example.com").await;
+    let user = login(&app, "lee@example.com").await;
+
+    let req = test::TestRequest::post()
+        .uri("/password-reset/request")
+        .set_json(json!({ "email": "lee@example.com" }))
+        .to_request();
+    test::call_service(&app, req).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/export", user_id))
+        .insert_header(user.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["profile"]["email"], "lee@example.com");
+    assert_eq!(body["profile"]["verified"], true);
+    assert!(body["profile"].get("password").is_none());
+    assert_eq!(body["email_verifications"], json!([]));
+    assert_eq!(body["password_resets"].as_array().unwrap().len(), 1);
+    assert!(body["password_resets"][0].get("token").is_none());
+
+    // Other accounts are off limits unless the caller is an admin
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/export", other_id))
+        .insert_header(user)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    state.users.set_role("max@example.com", "admin").await.unwrap();
+    let admin = login(&app, "max@example.com").await;
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/export", user_id))
+        .insert_header(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn successful_logins_are_listed_for_their_owner() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "ada@example.com").await;
+    let other_id = sign_up(&app, &pool, "bob@example.com").await;
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .peer_addr("198.51.100.4:5000".parse().unwrap())
+        .insert_header((header::USER_AGENT, "curl/8.0"))
+        .set_json(json!({ "email": "ada@example.com", "password": PASSWORD }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let user = login(&app, "ada@example.com").await;
+
+    // Failed attempts aren't recorded
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "ada@example.com", "password": "Wrong-pass-1" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/logins?limit=1", user_id))
+        .insert_header(user.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["limit"], 1);
+    assert_eq!(body["logins"].as_array().unwrap().len(), 1);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/logins", user_id))
+        .insert_header(user.clone())
+        .to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let logins = body["logins"].as_array().unwrap();
+    assert_eq!(logins.len(), 2);
+    assert_eq!(logins[1]["ip"], "198.51.100.4");
+    assert_eq!(logins[1]["user_agent"], "curl/8.0");
+
+    // Someone else's history is off limits
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/logins", other_id))
+        .insert_header(user)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn sessions_can_be_listed_and_revoked_one_by_one() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "ada@example.com").await;
+    sign_up(&app, &pool, "bob@example.com").await;
+    let laptop = login(&app, "ada@example.com").await;
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .peer_addr("198.51.100.4:5000".parse().unwrap())
+        .insert_header((header::USER_AGENT, "phone-app/1.0"))
+        .set_json(json!({ "email": "ada@example.com", "password": PASSWORD }))
+        .to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let phone = (header::AUTHORIZATION, format!("Bearer {}", body["token"].as_str().unwrap()));
+    let other = login(&app, "bob@example.com").await;
+
+    let req = test::TestRequest::get().uri("/users/me/sessions").insert_header(laptop.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    let sessions = body["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions.iter().filter(|session| session["current"] == true).count(), 1);
+    let phone_session = sessions.iter().find(|session| session["current"] == false).unwrap();
+    assert_eq!(phone_session["ip"], "198.51.100.4");
+    assert_eq!(phone_session["user_agent"], "phone-app/1.0");
+    assert!(phone_session["issued_at"].is_string());
+    assert!(phone_session["expires_at"].is_string());
+    let phone_jti = phone_session["jti"].as_str().unwrap().to_string();
+
+    // Another user's session can't be revoked, nor can one that doesn't exist
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/me/sessions/{}", phone_jti))
+        .insert_header(other.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/me/sessions/{}", uuid::Uuid::new_v4()))
+        .insert_header(laptop.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::delete().uri("/users/me/sessions/nope").insert_header(laptop.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/me/sessions/{}", phone_jti))
+        .insert_header(laptop.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+    // The revoked token stops working and drops off the list; the others keep going
+    let req = test::TestRequest::get().uri("/users/me").insert_header(phone).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::get().uri("/users/me").insert_header(other).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/users/me/sessions").insert_header(laptop.clone()).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["sessions"].as_array().unwrap().len(), 1);
+
+    // Revoking twice is a 404, the session is already gone
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/me/sessions/{}", phone_jti))
+        .insert_header(laptop)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/users/me/sessions").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn two_factor_login_needs_a_current_code() {
+    let (mut state, pool) = test_state().await;
+    state.totp = Some(Arc::new(TotpCipher::from_base64("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").unwrap()));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    sign_up(&app, &pool, "eve@example.com").await;
+    let user = login(&app, "eve@example.com").await;
+
+    let req = test::TestRequest::post().uri("/2fa/enable").insert_header(user.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let setup: Value = test::read_body_json(resp).await;
+    assert!(setup["otpauth_uri"].as_str().unwrap().starts_with("otpauth://totp/rust_learning:eve%40example.com?"));
+
+    // The secret is stored encrypted, not as the base32 the app was given
+    let stored: String = sqlx::query_scalar("SELECT totp_secret FROM users WHERE email = 'eve@example.com'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(!stored.contains(setup["secret"].as_str().unwrap()));
+
+    let secret = Secret::Encoded(setup["secret"].as_str().unwrap().to_string()).to_bytes().unwrap();
+    let generator = TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, None, String::new()).unwrap();
+    let code = generator.generate_current().unwrap();
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+
+    // Login is unaffected until the code is confirmed
+    let req = test::TestRequest::post()
+        .uri("/2fa/confirm")
+        .insert_header(user.clone())
+        .set_json(json!({ "code": wrong }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    login(&app, "eve@example.com").await;
+
+    let req = test::TestRequest::post()
+        .uri("/2fa/confirm")
+        .insert_header(user.clone())
+        .set_json(json!({ "code": code }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post().uri("/2fa/enable").insert_header(user).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+
+    for (totp_code, expected) in [
+        (None, json!({ "error": "two-factor code required" })),
+        (Some(wrong), json!({ "error": "invalid two-factor code" })),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .set_json(json!({ "email": "eve@example.com", "password": PASSWORD, "totp_code": totp_code }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, expected);
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(json!({ "email": "eve@example.com", "password": PASSWORD, "totp_code": code }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn errors_can_be_rendered_as_problem_details() {
+    let (mut state, _pool) = test_state().await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(negotiate_error_format))
+            .configure(|cfg| configure(cfg, &state)),
+    )
+    .await;
+
+    // The existing shape stays the default
+    let req = test::TestRequest::get().uri("/users").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/json");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "unauthorized" }));
+
+    // Asking for problem+json gets RFC 7807 members, validation messages included
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .insert_header((header::ACCEPT, "application/problem+json"))
+        .set_json(json!({ "name": "", "email": "nope", "username": "ab", "password": "x" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/problem+json");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["type"], "about:blank");
+    assert_eq!(body["title"], "Bad Request");
+    assert_eq!(body["status"], 400);
+    assert_eq!(body["detail"], "validation failed");
+    assert_eq!(body["errors"]["email"], json!(["Invalid email address"]));
+
+    // ERROR_FORMAT=problem makes it the default, also for extractor errors
+    state.error_format = ErrorFormat::Problem;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(negotiate_error_format))
+            .configure(|cfg| configure(cfg, &state)),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/users").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "type": "about:blank", "title": "Unauthorized", "status": 401, "detail": "unauthorized" }));
+}
+
+#[actix_web::test]
+async fn an_exhausted_pool_answers_503_with_retry_after() {
+    let (mut state, _pool) = test_state().await;
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(std::time::Duration::from_millis(50))
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    let db_pool = DbPool::Sqlite(pool.clone());
+    db_pool.migrate().await.unwrap();
+    state.users = repository::user_repository(&db_pool);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(negotiate_error_format))
+            .configure(|cfg| configure(cfg, &state)),
+    )
+    .await;
+
+    // Hold the only connection so the handler's query can't get one
+    let _held = pool.acquire().await.unwrap();
+    for accept in ["application/json", "application/problem+json"] {
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .insert_header((header::ACCEPT, accept))
+            .set_json(json!({ "email": "amy@example.com", "password": PASSWORD }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE, "{}", accept);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "5", "{}", accept);
+    }
+}
+
+#[actix_web::test]
+async fn users_me_returns_the_callers_profile() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::get().uri("/users/me").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let user_id = sign_up(&app, &pool, "joy@example.com").await;
+    let user = login(&app, "joy@example.com").await;
+    let req = test::TestRequest::get().uri("/users/me").insert_header(user.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["id"], user_id.as_str());
+    assert_eq!(body["email"], "joy@example.com");
+
+    // Suspending the account stops its token, and deleting it more so
+    state.users.set_status(&user_id, "suspended").await.unwrap();
+    let req = test::TestRequest::get().uri("/users/me").insert_header(user.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "account suspended" }));
+
+    state.users.set_status(&user_id, "active").await.unwrap();
+    let req = test::TestRequest::get().uri("/users/me").insert_header(user.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    state.users.soft_delete(&user_id, chrono::Utc::now()).await.unwrap();
+    let req = test::TestRequest::get().uri("/users/me").insert_header(user).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn fields_param_returns_sparse_users() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let user_id = sign_up(&app, &pool, "sue@example.com").await;
+    state.users.set_role("sue@example.com", "admin").await.unwrap();
+    let admin = login(&app, "sue@example.com").await;
+
+    // Order follows the record, not the query, and whitespace is ignored
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}?fields=email,%20id", user_id))
+        .insert_header(admin.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "id": user_id, "email": "sue@example.com" }));
+
+    let req = test::TestRequest::get().uri("/users?fields=email").insert_header(admin.clone()).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["users"], json!([{ "email": "sue@example.com" }]));
+    assert_eq!(body["total"], 1);
+
+    for query in ["fields=id,password", "fields="] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/users?{}", query))
+            .insert_header(admin.clone())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST, "{}", query);
+    }
+}
+
+#[actix_web::test]
+async fn slow_handlers_time_out_with_504() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(assign_request_id))
+            .app_data(web::Data::new(RequestTimeout(std::time::Duration::from_millis(50))))
+            .route(
+                "/slow",
+                web::get()
+                    .to(|| async {
+                        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                        HttpResponse::Ok().finish()
+                    })
+                    .wrap(from_fn(enforce_timeout)),
+            )
+            .route("/fast", web::get().to(HttpResponse::Ok).wrap(from_fn(enforce_timeout))),
+    )
+    .await;
+
+    // The timeout is an ordinary response, so the request id middleware further out still tags it
+    let req = test::TestRequest::get().uri("/slow").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(resp.headers().contains_key(REQUEST_ID_HEADER));
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "request timed out" }));
+
+    let req = test::TestRequest::get().uri("/fast").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+/// Accept one SMTP session on `listener`, answering every command with success, and return the message data
+/// (with the quoted-printable line breaks and `=3D` escapes that links get undone)
+async fn receive_one_email(listener: tokio::net::TcpListener) -> String {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let (read, mut write) = socket.into_split();
+    let mut lines = BufReader::new(read).lines();
+    write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+
+    let mut data = String::new();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        let reply: &[u8] = match line.to_ascii_uppercase().as_str() {
+            "DATA" => {
+                write.write_all(b"354 go ahead\r\n").await.unwrap();
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                b"250 queued\r\n"
+            }
+            "QUIT" => {
+                write.write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            }
+            _ => b"250 ok\r\n",
+        };
+        write.write_all(reply).await.unwrap();
+    }
+
+    data.replace("=\n", "").replace("=3D", "=")
+}
+
+#[actix_web::test]
+async fn registration_sends_a_welcome_email_when_smtp_is_configured() {
+    let (mut state, pool) = test_state().await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("smtp://{}", listener.local_addr().unwrap());
+    state.mailer = Some(Arc::new(Mailer::new(&url, "Accounts <accounts@example.com>", "https://app.example.com/").unwrap()));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("welcome@example.com")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    let email = tokio::time::timeout(std::time::Duration::from_secs(5), receive_one_email(listener))
+        .await
+        .expect("welcome email should be sent");
+    assert!(email.contains("To: Alice <welcome@example.com>"), "{}", email);
+    assert!(email.contains("Subject: Welcome to rust_learning"), "{}", email);
+    let token: String = sqlx::query_scalar("SELECT token FROM email_verifications").fetch_one(&pool).await.unwrap();
+    assert!(email.contains(&format!("https://app.example.com/verify?token={}", token)), "{}", email);
+
+    // A server that isn't listening doesn't fail the signup
+    state.mailer = Some(Arc::new(Mailer::new("smtp://127.0.0.1:1", "accounts@example.com", "https://app.example.com").unwrap()));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("unsent@example.com")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn password_reset_tokens_work_once_and_expire() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    sign_up(&app, &pool, "reset@example.com").await;
+
+    let request_reset = || {
+        test::TestRequest::post()
+            .uri("/password-reset/request")
+            .set_json(json!({ "email": "reset@example.com" }))
+            .to_request()
+    };
+    let confirm = |token: &str| {
+        test::TestRequest::post()
+            .uri("/password-reset/confirm")
+            .set_json(json!({ "token": token, "new_password": "N3w-secret!" }))
+            .to_request()
+    };
+    let login_with = |password: &str| {
+        test::TestRequest::post()
+            .uri("/login")
+            .set_json(json!({ "email": "reset@example.com", "password": password }))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, request_reset()).await.status(), StatusCode::OK);
+    let token: String = sqlx::query_scalar("SELECT token FROM password_resets").fetch_one(&pool).await.unwrap();
+
+    let resp = test::call_service(&app, confirm(&token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, login_with("N3w-secret!")).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, login_with(PASSWORD)).await.status(), StatusCode::UNAUTHORIZED);
+
+    // The token is spent
+    let resp = test::call_service(&app, confirm(&token)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "invalid or expired token" }));
+
+    // A fresh token past its hour is refused and leaves the password alone
+    assert_eq!(test::call_service(&app, request_reset()).await.status(), StatusCode::OK);
+    let token: String = sqlx::query_scalar("SELECT token FROM password_resets").fetch_one(&pool).await.unwrap();
+    sqlx::query("UPDATE password_resets SET expires_at = ?")
+        .bind(chrono::Utc::now() - chrono::Duration::minutes(1))
+        .execute(&pool)
+        .await
+        .unwrap();
+    let resp = test::call_service(&app, confirm(&token)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::call_service(&app, login_with("N3w-secret!")).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn tokens_do_nothing_once_their_user_is_soft_deleted() {
+    let (state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("gone@example.com")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = test::read_body_json(resp).await;
+    let unverified_id = created["id"].as_str().unwrap().to_string();
+    let verify_token: String = sqlx::query_scalar("SELECT token FROM email_verifications").fetch_one(&pool).await.unwrap();
+
+    let verified_id = sign_up(&app, &pool, "left@example.com").await;
+    let req = test::TestRequest::post()
+        .uri("/password-reset/request")
+        .set_json(json!({ "email": "left@example.com" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let reset_token: String = sqlx::query_scalar("SELECT token FROM password_resets").fetch_one(&pool).await.unwrap();
+    let old_hash: String = sqlx::query_scalar("SELECT password FROM users WHERE id = ?")
+        .bind(&verified_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    for id in [&unverified_id, &verified_id] {
+        assert!(state.users.soft_delete(id, chrono::Utc::now()).await.unwrap());
+    }
+
+    let req = test::TestRequest::get().uri(&format!("/verify?token={}", verify_token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let verified: bool = sqlx::query_scalar("SELECT verified FROM users WHERE id = ?")
+        .bind(&unverified_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(!verified);
+
+    let req = test::TestRequest::post()
+        .uri("/password-reset/confirm")
+        .set_json(json!({ "token": reset_token, "new_password": "N3w-secret!" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let hash: String = sqlx::query_scalar("SELECT password FROM users WHERE id = ?")
+        .bind(&verified_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(hash, old_hash);
+}
+
+#[actix_web::test]
+async fn mail_sending_requests_are_limited_and_replace_earlier_tokens() {
+    let (mut state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let user_id = sign_up(&app, &pool, "flood@example.com").await;
+    let auth = login(&app, "flood@example.com").await;
+
+    let request_reset = |peer: &str| {
+        test::TestRequest::post()
+            .uri("/password-reset/request")
+            .peer_addr(peer.parse().unwrap())
+            .set_json(json!({ "email": "flood@example.com" }))
+            .to_request()
+    };
+
+    // Only the newest reset token is kept
+    assert_eq!(test::call_service(&app, request_reset("203.0.113.5:4000")).await.status(), StatusCode::OK);
+    let first: String = sqlx::query_scalar("SELECT token FROM password_resets").fetch_one(&pool).await.unwrap();
+    assert_eq!(test::call_service(&app, request_reset("203.0.113.5:4000")).await.status(), StatusCode::OK);
+    let tokens: Vec<String> = sqlx::query_scalar("SELECT token FROM password_resets").fetch_all(&pool).await.unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_ne!(tokens[0], first);
+
+    state.resend_limiter = Arc::new(RateLimiter::new(1));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    for expected in [StatusCode::OK, StatusCode::TOO_MANY_REQUESTS] {
+        assert_eq!(test::call_service(&app, request_reset("203.0.113.5:4000")).await.status(), expected);
+    }
+
+    for (new_email, expected) in [("one@example.com", StatusCode::ACCEPTED), ("two@example.com", StatusCode::TOO_MANY_REQUESTS)] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/users/{}/email", user_id))
+            .peer_addr("198.51.100.7:4000".parse().unwrap())
+            .insert_header(auth.clone())
+            .set_json(json!({ "email": new_email }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), expected, "{}", new_email);
+    }
+}
+
+#[actix_web::test]
+async fn password_reset_and_email_change_tokens_are_mailed() {
+    let (mut state, pool) = test_state().await;
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let user_id = sign_up(&app, &pool, "moving@example.com").await;
+    let auth = login(&app, "moving@example.com").await;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("smtp://{}", listener.local_addr().unwrap());
+    state.mailer = Some(Arc::new(Mailer::new(&url, "accounts@example.com", "https://app.example.com").unwrap()));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/password-reset/request")
+        .set_json(json!({ "email": "moving@example.com" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let email = tokio::time::timeout(std::time::Duration::from_secs(5), receive_one_email(listener))
+        .await
+        .expect("password reset email should be sent");
+    assert!(email.contains("To: moving@example.com"), "{}", email);
+    assert!(email.contains("Subject: Reset your password"), "{}", email);
+    let token: String = sqlx::query_scalar("SELECT token FROM password_resets").fetch_one(&pool).await.unwrap();
+    assert!(email.contains(&token), "{}", email);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("smtp://{}", listener.local_addr().unwrap());
+    state.mailer = Some(Arc::new(Mailer::new(&url, "accounts@example.com", "https://app.example.com").unwrap()));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/email", user_id))
+        .insert_header(auth)
+        .set_json(json!({ "email": "moved@example.com" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::ACCEPTED);
+
+    // The confirmation link goes to the new address, not the current one
+    let email = tokio::time::timeout(std::time::Duration::from_secs(5), receive_one_email(listener))
+        .await
+        .expect("email change confirmation should be sent");
+    assert!(email.contains("To: moved@example.com"), "{}", email);
+    let token: String = sqlx::query_scalar("SELECT token FROM email_change_tokens").fetch_one(&pool).await.unwrap();
+    assert!(email.contains(&format!("https://app.example.com/verify-email-change?token={}", token)), "{}", email);
+}
+
+#[actix_web::test]
+async fn verification_links_can_be_resent() {
+    let (mut state, pool) = test_state().await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("smtp://{}", listener.local_addr().unwrap());
+    state.mailer = Some(Arc::new(Mailer::new(&url, "accounts@example.com", "https://app.example.com").unwrap()));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    // Skip the welcome email so the fake server takes the resent one
+    state.mailer = None;
+    let no_mail = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    let req = test::TestRequest::post().uri("/register").set_json(register_body("lost@example.com")).to_request();
+    assert_eq!(test::call_service(&no_mail, req).await.status(), StatusCode::CREATED);
+    let first: String = sqlx::query_scalar("SELECT token FROM email_verifications").fetch_one(&pool).await.unwrap();
+
+    // An expired link left by another account is swept up along the way
+    sign_up(&no_mail, &pool, "done@example.com").await;
+    sqlx::query("INSERT INTO email_verifications (token, user_id, expires_at) SELECT 'stale', id, ? FROM users WHERE email = 'done@example.com'")
+        .bind(chrono::Utc::now() - Duration::hours(1))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let message = json!({ "message": "If the email is registered and unverified, a new verification link has been sent" });
+    let req = test::TestRequest::post().uri("/verify/resend").set_json(json!({ "email": " LOST@example.com" })).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await, message);
+
+    let email = tokio::time::timeout(std::time::Duration::from_secs(5), receive_one_email(listener))
+        .await
+        .expect("verification email should be sent");
+    let tokens: Vec<String> = sqlx::query_scalar("SELECT token FROM email_verifications").fetch_all(&pool).await.unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_ne!(tokens[0], first);
+    assert!(email.contains(&format!("https://app.example.com/verify?token={}", tokens[0])), "{}", email);
+
+    // Only the latest link works
+    let req = test::TestRequest::get().uri(&format!("/verify?token={}", first)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::get().uri(&format!("/verify?token={}", tokens[0])).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    // Verified and unknown accounts get the same answer and no new link
+    for email in ["lost@example.com", "nobody@example.com"] {
+        let req = test::TestRequest::post().uri("/verify/resend").set_json(json!({ "email": email })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", email);
+        assert_eq!(test::read_body_json::<Value, _>(resp).await, message);
+    }
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM email_verifications").fetch_one(&pool).await.unwrap();
+    assert_eq!(count, 0);
+
+    // The endpoint has its own, tighter limit
+    state.resend_limiter = Arc::new(RateLimiter::new(1));
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    for expected in [StatusCode::OK, StatusCode::TOO_MANY_REQUESTS] {
+        let req = test::TestRequest::post()
+            .uri("/verify/resend")
+            .peer_addr("203.0.113.9:4000".parse().unwrap())
+            .set_json(json!({ "email": "nobody@example.com" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), expected);
+    }
+}
+
+#[actix_web::test]
+async fn register_and_login_share_a_per_client_limit() {
+    let (mut state, pool) = test_state().await;
+    state.auth_limiter = Arc::new(RateLimiter::new(2));
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(negotiate_error_format))
+            .configure(|cfg| configure(cfg, &state)),
+    )
+    .await;
+
+    let register = |peer: &str| {
+        test::TestRequest::post()
+            .uri("/register")
+            .peer_addr(peer.parse().unwrap())
+            .set_json(register_body("burst@example.com"))
+            .to_request()
+    };
+    let login = |peer: &str| {
+        test::TestRequest::post()
+            .uri("/login")
+            .peer_addr(peer.parse().unwrap())
+            .set_json(json!({ "email": "burst@example.com", "password": PASSWORD }))
+            .to_request()
+    };
+
+    assert_eq!(test::call_service(&app, register("203.0.113.5:4000")).await.status(), StatusCode::CREATED);
+    sqlx::query("UPDATE users SET verified = TRUE").execute(&pool).await.unwrap();
+    assert_eq!(test::call_service(&app, login("203.0.113.5:4000")).await.status(), StatusCode::OK);
+
+    // Two requests a minute refill one token every 30 seconds
+    for req in [register("203.0.113.5:4000"), login("203.0.113.5:4000")] {
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "30");
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, json!({ "error": "too many requests" }));
+    }
+
+    // The refusal is an ordinary error, so it comes as problem details on request
+    let mut req = login("203.0.113.5:4000");
+    req.headers_mut().insert(header::ACCEPT, "application/problem+json".parse().unwrap());
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/problem+json");
+    assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "30");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], 429);
+    assert_eq!(body["detail"], "too many requests");
+
+    // Other clients have their own quota
+    assert_eq!(test::call_service(&app, login("198.51.100.7:4000")).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn every_response_carries_its_request_id() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(request_logger))
+            .wrap(from_fn(assign_request_id))
+            .route("/ok", web::get().to(HttpResponse::Ok))
+            .route(
+                "/id",
+                web::get().to(|req: actix_web::HttpRequest| async move {
+                    use actix_web::HttpMessage;
+                    let id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+                    HttpResponse::Ok().body(id.unwrap_or_default())
+                }),
+            ),
+    )
+    .await;
+
+    // Without an incoming id each request gets a fresh UUID
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/ok").to_request()).await;
+        let id = resp.headers().get(REQUEST_ID_HEADER).expect("X-Request-Id should be set").to_str().unwrap();
+        ids.push(uuid::Uuid::parse_str(id).unwrap());
+    }
+    assert_ne!(ids[0], ids[1]);
+
+    // An upstream id is kept, echoed and visible to handlers
+    let req = test::TestRequest::get().uri("/id").insert_header(("X-Request-Id", "lb-7f3a.42")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "lb-7f3a.42");
+    assert_eq!(test::read_body(resp).await, "lb-7f3a.42");
+
+    // Ids that could mangle log lines are replaced
+    for bad in ["has space", "", &"x".repeat(129)] {
+        let req = test::TestRequest::get().uri("/ok").insert_header(("X-Request-Id", bad)).to_request();
+        let resp = test::call_service(&app, req).await;
+        let id = resp.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(id).is_ok(), "{:?} was kept", bad);
+    }
+}
@@ -0,0 +1,59 @@
+//! `BreachChecker` against a local stand-in for the Pwned Passwords range API
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use hello_resut_1::breach::BreachChecker;
+use sha1::{Digest, Sha1};
+use std::time::Duration;
+
+/// Serve `/range/{prefix}` with the suffix and count of each listed password under
+/// that prefix, like the real API (counts of 0 are its padding); returns the base URL
+fn fake_range_api(passwords: &'static [(&'static str, u32)], delay: Duration) -> String {
+    let server = HttpServer::new(move || {
+        App::new().route(
+            "/range/{prefix}",
+            web::get().to(move |prefix: web::Path<String>| async move {
+                tokio::time::sleep(delay).await;
+                let body: String = passwords
+                    .iter()
+                    .map(|(password, count)| (format!("{:X}", Sha1::digest(password.as_bytes())), count))
+                    .filter(|(digest, _)| digest.starts_with(prefix.as_str()))
+                    .map(|(digest, count)| format!("{}:{}\r\n", &digest[5..], count))
+                    .collect();
+                HttpResponse::Ok().body(body)
+            }),
+        )
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+
+    let url = format!("http://{}", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+    url
+}
+
+#[actix_web::test]
+async fn breached_passwords_are_matched_by_hash_suffix() {
+    let url = fake_range_api(&[("password123", 42), ("Padding-only-1", 0)], Duration::ZERO);
+    let checker = BreachChecker::new(&url, Duration::from_secs(5));
+
+    assert_eq!(checker.is_breached("password123").await, Ok(true));
+    assert_eq!(checker.is_breached("Padding-only-1").await, Ok(false));
+    assert_eq!(checker.is_breached("Never-seen-before-9").await, Ok(false));
+    assert!(checker.check("password123").await);
+}
+
+#[actix_web::test]
+async fn lookups_fail_open_when_the_api_is_down_or_slow() {
+    // Nothing listens on a port that was just released
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let checker = BreachChecker::new(&format!("http://127.0.0.1:{}", port), Duration::from_secs(5));
+    assert!(checker.is_breached("password123").await.is_err());
+    assert!(!checker.check("password123").await);
+
+    let url = fake_range_api(&[("password123", 42)], Duration::from_secs(5));
+    let checker = BreachChecker::new(&url, Duration::from_millis(100));
+    let error = checker.is_breached("password123").await.unwrap_err();
+    assert!(error.starts_with("no answer within"), "{}", error);
+    assert!(!checker.check("password123").await);
+}
@@ -0,0 +1,60 @@
+//! `client_ip` only believes `X-Forwarded-For` when the socket peer is a trusted proxy
+
+use actix_web::{test::TestRequest, web};
+use hello_resut_1::client_ip::{client_ip, ProxyConfig};
+use std::net::{IpAddr, SocketAddr};
+
+fn resolve(trusted: &str, peer: &str, forwarded_for: Option<&str>) -> IpAddr {
+    let mut req = TestRequest::default()
+        .peer_addr(SocketAddr::new(peer.parse().unwrap(), 40000))
+        .app_data(web::Data::new(ProxyConfig::parse(trusted).unwrap()));
+    if let Some(value) = forwarded_for {
+        req = req.insert_header(("X-Forwarded-For", value));
+    }
+    client_ip(&req.to_http_request()).unwrap()
+}
+
+#[test]
+fn forwarded_for_needs_a_trusted_peer() {
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+    // Nobody is trusted by default
+    assert_eq!(resolve("", "10.0.0.5", Some("203.0.113.7")), ip("10.0.0.5"));
+    // A peer outside the ranges can't spoof its address
+    assert_eq!(resolve("10.0.0.0/8", "198.51.100.1", Some("203.0.113.7")), ip("198.51.100.1"));
+    // A trusted load balancer reports the client
+    assert_eq!(resolve("10.0.0.0/8", "10.0.0.5", Some("203.0.113.7")), ip("203.0.113.7"));
+    // Trusted hops are skipped, and whatever the client prepended is ignored
+    assert_eq!(
+        resolve("10.0.0.0/8, 192.0.2.10", "10.0.0.5", Some("1.2.3.4, 203.0.113.7, 192.0.2.10")),
+        ip("203.0.113.7")
+    );
+    // Without the header, or with garbage in it, the nearest known address wins
+    assert_eq!(resolve("10.0.0.0/8", "10.0.0.5", None), ip("10.0.0.5"));
+    assert_eq!(resolve("10.0.0.0/8", "10.0.0.5", Some("not-an-ip")), ip("10.0.0.5"));
+    // IPv6 ranges and bare addresses both work
+    assert_eq!(resolve("::1", "::1", Some("2001:db8::1")), ip("2001:db8::1"));
+}
+
+#[test]
+fn unix_socket_peers_are_trusted_proxies() {
+    // A request over `BIND_UDS` has no peer address; the proxy on the other end reports the client
+    let req = |forwarded_for: Option<&str>| {
+        let mut req = TestRequest::default().app_data(web::Data::new(ProxyConfig::parse("10.0.0.0/8").unwrap()));
+        if let Some(value) = forwarded_for {
+            req = req.insert_header(("X-Forwarded-For", value));
+        }
+        client_ip(&req.to_http_request())
+    };
+
+    assert_eq!(req(Some("203.0.113.7, 10.0.0.5")), Some("203.0.113.7".parse().unwrap()));
+    assert_eq!(req(None), None);
+    assert_eq!(req(Some("not-an-ip")), None);
+}
+
+#[test]
+fn rejects_entries_that_are_not_ranges() {
+    assert!(ProxyConfig::parse("10.0.0.0/33").is_err());
+    assert!(ProxyConfig::parse("lb.internal").is_err());
+    assert_eq!(ProxyConfig::parse(" 10.0.0.0/8 , ,172.16.0.0/12").unwrap().trusted_proxies.len(), 2);
+}
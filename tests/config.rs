@@ -0,0 +1,133 @@
+//! `AppConfig` reads the process environment, so everything runs in one test
+//! to keep the variables from racing between threads
+
+use hello_resut_1::config::{maintenance_mode_from_env, AppConfig};
+use std::env;
+
+fn set(name: &str, value: &str) {
+    // SAFETY: this binary runs a single test, so nothing else reads the environment concurrently
+    unsafe { env::set_var(name, value) }
+}
+
+#[test]
+fn loads_defaults_and_reports_every_problem() {
+    set("PORT", "70000");
+    set("DB_MAX_CONNECTIONS", "lots");
+    set("DB_WARMUP", "sometimes");
+    set("DB_MAX_RETRIES", "-1");
+    set("WORKERS", "0");
+    set("IMPORT_MAX_USERS", "0");
+    set("IMPORT_TIMEOUT_SECS", "0");
+    set("BIND_UDS", "/nonexistent-dir/app.sock");
+    set("RESEND_LIMIT_PER_MINUTE", "0");
+    set("REQUEST_TIMEOUT_SECS", "0");
+    set("ERROR_FORMAT", "xml");
+    set("SMTP_URL", "smtp://localhost");
+    set("ARGON2_VARIANT", "argon2x");
+    set("ARGON2_MEMORY_KIB", "1");
+    set("TRUSTED_PROXIES", "10.0.0.0/8, lb.internal");
+    set("ALLOWED_ORIGINS", "*");
+    set("CORS_ALLOW_CREDENTIALS", "true");
+    set("CORS_MAX_AGE_SECS", "a day");
+    set("ALLOWED_EMAIL_DOMAINS", ",");
+    set("DEFAULT_PAGE_SIZE", "50");
+    set("MAX_PAGE_SIZE", "10");
+    set("MAINTENANCE_MODE", "later");
+    set("HIBP_TIMEOUT_SECS", "0");
+    set("LOG_EXCLUDE_PATHS", "/health,metrics");
+    set("LOCKOUT_THRESHOLD", "0");
+
+    let error = AppConfig::from_env().err().expect("config should be rejected").to_string();
+    assert!(error.starts_with("invalid configuration:"));
+    assert!(error.contains("DATABASE_URL must be set"));
+    assert!(error.contains("JWT_SECRET must be set"));
+    assert!(error.contains(r#"PORT must be a port number between 1 and 65535, got "70000""#));
+    assert!(error.contains(r#"DB_MAX_CONNECTIONS must be a valid number, got "lots""#));
+    assert!(error.contains(r#"DB_MAX_RETRIES must be a valid number, got "-1""#));
+    assert!(error.contains("WORKERS must be at least 1"));
+    assert!(error.contains("IMPORT_MAX_USERS must be at least 1"));
+    assert!(error.contains("IMPORT_TIMEOUT_SECS must be at least 1"));
+    assert!(error.contains("BIND_UDS directory /nonexistent-dir does not exist"));
+    assert!(error.contains("RESEND_LIMIT_PER_MINUTE must be at least 1"));
+    assert!(error.contains("REQUEST_TIMEOUT_SECS must be at least 1"));
+    assert!(error.contains(r#"DB_WARMUP must be true or false, got "sometimes""#));
+    assert!(error.contains(r#"ERROR_FORMAT must be json or problem, got "xml""#));
+    assert!(error.contains("SMTP_FROM must be set when SMTP_URL is"));
+    assert!(error.contains(r#"ARGON2_VARIANT must be argon2i, argon2d or argon2id, got "argon2x""#));
+    assert!(error.contains("ARGON2_MEMORY_KIB=1"));
+    assert!(error.contains(r#"TRUSTED_PROXIES entry "lb.internal" is not an IP address or CIDR range"#));
+    assert!(error.contains("CORS_ALLOW_CREDENTIALS cannot be combined with ALLOWED_ORIGINS=*"));
+    assert!(error.contains(r#"CORS_MAX_AGE_SECS must be a valid number, got "a day""#));
+    assert!(error.contains("ALLOWED_EMAIL_DOMAINS must name at least one domain"));
+    assert!(error.contains("DEFAULT_PAGE_SIZE (50) cannot exceed MAX_PAGE_SIZE (10)"));
+    assert!(error.contains(r#"MAINTENANCE_MODE must be true or false, got "later""#));
+    assert!(maintenance_mode_from_env().is_err());
+    assert!(error.contains("HIBP_TIMEOUT_SECS must be at least 1"));
+    assert!(error.contains(r#"LOG_EXCLUDE_PATHS entry "metrics" must start with /"#));
+    assert!(error.contains("LOCKOUT_THRESHOLD must be at least 1"));
+
+    for name in [
+        "PORT",
+        "DB_MAX_CONNECTIONS",
+        "DB_WARMUP",
+        "DB_MAX_RETRIES",
+        "WORKERS",
+        "IMPORT_MAX_USERS",
+        "IMPORT_TIMEOUT_SECS",
+        "BIND_UDS",
+        "RESEND_LIMIT_PER_MINUTE",
+        "REQUEST_TIMEOUT_SECS",
+        "ERROR_FORMAT",
+        "SMTP_URL",
+        "ARGON2_VARIANT",
+        "ARGON2_MEMORY_KIB",
+        "TRUSTED_PROXIES",
+        "CORS_ALLOW_CREDENTIALS",
+        "CORS_MAX_AGE_SECS",
+        "ALLOWED_EMAIL_DOMAINS",
+        "DEFAULT_PAGE_SIZE",
+        "MAX_PAGE_SIZE",
+        "MAINTENANCE_MODE",
+        "HIBP_TIMEOUT_SECS",
+        "LOG_EXCLUDE_PATHS",
+        "LOCKOUT_THRESHOLD",
+    ] {
+        // SAFETY: see `set`
+        unsafe { env::remove_var(name) }
+    }
+    set("DATABASE_URL", "sqlite::memory:");
+    set("JWT_SECRET", "secret");
+    set("ALLOWED_ORIGINS", "https://a.example, ,https://b.example");
+
+    let config = AppConfig::from_env().expect("config should load");
+    assert_eq!(config.host, "127.0.0.1");
+    assert_eq!(config.port, 8080);
+    assert_eq!(config.workers, None);
+    assert!(config.bind_tcp);
+    assert_eq!(config.import_max_users, 1000);
+    assert_eq!(config.import_timeout, std::time::Duration::from_secs(600));
+    assert_eq!(config.page_size.default, 20);
+    assert_eq!(config.page_size.max, 100);
+    assert_eq!(config.bind_uds, None);
+    assert_eq!(config.request_timeout, std::time::Duration::from_secs(30));
+    assert_eq!(config.database.max_connections, 5);
+    assert!(!config.database.warmup);
+    assert_eq!(config.rate_limit_per_minute, 60);
+    assert_eq!(config.resend_rate_limit_per_minute, 3);
+    assert_eq!(config.lockout.max_failed_attempts, 5);
+    assert!(config.proxy.trusted_proxies.is_empty());
+    assert_eq!(config.cors.allowed_origins, ["https://a.example", "https://b.example"]);
+    assert!(!config.cors.allow_credentials);
+    assert_eq!(config.cors.max_age_secs, None);
+    assert_eq!(config.jwt.expiry_secs(), 3600);
+    assert!(config.mailer.is_none());
+    assert!(config.allowed_email_domains.is_none());
+    assert!(!config.maintenance_mode);
+    assert!(config.breach_checker.is_none());
+    assert!(config.log_exclude_paths.0.is_empty());
+    assert_eq!(config.argon2_algorithm, argon2::Algorithm::Argon2id);
+
+    // What a SIGHUP re-reads
+    set("MAINTENANCE_MODE", "yes");
+    assert!(maintenance_mode_from_env().unwrap());
+}
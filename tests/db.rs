@@ -0,0 +1,21 @@
+//! `db::connect` opens the minimum connections up front when asked to
+
+use hello_resut_1::config::DatabaseConfig;
+use hello_resut_1::db;
+
+fn config(warmup: bool) -> DatabaseConfig {
+    DatabaseConfig {
+        url: "sqlite::memory:".to_string(),
+        max_connections: 4,
+        min_connections: 3,
+        acquire_timeout: None,
+        warmup,
+    }
+}
+
+#[tokio::test]
+async fn warmup_opens_the_minimum_connections_before_returning() {
+    let pool = db::connect(&config(true)).await;
+    assert_eq!(pool.size(), 3);
+    pool.close().await;
+}
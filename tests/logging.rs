@@ -0,0 +1,102 @@
+//! What `request_logger` writes, captured through a thread-local subscriber
+
+use actix_web::middleware::from_fn;
+use actix_web::test::{call_service, init_service, TestRequest};
+use actix_web::{web, App, HttpResponse};
+use hello_resut_1::middleware::logging::{request_logger, LogExcludePaths};
+use hello_resut_1::middleware::request_id::assign_request_id;
+use hello_resut_1::telemetry::{self, LogFormat};
+use serde_json::Value;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Collects everything the subscriber writes
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[actix_web::test]
+async fn excluded_paths_are_only_logged_when_they_fail() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    // The actix test runtime is single-threaded, so this covers the whole request
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let app = init_service(
+        App::new()
+            .wrap(from_fn(request_logger))
+            .app_data(web::Data::new(LogExcludePaths::parse("/health, /metrics").unwrap()))
+            .route("/health", web::get().to(HttpResponse::Ok))
+            .route("/metrics", web::get().to(HttpResponse::ServiceUnavailable))
+            .route("/users", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+
+    for uri in ["/health", "/metrics", "/users"] {
+        call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+    }
+
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let completed: Vec<&str> = logs.lines().filter(|line| line.contains("request completed")).collect();
+    assert_eq!(completed.len(), 2, "{}", logs);
+    assert!(completed[0].contains("/metrics") && completed[0].contains("status=503"), "{}", logs);
+    assert!(completed[1].contains("/users"), "{}", logs);
+}
+
+#[actix_web::test]
+async fn json_lines_carry_the_request_span() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = telemetry::subscriber(LogFormat::Json, tracing_subscriber::EnvFilter::new("info"), move || writer.clone());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let app = init_service(
+        App::new()
+            .wrap(from_fn(request_logger))
+            .wrap(from_fn(assign_request_id))
+            .route("/users", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+    let req = TestRequest::get().uri("/users").insert_header(("X-Request-Id", "trace-me-123")).to_request();
+    call_service(&app, req).await;
+
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let line = logs.lines().find(|line| line.contains("request completed")).expect(&logs);
+    let event: Value = serde_json::from_str(line).unwrap();
+    assert_eq!(event["message"], "request completed", "{}", line);
+    assert_eq!(event["status"], 200, "{}", line);
+    assert_eq!(event["span"]["request_id"], "trace-me-123", "{}", line);
+    assert_eq!(event["span"]["path"], "/users", "{}", line);
+}
+
+#[test]
+fn log_formats_are_json_or_text() {
+    assert_eq!(LogFormat::parse("").unwrap(), LogFormat::Text);
+    assert_eq!(LogFormat::parse("text").unwrap(), LogFormat::Text);
+    assert_eq!(LogFormat::parse(" JSON ").unwrap(), LogFormat::Json);
+    assert!(LogFormat::parse("jsno").unwrap_err().contains("LOG_FORMAT"));
+}
+
+#[test]
+fn exclusions_must_be_absolute_paths() {
+    assert_eq!(LogExcludePaths::parse(" /health ,,/metrics").unwrap().0, ["/health", "/metrics"]);
+    assert_eq!(
+        LogExcludePaths::parse("/health,metrics").unwrap_err(),
+        r#"LOG_EXCLUDE_PATHS entry "metrics" must start with /"#
+    );
+}
@@ -0,0 +1,64 @@
+//! `PasswordHasher` behaviour that doesn't need the HTTP stack
+
+use argon2::{Algorithm, Params, Version};
+use hello_resut_1::password::{parse_algorithm, parse_version, PasswordHasher};
+
+fn hasher(pepper: Option<&str>) -> PasswordHasher {
+    hasher_for(Algorithm::Argon2id, pepper)
+}
+
+fn hasher_for(algorithm: Algorithm, pepper: Option<&str>) -> PasswordHasher {
+    PasswordHasher::new(algorithm, Version::V0x13, Params::new(1024, 1, 1, None).unwrap(), pepper)
+}
+
+#[test]
+fn peppered_hashes_only_verify_with_the_same_pepper() {
+    let peppered = hasher(Some("server-side-secret"));
+    let hash = peppered.hash("Sup3r-secret!").unwrap();
+
+    assert!(peppered.verify("Sup3r-secret!", &hash).unwrap());
+    assert!(!peppered.verify("wrong-password", &hash).unwrap());
+
+    // The database alone isn't enough: without the pepper, or after rotating it, nothing verifies
+    assert!(!hasher(None).verify("Sup3r-secret!", &hash).unwrap());
+    assert!(!hasher(Some("rotated-secret")).verify("Sup3r-secret!", &hash).unwrap());
+}
+
+#[test]
+fn hashes_keep_verifying_after_the_variant_changes() {
+    let hash = hasher_for(Algorithm::Argon2i, Some("pepper")).hash("Sup3r-secret!").unwrap();
+    assert!(hash.starts_with("$argon2i$v=19$"), "{}", hash);
+
+    // The stored hash names its variant, so an Argon2id hasher still checks it as Argon2i
+    let current = hasher_for(Algorithm::Argon2id, Some("pepper"));
+    assert!(current.verify("Sup3r-secret!", &hash).unwrap());
+    assert!(!current.verify("wrong-password", &hash).unwrap());
+    assert!(current.hash("Sup3r-secret!").unwrap().starts_with("$argon2id$v=19$"));
+}
+
+#[test]
+fn variant_and_version_names_are_parsed() {
+    assert_eq!(parse_algorithm("argon2id"), Some(Algorithm::Argon2id));
+    assert_eq!(parse_algorithm("Argon2D"), Some(Algorithm::Argon2d));
+    assert_eq!(parse_algorithm("argon2i"), Some(Algorithm::Argon2i));
+    assert_eq!(parse_algorithm("bcrypt"), None);
+
+    assert_eq!(parse_version("0x13"), Some(Version::V0x13));
+    assert_eq!(parse_version("16"), Some(Version::V0x10));
+    assert_eq!(parse_version("0x12"), None);
+}
+
+#[test]
+fn hashes_from_other_settings_need_rehashing() {
+    let current = hasher(None);
+    assert!(!current.needs_rehash(&current.hash("Sup3r-secret!").unwrap()));
+
+    // Different cost, variant or version all call for a fresh hash
+    let cheaper = PasswordHasher::new(Algorithm::Argon2id, Version::V0x13, Params::new(512, 1, 1, None).unwrap(), None);
+    assert!(current.needs_rehash(&cheaper.hash("Sup3r-secret!").unwrap()));
+    assert!(current.needs_rehash(&hasher_for(Algorithm::Argon2i, None).hash("Sup3r-secret!").unwrap()));
+    let legacy = PasswordHasher::new(Algorithm::Argon2id, Version::V0x10, Params::new(1024, 1, 1, None).unwrap(), None);
+    assert!(current.needs_rehash(&legacy.hash("Sup3r-secret!").unwrap()));
+
+    assert!(!current.needs_rehash("not-a-hash"));
+}
@@ -0,0 +1,34 @@
+//! `db::with_retry` only repeats errors it knows to be transient
+
+use hello_resut_1::db::{is_transient, with_retry, RetryPolicy};
+use std::cell::Cell;
+use std::time::Duration;
+
+const POLICY: RetryPolicy = RetryPolicy { max_retries: 3, base_delay: Duration::from_millis(1) };
+
+#[tokio::test]
+async fn permanent_errors_and_successes_run_once() {
+    let calls = Cell::new(0);
+    let result: Result<(), _> = with_retry(POLICY, || {
+        calls.set(calls.get() + 1);
+        async { Err(sqlx::Error::RowNotFound) }
+    })
+    .await;
+    assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+    assert_eq!(calls.get(), 1);
+
+    let calls = Cell::new(0);
+    let result = with_retry(POLICY, || {
+        calls.set(calls.get() + 1);
+        async { Ok::<_, sqlx::Error>(42) }
+    })
+    .await;
+    assert_eq!(result.unwrap(), 42);
+    assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn only_driver_errors_can_be_transient() {
+    assert!(!is_transient(&sqlx::Error::RowNotFound));
+    assert!(!is_transient(&sqlx::Error::PoolTimedOut));
+}
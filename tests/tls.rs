@@ -0,0 +1,24 @@
+//! Loading the rustls server config from PEM files
+
+use hello_resut_1::tls::load_server_config;
+use std::path::Path;
+
+const CERT: &str = "tests/fixtures/cert.pem";
+const KEY: &str = "tests/fixtures/key.pem";
+
+#[test]
+fn loads_a_matching_certificate_and_key() {
+    assert!(load_server_config(Path::new(CERT), Path::new(KEY)).is_ok());
+}
+
+#[test]
+fn missing_files_name_the_path() {
+    let error = load_server_config(Path::new("tests/fixtures/nope.pem"), Path::new(KEY)).unwrap_err();
+    assert!(error.starts_with("Failed to open tests/fixtures/nope.pem"), "{}", error);
+}
+
+#[test]
+fn swapped_files_are_rejected() {
+    let error = load_server_config(Path::new(KEY), Path::new(CERT)).unwrap_err();
+    assert_eq!(error, "No PEM certificates found in tests/fixtures/key.pem");
+}
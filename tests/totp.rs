@@ -0,0 +1,26 @@
+//! TOTP secrets are sealed per user and only open with the same key
+
+use hello_resut_1::totp::TotpCipher;
+
+const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+#[test]
+fn secrets_round_trip_only_for_their_user_and_key() {
+    let cipher = TotpCipher::from_base64(KEY).unwrap();
+    let secret = cipher.generate_secret().unwrap();
+    let stored = cipher.encrypt("user-1", &secret).unwrap();
+
+    assert_eq!(cipher.decrypt("user-1", &stored).unwrap(), secret);
+    assert!(cipher.decrypt("user-2", &stored).is_err());
+    // Each encryption uses a new nonce
+    assert_ne!(cipher.encrypt("user-1", &secret).unwrap(), stored);
+
+    let other = TotpCipher::from_base64("AQECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").unwrap();
+    assert!(other.decrypt("user-1", &stored).is_err());
+}
+
+#[test]
+fn keys_must_be_32_base64_bytes() {
+    assert!(TotpCipher::from_base64("not base64!").is_err());
+    assert!(TotpCipher::from_base64("AAECAwQF").is_err());
+}
@@ -0,0 +1,43 @@
+//! Replacing the socket file a previous run left behind
+#![cfg(unix)]
+
+use hello_resut_1::uds::remove_stale_socket;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("uds-test-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("app.sock")
+}
+
+#[test]
+fn stale_sockets_are_removed_and_live_ones_kept() {
+    let path = scratch("sockets");
+
+    // Nothing there yet
+    remove_stale_socket(&path).unwrap();
+
+    // A listener still running keeps its socket
+    let listener = UnixListener::bind(&path).unwrap();
+    assert!(remove_stale_socket(&path).unwrap_err().contains("in use"));
+
+    // Once it's gone the file is stale and can be replaced
+    drop(listener);
+    remove_stale_socket(&path).unwrap();
+    assert!(!path.exists());
+    UnixListener::bind(&path).unwrap();
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn other_files_are_never_removed() {
+    let path = scratch("files");
+    std::fs::write(&path, "not a socket").unwrap();
+
+    assert!(remove_stale_socket(&path).unwrap_err().contains("not a socket"));
+    assert!(path.exists());
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
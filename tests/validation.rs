@@ -0,0 +1,100 @@
+//! The JSON shape validation failures are reported in
+
+use hello_resut_1::error::flatten_validation_errors;
+use hello_resut_1::models::user::{normalize_email, validate_password_strength, validate_phone, RegisterRequest};
+use serde_json::{json, Value};
+use validator::Validate;
+
+#[test]
+fn flattens_every_failing_field_into_a_list_of_messages() {
+    let request = RegisterRequest {
+        name: String::new(),
+        email: "not-an-email".to_string(),
+        username: "a!".to_string(),
+        phone: None,
+        password: "short".to_string(),
+    };
+
+    let errors = request.validate().expect_err("request should be rejected");
+    let flattened = serde_json::to_value(flatten_validation_errors(&errors)).unwrap();
+
+    let expected: Value = json!({
+        "email": ["Invalid email address"],
+        "name": ["Name is required"],
+        "password": [
+            "Password must be at least 8 characters long",
+            "Password must contain at least one uppercase letter"
+        ],
+        "username": [
+            "Username must be between 3 and 30 characters long",
+            "Username may only contain letters and digits"
+        ]
+    });
+    assert_eq!(flattened, expected);
+}
+
+#[test]
+fn phone_numbers_must_be_e164() {
+    for valid in ["+14155550123", "+4930123456", "+1234567", "+123456789012345"] {
+        assert!(validate_phone(valid).is_ok(), "{}", valid);
+    }
+
+    for invalid in [
+        "14155550123",       // No leading +
+        "+1 415 555 0123",   // Separators
+        "+1-415-555-0123",
+        "+123456",           // Too short
+        "+1234567890123456", // Too long
+        "+1415555O123",      // Letter O
+        "++14155550123",
+        "+",
+        "",
+    ] {
+        let error = validate_phone(invalid).expect_err(invalid);
+        assert_eq!(error.code, "phone_e164");
+    }
+}
+
+#[test]
+fn passwords_need_length_and_every_character_class() {
+    let cases = [
+        ("Ab1!", Some("length")),                  // Too short
+        ("Abcdefg!", Some("password_digit")),
+        ("abcdef1!", Some("password_uppercase")),
+        ("ABCDEF1!", Some("password_lowercase")),
+        ("Abcdefg1", Some("password_symbol")),
+        ("Sup3r-secret!", None),
+    ];
+
+    for (password, expected) in cases {
+        let request = RegisterRequest {
+            name: "Kim".to_string(),
+            email: "kim@example.com".to_string(),
+            username: "kim".to_string(),
+            phone: None,
+            password: password.to_string(),
+        };
+
+        let codes: Vec<String> = match request.validate() {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.field_errors()["password"].iter().map(|error| error.code.to_string()).collect(),
+        };
+        assert_eq!(codes, expected.into_iter().collect::<Vec<_>>(), "{}", password);
+    }
+
+    // Spaces don't count as symbols
+    assert!(validate_password_strength("Sup3r-secret!").is_ok());
+    assert_eq!(validate_password_strength("Sup3r secret").unwrap_err().code, "password_symbol");
+}
+
+#[test]
+fn emails_are_trimmed_and_lowercased() {
+    for (input, expected) in [
+        ("alice@example.com", "alice@example.com"),
+        ("  Alice@Example.COM\t", "alice@example.com"),
+        ("\nBOB@EXAMPLE.ORG ", "bob@example.org"),
+        ("Ünïcode@Example.com", "ünïcode@example.com"),
+    ] {
+        assert_eq!(normalize_email(input), expected, "{:?}", input);
+    }
+}